@@ -0,0 +1,29 @@
+#!/bin/sh
+# A scripted UCI engine for trying out the engine opponent:
+#   chess-ai --black engine --engine assets/engines/mock-uci.sh
+# It answers each go with the next move of MOCK_MOVES, then with 0000.
+
+set -- ${MOCK_MOVES:-e7e5 b8c6 g8f6 f8c5}
+
+while read -r command rest; do
+    case "$command" in
+        uci)
+            echo "id name mock"
+            echo "uciok"
+            ;;
+        isready)
+            echo "readyok"
+            ;;
+        go)
+            echo "info string position $position"
+            echo "bestmove ${1:-0000}"
+            [ $# -gt 0 ] && shift
+            ;;
+        position)
+            position="$rest"
+            ;;
+        quit)
+            exit 0
+            ;;
+    esac
+done
@@ -0,0 +1,729 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    board::Board,
+    engine::search::{Limits, Line, Options, Search, MAX_PLY},
+    json::{self, Value},
+    pgn,
+    piece::Piece,
+};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+const DEFAULT_MOVETIME: u64 = 1000;
+const DEFAULT_MAX_MOVETIME: u64 = 10000;
+const DEFAULT_MAX_CONNECTIONS: usize = 16;
+const DEFAULT_MAX_SEARCHES: usize = 2;
+const DEFAULT_HASH_MB: usize = 16;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+const MAX_BODY: usize = 64 * 1024;
+const MAX_MOVES: usize = 1024;
+const MAX_MULTI_PV: usize = 8;
+
+struct Config {
+    max_movetime: Duration,
+    max_connections: usize,
+    max_searches: usize,
+    hash_mb: usize,
+    connections: AtomicUsize,
+    searches: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+fn http_error(status: u16, message: impl Into<String>) -> anyhow::Error {
+    HttpError {
+        status,
+        message: message.into(),
+    }
+    .into()
+}
+
+struct Request {
+    method: String,
+    path: String,
+    params: HashMap<String, Value>,
+}
+
+impl Request {
+    fn string(&self, name: &str) -> Result<Option<&str>> {
+        match self.params.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => bail!("{} must be a string", name),
+        }
+    }
+
+    fn number(&self, name: &str) -> Result<Option<u64>> {
+        match self.params.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Number(value)) if *value >= 0. && value.fract() == 0. => {
+                Ok(Some(*value as u64))
+            }
+            Some(Value::String(value)) => value
+                .parse()
+                .map(Some)
+                .with_context(|| format!("{} must be a non-negative integer", name)),
+            Some(_) => bail!("{} must be a non-negative integer", name),
+        }
+    }
+
+    fn moves(&self, name: &str) -> Result<Vec<String>> {
+        let moves: Vec<String> = match self.params.get(name) {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::String(moves)) => moves
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|m| !m.is_empty())
+                .map(String::from)
+                .collect(),
+            Some(Value::Array(moves)) => moves
+                .iter()
+                .map(|m| match m {
+                    Value::String(m) => Ok(m.clone()),
+                    _ => Err(anyhow!("{} must only contain strings", name)),
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => bail!("{} must be a list of moves", name),
+        };
+        if moves.len() > MAX_MOVES {
+            bail!("{} may contain at most {} moves", name, MAX_MOVES);
+        }
+        Ok(moves)
+    }
+
+    fn position(&self) -> Result<Board> {
+        let mut board = match self.string("fen")? {
+            Some(fen) => Board::from_fen(fen)?,
+            None => Board::default(),
+        };
+        validate(&mut board)?;
+        for (i, name) in self.moves("moves")?.iter().enumerate() {
+            let m = pgn::parse_move(&mut board, name)
+                .with_context(|| format!("move {} ({}) cannot be played", i + 1, name))?;
+            board.make_move(m);
+        }
+        board.update_status();
+        Ok(board)
+    }
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut address = DEFAULT_ADDRESS.to_string();
+    let mut config = Config {
+        max_movetime: Duration::from_millis(DEFAULT_MAX_MOVETIME),
+        max_connections: DEFAULT_MAX_CONNECTIONS,
+        max_searches: DEFAULT_MAX_SEARCHES,
+        hash_mb: DEFAULT_HASH_MB,
+        connections: AtomicUsize::new(0),
+        searches: AtomicUsize::new(0),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => match args.next() {
+                Some(value) => address = value.clone(),
+                None => bail!("--listen expects an address such as 127.0.0.1:8080"),
+            },
+            "--max-movetime" => match args.next().map(|movetime| movetime.parse()) {
+                Some(Ok(movetime)) => config.max_movetime = Duration::from_millis(movetime),
+                _ => bail!("--max-movetime expects a number of milliseconds"),
+            },
+            "--max-connections" => match args.next().map(|connections| connections.parse()) {
+                Some(Ok(connections)) if connections > 0 => config.max_connections = connections,
+                _ => bail!("--max-connections expects a positive number"),
+            },
+            "--max-searches" => match args.next().map(|searches| searches.parse()) {
+                Some(Ok(searches)) if searches > 0 => config.max_searches = searches,
+                _ => bail!("--max-searches expects a positive number"),
+            },
+            "--hash" => match args.next().map(|hash| hash.parse()) {
+                Some(Ok(hash)) => config.hash_mb = hash,
+                _ => bail!("--hash expects a size in megabytes"),
+            },
+            arg => bail!("unexpected argument: {}", arg),
+        }
+    }
+
+    let listener =
+        TcpListener::bind(&address).with_context(|| format!("could not listen on {}", address))?;
+    println!("serving on http://{}/", listener.local_addr()?);
+
+    let config = Arc::new(config);
+    for stream in listener.incoming().map_while(|stream| stream.ok()) {
+        let config = config.clone();
+        if !reserve(&config.connections, config.max_connections) {
+            let mut stream = stream;
+            let _ = respond(
+                &mut stream,
+                503,
+                &error_json("too many connections, try again later"),
+            );
+            continue;
+        }
+
+        thread::spawn(move || {
+            let _release = Release(&config.connections);
+            let _ = handle(stream, &config);
+        });
+    }
+    Ok(())
+}
+
+fn reserve(counter: &AtomicUsize, max: usize) -> bool {
+    if counter.fetch_add(1, Ordering::SeqCst) >= max {
+        counter.fetch_sub(1, Ordering::SeqCst);
+        return false;
+    }
+    true
+}
+
+// gives a reserved slot back even when the handler panics
+struct Release<'a>(&'a AtomicUsize);
+
+impl Drop for Release<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn handle(mut stream: TcpStream, config: &Config) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+
+    let (status, body) = answer(&stream, config);
+    respond(&mut stream, status, &body)
+}
+
+fn answer(stream: impl Read, config: &Config) -> (u16, String) {
+    match read_request(stream).and_then(|request| route(&request, config)) {
+        Ok(body) => (200, body),
+        Err(err) => {
+            let status = err
+                .downcast_ref::<HttpError>()
+                .map_or(400, |err| err.status);
+            (status, error_json(&format!("{:#}", err)))
+        }
+    }
+}
+
+fn read_request(stream: impl Read) -> Result<Request> {
+    let mut reader = BufReader::new(stream.take((MAX_LINE * (MAX_HEADERS + 1) + MAX_BODY) as u64));
+    let request_line = read_line(&mut reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => bail!("malformed request line"),
+    };
+
+    let mut content_length = 0;
+    let mut content_type = String::new();
+    for i in 0.. {
+        let header = read_line(&mut reader)?;
+        if header.is_empty() {
+            break;
+        }
+        if i >= MAX_HEADERS {
+            return Err(http_error(431, "too many headers"));
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => {
+                    content_length = value.trim().parse().context("invalid content-length")?
+                }
+                "content-type" => content_type = value.trim().to_ascii_lowercase(),
+                _ => (),
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(http_error(
+            413,
+            format!("body larger than {} bytes", MAX_BODY),
+        ));
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let mut params: HashMap<String, Value> = parse_form(query)?;
+
+    if content_length > 0 {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).context("incomplete body")?;
+        let body = String::from_utf8(body).context("body is not valid UTF-8")?;
+        if content_type.starts_with("application/x-www-form-urlencoded") {
+            params.extend(parse_form(&body)?);
+        } else {
+            match json::parse(&body).context("invalid JSON body")? {
+                Value::Object(fields) => params.extend(fields),
+                _ => bail!("the JSON body must be an object"),
+            }
+        }
+    }
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        params,
+    })
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_LINE as u64)
+        .read_until(b'\n', &mut line)
+        .context("could not read the request")?;
+    if !line.ends_with(b"\n") {
+        return Err(http_error(431, "request line or header too long"));
+    }
+    Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+fn route(request: &Request, config: &Config) -> Result<String> {
+    if request.method != "GET" && request.method != "POST" {
+        return Err(http_error(
+            405,
+            format!("method {} not allowed", request.method),
+        ));
+    }
+
+    match request.path.as_str() {
+        "/health" => Ok(format!(
+            "{{\"status\":\"ok\",\"searches\":{},\"max_searches\":{},\"max_movetime\":{}}}",
+            config.searches.load(Ordering::SeqCst),
+            config.max_searches,
+            config.max_movetime.as_millis()
+        )),
+        "/status" => {
+            let mut board = request.position()?;
+            Ok(format!("{{{}}}", position_fields(&mut board)))
+        }
+        "/moves" => {
+            let mut board = request.position()?;
+            let moves = board.get_all_moves();
+            Ok(format!(
+                "{{{},\"moves\":{}}}",
+                position_fields(&mut board),
+                json::array(moves, |m| format!(
+                    "{{\"uci\":\"{}\",\"san\":{}}}",
+                    m,
+                    json::string(&pgn::to_san(&mut board.clone(), m))
+                ))
+            ))
+        }
+        "/validate" => {
+            let mut board = request.position()?;
+            let name = request
+                .string("move")?
+                .ok_or_else(|| anyhow!("missing move"))?;
+            match pgn::parse_move(&mut board, name) {
+                Ok(m) => {
+                    let san = pgn::to_san(&mut board, m);
+                    board.make_move(m);
+                    board.update_status();
+                    Ok(format!(
+                        "{{\"legal\":true,\"uci\":\"{}\",\"san\":{},\"position\":{{{}}}}}",
+                        m,
+                        json::string(&san),
+                        position_fields(&mut board)
+                    ))
+                }
+                Err(err) => Ok(format!(
+                    "{{\"legal\":false,\"reason\":{}}}",
+                    json::string(&format!("{:#}", err))
+                )),
+            }
+        }
+        "/san" => {
+            let mut board = request.position()?;
+            let mut uci = Vec::new();
+            let mut san = Vec::new();
+            for (i, name) in request.moves("line")?.iter().enumerate() {
+                let m = pgn::parse_move(&mut board, name)
+                    .with_context(|| format!("line move {} ({}) cannot be played", i + 1, name))?;
+                san.push(pgn::to_san(&mut board, m));
+                uci.push(m);
+                board.make_move(m);
+            }
+            Ok(format!(
+                "{{\"uci\":{},\"san\":{}}}",
+                json::array(uci, |m| format!("\"{}\"", m)),
+                json::array(&san, |san| json::string(san))
+            ))
+        }
+        "/search" => search(request, config),
+        path => Err(http_error(404, format!("no endpoint {}", path))),
+    }
+}
+
+fn search(request: &Request, config: &Config) -> Result<String> {
+    let mut board = request.position()?;
+    let requested = request.number("movetime")?.map(Duration::from_millis);
+    let limits = Limits {
+        depth: request
+            .number("depth")?
+            .map(|depth| depth.clamp(1, MAX_PLY as u64 - 1) as i32),
+        nodes: request.number("nodes")?,
+        movetime: Some(
+            requested
+                .unwrap_or(Duration::from_millis(DEFAULT_MOVETIME))
+                .min(config.max_movetime),
+        ),
+    };
+    let multi_pv = request
+        .number("multipv")?
+        .unwrap_or(1)
+        .clamp(1, MAX_MULTI_PV as u64) as usize;
+
+    if board.get_all_moves().is_empty() {
+        return Ok(format!(
+            "{{\"bestmove\":null,\"lines\":[],{}}}",
+            position_fields(&mut board)
+        ));
+    }
+
+    if !reserve(&config.searches, config.max_searches) {
+        return Err(http_error(
+            503,
+            "too many searches in progress, try again later",
+        ));
+    }
+
+    let release = Release(&config.searches);
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let reported = lines.clone();
+    let mut search = Search::new(Options::default());
+    search.set_hash(config.hash_mb);
+    search.set_multi_pv(multi_pv);
+    search.set_reporter(Arc::new(move |new_lines: &[Line]| {
+        *reported.lock().unwrap() = new_lines.to_vec();
+    }));
+
+    let start = Instant::now();
+    let best_move = search.think_until(&board, limits, &AtomicBool::new(false));
+    drop(release);
+    let elapsed = start.elapsed();
+
+    let white_to_move = board.get_current_turn().is_white();
+    let lines = lines.lock().unwrap().clone();
+    let best_move = best_move.map_or("null".to_string(), |m| format!("\"{}\"", m));
+    Ok(format!(
+        "{{\"bestmove\":{},\"time_ms\":{},\"lines\":{},{}}}",
+        best_move,
+        elapsed.as_millis(),
+        json::array(&lines, |line| line_json(&board, line, white_to_move)),
+        position_fields(&mut board)
+    ))
+}
+
+fn line_json(board: &Board, line: &Line, white_to_move: bool) -> String {
+    let sign = if white_to_move { 1 } else { -1 };
+    let mate = line
+        .mate_in()
+        .map_or("null".to_string(), |moves| (moves * sign).to_string());
+
+    let mut position = board.clone();
+    let san: Vec<String> = line
+        .pv
+        .iter()
+        .map(|&m| {
+            let san = pgn::to_san(&mut position, m);
+            position.make_move(m);
+            san
+        })
+        .collect();
+
+    format!(
+        "{{\"multipv\":{},\"depth\":{},\"score\":{},\"mate\":{},\"nodes\":{},\"pv\":{},\"san\":{}}}",
+        line.multipv,
+        line.depth,
+        line.score * sign,
+        mate,
+        line.nodes,
+        json::array(&line.pv, |m| format!("\"{}\"", m)),
+        json::array(&san, |san| json::string(san))
+    )
+}
+
+fn position_fields(board: &mut Board) -> String {
+    let turn = if board.get_current_turn().is_white() {
+        "white"
+    } else {
+        "black"
+    };
+    format!(
+        "\"fen\":{},\"turn\":\"{}\",\"status\":\"{}\",\"check\":{}",
+        json::string(&board.to_fen()),
+        turn,
+        board.get_status(),
+        board.is_in_check()
+    )
+}
+
+fn validate(board: &mut Board) -> Result<()> {
+    let waiting = board.get_current_turn().ennemy();
+    if board.is_check(waiting) {
+        bail!(
+            "invalid fen: {} is in check but it is not its turn",
+            color_name(waiting)
+        );
+    }
+    Ok(())
+}
+
+fn color_name(color: Piece) -> &'static str {
+    if color.is_white() {
+        "white"
+    } else {
+        "black"
+    }
+}
+
+fn parse_form(form: &str) -> Result<HashMap<String, Value>> {
+    form.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key)?, Value::String(percent_decode(value)?)))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> Result<String> {
+    let mut bytes = Vec::new();
+    let mut input = s.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = input.by_ref().take(2).collect();
+                let hex = std::str::from_utf8(&hex).unwrap_or_default();
+                bytes.push(
+                    u8::from_str_radix(hex, 16)
+                        .map_err(|_| anyhow!("invalid percent escape in {}", s))?,
+                );
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).context("query is not valid UTF-8")
+}
+
+fn error_json(message: &str) -> String {
+    format!("{{\"error\":{}}}", json::string(message))
+}
+
+fn respond(stream: &mut TcpStream, status: u16, body: &str) -> Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            max_movetime: Duration::from_millis(100),
+            max_connections: 1,
+            max_searches: 1,
+            hash_mb: 1,
+            connections: AtomicUsize::new(0),
+            searches: AtomicUsize::new(0),
+        }
+    }
+
+    fn get(target: &str) -> (u16, Value) {
+        call(&format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            target
+        ))
+    }
+
+    fn post(path: &str, body: &str) -> (u16, Value) {
+        call(&format!(
+            "POST {} HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        ))
+    }
+
+    fn call(request: &str) -> (u16, Value) {
+        let (status, body) = answer(request.as_bytes(), &config());
+        (status, json::parse(&body).unwrap())
+    }
+
+    fn field<'a>(value: &'a Value, name: &str) -> &'a Value {
+        match value {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value)
+                .unwrap_or_else(|| panic!("missing {} in {:?}", name, value)),
+            _ => panic!("{:?} is not an object", value),
+        }
+    }
+
+    fn text(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    #[test]
+    fn lists_moves() {
+        let (status, body) = get("/moves");
+        assert_eq!(status, 200);
+        match field(&body, "moves") {
+            Value::Array(moves) => {
+                assert_eq!(moves.len(), 20);
+                assert!(moves
+                    .iter()
+                    .any(|m| field(m, "uci") == &text("g1f3") && field(m, "san") == &text("Nf3")));
+            }
+            moves => panic!("{:?}", moves),
+        }
+
+        let (status, body) = post(
+            "/moves",
+            r#"{"moves":["e4","e5","Qh5","Nc6","Bc4","Nf6","Qxf7"]}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(field(&body, "moves"), &Value::Array(Vec::new()));
+        assert_eq!(field(&body, "status"), &text("checkmate"));
+    }
+
+    #[test]
+    fn validates_moves() {
+        let (status, body) = get("/validate?move=e4");
+        assert_eq!(status, 200);
+        assert_eq!(field(&body, "legal"), &Value::Bool(true));
+        assert_eq!(field(&body, "uci"), &text("e2e4"));
+        assert_eq!(field(&body, "san"), &text("e4"));
+        assert_eq!(field(field(&body, "position"), "turn"), &text("black"));
+
+        let (status, body) = get("/validate?move=e2e5");
+        assert_eq!(status, 200);
+        assert_eq!(field(&body, "legal"), &Value::Bool(false));
+        assert!(matches!(field(&body, "reason"), Value::String(_)));
+
+        assert_eq!(get("/validate").0, 400);
+    }
+
+    #[test]
+    fn converts_lines_to_san() {
+        let (status, body) = post("/san", r#"{"moves":"e2e4","line":["e7e5","g1f3","b8c6"]}"#);
+        assert_eq!(status, 200);
+        assert_eq!(
+            field(&body, "san"),
+            &Value::Array(vec![text("e5"), text("Nf3"), text("Nc6")])
+        );
+        assert_eq!(post("/san", r#"{"line":["e2e5"]}"#).0, 400);
+    }
+
+    #[test]
+    fn reports_game_over() {
+        let (status, body) =
+            get("/status?fen=rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR+w+KQkq+-+1+3");
+        assert_eq!(status, 200);
+        assert_eq!(field(&body, "status"), &text("checkmate"));
+        assert_eq!(field(&body, "check"), &Value::Bool(true));
+
+        let (status, body) = get("/status?fen=7k/5Q2/6K1/8/8/8/8/8+b+-+-+0+1");
+        assert_eq!(status, 200);
+        assert_eq!(field(&body, "status"), &text("stalemate"));
+        assert_eq!(field(&body, "check"), &Value::Bool(false));
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        assert_eq!(get("/nowhere").0, 404);
+        assert_eq!(call("DELETE /status HTTP/1.1\r\n\r\n").0, 405);
+        assert_eq!(
+            call(&format!(
+                "POST /moves HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+                MAX_BODY + 1
+            ))
+            .0,
+            413
+        );
+        let headers = "X-Header: 1\r\n".repeat(MAX_HEADERS + 1);
+        assert_eq!(
+            call(&format!("GET /status HTTP/1.1\r\n{}\r\n", headers)).0,
+            431
+        );
+        assert_eq!(
+            call(&format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE))).0,
+            431
+        );
+
+        let (status, body) = get("/status?fen=not+a+fen");
+        assert_eq!(status, 400);
+        assert!(matches!(field(&body, "error"), Value::String(_)));
+        assert_eq!(get("/moves?fen=P3k3/8/8/8/8/8/8/4K3+w+-+-+0+1").0, 400);
+        assert_eq!(post("/moves", "[1]").0, 400);
+    }
+
+    #[test]
+    fn panicking_handlers_release_their_slot() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..2 {
+            assert!(reserve(&counter, 2));
+            let held = counter.clone();
+            let handler = thread::spawn(move || {
+                let _release = Release(&held);
+                panic!("handler failed");
+            });
+            assert!(handler.join().is_err());
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+
+        assert!(reserve(&counter, 2));
+        assert!(reserve(&counter, 2));
+        assert!(!reserve(&counter, 2));
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+    }
+}
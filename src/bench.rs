@@ -0,0 +1,132 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    board::Board,
+    engine::{
+        eval::{Evaluator, Handcrafted},
+        nnue::{Network, Nnue},
+        search::{Limits, Line, Options, Search},
+        syzygy::Syzygy,
+        tablebase::Tablebase,
+    },
+};
+
+const DEFAULT_DEPTH: i32 = 6;
+
+const POSITIONS: [&str; 12] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "r1bq1rk1/ppp1nppp/4n3/3p3Q/3P4/1BP1B3/PP1N2PP/R4RK1 w - - 1 16",
+    "4r1k1/r1q2ppp/ppp2n2/4P3/5Rb1/1N1BQ3/PPP3PP/R5K1 w - - 1 17",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/3N4 b - - 0 1",
+    "8/8/8/8/5kp1/P7/8/1K1N4 w - - 0 1",
+];
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut limits = Limits::depth(DEFAULT_DEPTH);
+    let mut threads = 1;
+    let mut options = Options::default();
+    let mut evaluator: Arc<dyn Evaluator> = Arc::new(Handcrafted);
+    let mut tablebase = None;
+    let mut syzygy = None;
+    let mut multi_pv = 1;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--depth" => match args.next().map(|depth| depth.parse()) {
+                Some(Ok(depth)) => limits = Limits::depth(depth),
+                _ => bail!("--depth expects a number"),
+            },
+            "--movetime" => match args.next().map(|movetime| movetime.parse()) {
+                Some(Ok(movetime)) => limits = Limits::movetime(Duration::from_millis(movetime)),
+                _ => bail!("--movetime expects a number of milliseconds"),
+            },
+            "--threads" => match args.next().map(|threads| threads.parse()) {
+                Some(Ok(value)) => threads = value,
+                _ => bail!("--threads expects a number"),
+            },
+            "--nnue" => match args.next() {
+                Some(path) => evaluator = Arc::new(Nnue::new(Network::load(path)?)),
+                None => bail!("--nnue expects a network file"),
+            },
+            "--tablebases" => match args.next() {
+                Some(dir) => tablebase = Some(Arc::new(Tablebase::open(dir)?)),
+                None => bail!("--tablebases expects a directory"),
+            },
+            "--syzygy" => match args.next() {
+                Some(dir) => syzygy = Some(Arc::new(Syzygy::open(dir)?)),
+                None => bail!("--syzygy expects a directory"),
+            },
+            "--multipv" => match args.next().map(|multi_pv| multi_pv.parse()) {
+                Some(Ok(value)) => multi_pv = value,
+                _ => bail!("--multipv expects a number of lines"),
+            },
+            "--no-null-move" => options.null_move = false,
+            "--no-lmr" => options.late_move_reductions = false,
+            "--no-futility" => options.futility_pruning = false,
+            "--no-reverse-futility" => options.reverse_futility_pruning = false,
+            "--no-check-extensions" => options.check_extensions = false,
+            arg => bail!("unexpected argument: {}", arg),
+        }
+    }
+
+    println!("{:?}, {} thread(s)", options, threads);
+
+    let start = Instant::now();
+    let mut total_nodes = 0;
+    for (i, fen) in POSITIONS.iter().enumerate() {
+        let board = Board::from_fen(fen)?;
+        let mut search = Search::new(options);
+        search.set_threads(threads);
+        search.set_evaluator(evaluator.clone());
+        if let Some(tablebase) = &tablebase {
+            search.set_tablebase(tablebase.clone());
+        }
+        if let Some(syzygy) = &syzygy {
+            search.set_syzygy(syzygy.clone());
+        }
+        if multi_pv > 1 {
+            search.set_multi_pv(multi_pv);
+            search.set_reporter(Arc::new(|lines: &[Line]| {
+                for line in lines {
+                    println!("info {}", line);
+                }
+            }));
+        }
+
+        let position_start = Instant::now();
+        let best_move = search.think(&board, limits);
+        total_nodes += search.nodes();
+
+        println!(
+            "position {:>2}: bestmove {} depth {:>2} nodes {:>9} time {:>6}ms",
+            i + 1,
+            best_move.map(|m| m.to_string()).unwrap_or("none".into()),
+            search.depth(),
+            search.nodes(),
+            position_start.elapsed().as_millis()
+        );
+    }
+
+    let elapsed = start.elapsed();
+    println!(
+        "total: nodes {} time {}ms nps {}",
+        total_nodes,
+        elapsed.as_millis(),
+        (total_nodes as f64 / elapsed.as_secs_f64()) as u64
+    );
+
+    Ok(())
+}
@@ -1,15 +1,35 @@
 use std::{
     collections::{HashMap, HashSet},
+    fmt,
     fs::File,
     io::BufReader,
+    str::FromStr,
+    sync::Arc,
     thread,
     time::Duration,
 };
 
-use rand::{rngs::ThreadRng, seq::SliceRandom, thread_rng};
+use anyhow::{anyhow, bail, Result};
 use rodio::{Decoder, OutputStream, Source};
 
-use crate::piece::Piece;
+use crate::{
+    engine::{
+        book::Book,
+        computer::Computer,
+        eval::Evaluator,
+        external::External,
+        nnue::Accumulator,
+        personality::{self, Personality},
+        search::{Limits, Line, Search},
+        strength::Strength,
+        syzygy::Syzygy,
+        tablebase::Tablebase,
+        zobrist,
+    },
+    piece::Piece,
+};
+
+const COMPUTER_MOVETIME: Duration = Duration::from_secs(1);
 
 const FRONT_ROW: [Piece; 8] = [Piece::Pawn; 8];
 const BACK_ROW: [Piece; 8] = [
@@ -27,6 +47,47 @@ const BACK_ROW: [Piece; 8] = [
 pub enum Opponent {
     Player,
     Computer,
+    MonteCarlo,
+    Engine,
+    Remote,
+    Personality(&'static Personality),
+}
+
+impl FromStr for Opponent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "player" => Ok(Self::Player),
+            "computer" => Ok(Self::Computer),
+            "mcts" => Ok(Self::MonteCarlo),
+            "engine" => Ok(Self::Engine),
+            _ => match personality::find(s) {
+                Some(personality) => Ok(Self::Personality(personality)),
+                None => bail!(
+                    "unknown opponent: {} (expected player, computer, mcts, engine{})",
+                    s,
+                    personality::personalities()
+                        .iter()
+                        .map(|personality| format!(", {}", personality.name()))
+                        .collect::<String>()
+                ),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Opponent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Player => write!(f, "player"),
+            Self::Computer => write!(f, "computer"),
+            Self::MonteCarlo => write!(f, "mcts"),
+            Self::Engine => write!(f, "engine"),
+            Self::Remote => write!(f, "remote"),
+            Self::Personality(personality) => write!(f, "{}", personality.name()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -35,6 +96,84 @@ pub enum Status {
     Stalemate,
     Checkmate,
     Dead,
+    Ended,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Playing => write!(f, "playing"),
+            Self::Stalemate => write!(f, "stalemate"),
+            Self::Checkmate => write!(f, "checkmate"),
+            Self::Dead => write!(f, "insufficient material"),
+            Self::Ended => write!(f, "ended"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: usize,
+    pub to: usize,
+    pub promotion: Piece,
+}
+
+impl Move {
+    pub fn new(from: usize, to: usize) -> Self {
+        Self {
+            from,
+            to,
+            promotion: Piece::None,
+        }
+    }
+
+    pub fn with_promotion(self, promotion: Piece) -> Self {
+        Self { promotion, ..self }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", square_name(self.from), square_name(self.to))?;
+
+        match self.promotion.split().0 {
+            Piece::Queen => write!(f, "q"),
+            Piece::Rook => write!(f, "r"),
+            Piece::Bishop => write!(f, "b"),
+            Piece::LeftKnight | Piece::RightKnight => write!(f, "n"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Move {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let square = |name: Option<&str>| {
+            name.and_then(square_from_name)
+                .ok_or_else(|| anyhow!("invalid move: {}", s))
+        };
+        let from = square(s.get(0..2))?;
+        let to = square(s.get(2..4))?;
+
+        let promotion = match s.get(4..) {
+            Some("") => Piece::None,
+            Some("q") => Piece::Queen,
+            Some("r") => Piece::Rook,
+            Some("b") => Piece::Bishop,
+            Some("n") => Piece::LeftKnight,
+            _ => bail!("invalid move: {}", s),
+        };
+
+        Ok(Self::new(from, to).with_promotion(promotion))
+    }
+}
+
+// moves played since the board was set up, newest first
+struct Played {
+    m: Move,
+    before: Option<Arc<Played>>,
 }
 
 #[derive(Clone)]
@@ -48,14 +187,244 @@ pub struct Board {
     current_turn: Piece,
     white_opponent: Opponent,
     black_opponent: Opponent,
-    rng: ThreadRng,
     flying_piece: Option<(usize, [f64; 2], usize)>,
     square_in_promotion: Option<usize>,
     status: Status,
     pieces_locations: HashMap<Piece, Vec<usize>>,
+    computer: Computer,
+    computer_promotion: Piece,
+    previous: Option<Arc<Board>>,
+    accumulator: Option<Accumulator>,
+    ply: usize,
+    halfmove: usize,
+    start_fen: Option<Arc<str>>,
+    played: Option<Arc<Played>>,
 }
 
 impl Board {
+    pub fn with_opponents(white_opponent: Opponent, black_opponent: Opponent) -> Self {
+        Self {
+            white_opponent,
+            black_opponent,
+            ..Self::default()
+        }
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Self> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 {
+            bail!("invalid fen: {}", fen);
+        }
+
+        let mut pieces = [Piece::None; 64];
+        let mut square_index = 0;
+        for c in fields[0].chars() {
+            match c {
+                '/' => continue,
+                '1'..='8' => square_index += c as usize - '0' as usize,
+                _ => {
+                    if square_index >= 64 {
+                        bail!("too many squares in fen: {}", fen);
+                    }
+
+                    let color = if c.is_ascii_uppercase() {
+                        Piece::White
+                    } else {
+                        Piece::Black
+                    };
+                    let piece = match c.to_ascii_lowercase() {
+                        'p' if !(8..56).contains(&square_index) => {
+                            bail!("pawn on the first or last rank in fen: {}", fen)
+                        }
+                        'p' => Piece::Pawn,
+                        'n' if square_index % 8 < 4 => Piece::LeftKnight,
+                        'n' => Piece::RightKnight,
+                        'b' => Piece::Bishop,
+                        'r' => Piece::Rook,
+                        'q' => Piece::Queen,
+                        'k' => Piece::King,
+                        _ => bail!("unexpected piece '{}' in fen: {}", c, fen),
+                    };
+
+                    pieces[square_index] = piece | color;
+                    square_index += 1;
+                }
+            }
+        }
+        if square_index != 64 {
+            bail!("wrong number of squares in fen: {}", fen);
+        }
+
+        let current_turn = match fields[1] {
+            "w" => Piece::White,
+            "b" => Piece::Black,
+            turn => bail!("unexpected turn '{}' in fen: {}", turn, fen),
+        };
+
+        let mut moved_pieces: HashSet<usize> = (0..64).collect();
+        for (right, [king, rook]) in [
+            ('K', [60, 63]),
+            ('Q', [60, 56]),
+            ('k', [4, 7]),
+            ('q', [4, 0]),
+        ] {
+            if fields[2].contains(right) {
+                moved_pieces.remove(&king);
+                moved_pieces.remove(&rook);
+            }
+        }
+
+        let last_move = match fields[3] {
+            "-" => [0; 2],
+            square => {
+                let square_index = square_from_name(square)
+                    .ok_or_else(|| anyhow!("invalid en passant square in fen: {}", fen))?;
+                let rank = if current_turn.is_white() { 2 } else { 5 };
+                if square_index / 8 != rank {
+                    bail!("en passant square on the wrong rank in fen: {}", fen);
+                }
+                if current_turn.is_white() {
+                    [square_index - 8, square_index + 8]
+                } else {
+                    [square_index + 8, square_index - 8]
+                }
+            }
+        };
+
+        let mut pieces_locations: HashMap<Piece, Vec<usize>> = HashMap::new();
+        for color in [Piece::White, Piece::Black] {
+            for i in 1..8 {
+                let piece: Piece = num::FromPrimitive::from_u8(i).unwrap();
+                pieces_locations.insert(piece | color, Vec::new());
+            }
+        }
+        for (i, piece) in pieces.iter().enumerate() {
+            if !piece.is_none() {
+                pieces_locations.get_mut(piece).unwrap().push(i);
+            }
+        }
+        for color in [Piece::White, Piece::Black] {
+            if pieces_locations[&(Piece::King | color)].len() != 1 {
+                bail!("expected exactly one {:?} king in fen: {}", color, fen);
+            }
+        }
+
+        let halfmove = match fields.get(4) {
+            Some(halfmove) => halfmove
+                .parse()
+                .map_err(|_| anyhow!("invalid halfmove clock in fen: {}", fen))?,
+            None => 0,
+        };
+        let fullmove: usize = match fields.get(5) {
+            Some(fullmove) => fullmove
+                .parse()
+                .map_err(|_| anyhow!("invalid fullmove number in fen: {}", fen))?,
+            None => 1,
+        };
+        let ply = fullmove.max(1) * 2 - 2 + usize::from(!current_turn.is_white());
+
+        let mut board = Self {
+            pieces,
+            last_move,
+            moved_pieces,
+            current_turn,
+            pieces_locations,
+            ply,
+            halfmove,
+            ..Self::default()
+        };
+        board.start_fen = Some(board.to_fen().into());
+        Ok(board)
+    }
+
+    /// Replaces the position, leaving no castling or en passant rights.
+    pub fn set_pieces(&mut self, pieces: &[(Piece, usize)], current_turn: Piece) {
+        self.pieces = [Piece::None; 64];
+        self.pieces_locations
+            .values_mut()
+            .for_each(|locations| locations.clear());
+        for &(piece, square_index) in pieces {
+            self.pieces[square_index] = piece;
+            self.pieces_locations
+                .entry(piece)
+                .or_default()
+                .push(square_index);
+        }
+
+        self.moved_pieces.extend(0..64);
+        self.legal_moves.clear();
+        self.last_move = [0; 2];
+        self.square_in_promotion = None;
+        self.current_turn = current_turn;
+        self.accumulator = None;
+        self.previous = None;
+        self.status = Status::Playing;
+        self.halfmove = 0;
+        self.played = None;
+        self.start_fen = Some(self.to_fen().into());
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for y in 0..8 {
+            let mut empty = 0;
+            for x in 0..8 {
+                let piece = self.pieces[y * 8 + x];
+                if piece.is_none() {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    placement.push_str(&empty.to_string());
+                    empty = 0;
+                }
+
+                let c = match piece.split().0 {
+                    Piece::Pawn => 'p',
+                    Piece::LeftKnight | Piece::RightKnight => 'n',
+                    Piece::Bishop => 'b',
+                    Piece::Rook => 'r',
+                    Piece::Queen => 'q',
+                    _ => 'k',
+                };
+                placement.push(if piece.is_white() {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                });
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if y < 7 {
+                placement.push('/');
+            }
+        }
+
+        let castling: String = self
+            .get_castling_rights()
+            .iter()
+            .zip(['K', 'Q', 'k', 'q'])
+            .filter(|(&right, _)| right)
+            .map(|(_, c)| c)
+            .collect();
+
+        format!(
+            "{} {} {} {} {} {}",
+            placement,
+            if self.current_turn.is_white() {
+                "w"
+            } else {
+                "b"
+            },
+            if castling.is_empty() { "-" } else { &castling },
+            self.get_en_passant_square()
+                .map_or("-".to_string(), square_name),
+            self.halfmove,
+            self.ply / 2 + 1
+        )
+    }
+
     pub fn get_piece_maybe(&self, x: isize, y: isize) -> Option<Piece> {
         if x < 0 || y < 0 {
             return None;
@@ -114,10 +483,10 @@ impl Board {
             .iter()
             .filter(|[x, y]| {
                 let mut board = self.clone();
-                board.force_move_piece(square_index, *y * 8 + *x, true);
+                board.make_move(Move::new(square_index, *y * 8 + *x));
                 !board.is_check(self.current_turn)
             })
-            .map(|m| m.clone())
+            .copied()
             .collect();
 
         self.legal_moves.insert(square_index, legal_moves.clone());
@@ -167,28 +536,252 @@ impl Board {
         legal_moves
     }
 
-    fn is_check(&mut self, color: Piece) -> bool {
+    pub fn get_square(&self, square_index: usize) -> Piece {
+        self.pieces[square_index]
+    }
+
+    pub fn get_current_turn(&self) -> Piece {
+        self.current_turn
+    }
+
+    pub fn get_status(&self) -> Status {
+        self.status
+    }
+
+    pub fn get_ply(&self) -> usize {
+        self.ply
+    }
+
+    /// The fen the board was set up from, `None` for the standard starting position.
+    pub fn get_start_fen(&self) -> Option<&str> {
+        self.start_fen.as_deref()
+    }
+
+    pub fn get_played_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let mut played = self.played.as_deref();
+        while let Some(Played { m, before }) = played {
+            moves.push(*m);
+            played = before.as_deref();
+        }
+        moves.reverse();
+        moves
+    }
+
+    pub fn get_castling_rights(&self) -> [bool; 4] {
+        let can_castle = |king: usize, rook: usize, color: Piece| {
+            self.pieces[king] == Piece::King | color
+                && self.pieces[rook] == Piece::Rook | color
+                && !self.moved_pieces.contains(&king)
+                && !self.moved_pieces.contains(&rook)
+        };
+
+        [
+            can_castle(60, 63, Piece::White),
+            can_castle(60, 56, Piece::White),
+            can_castle(4, 7, Piece::Black),
+            can_castle(4, 0, Piece::Black),
+        ]
+    }
+
+    pub fn get_en_passant_square(&self) -> Option<usize> {
+        let [from, to] = self.last_move;
+
+        if self.pieces[to].split().0 == Piece::Pawn && from.abs_diff(to) == 16 {
+            Some((from + to) / 2)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_accumulator(&self) -> Option<&Accumulator> {
+        self.accumulator.as_ref()
+    }
+
+    pub fn set_accumulator(&mut self, accumulator: Option<Accumulator>) {
+        self.accumulator = accumulator;
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.computer.set_threads(threads);
+    }
+
+    pub fn set_evaluator(&mut self, evaluator: Arc<dyn Evaluator>) {
+        self.computer.set_evaluator(evaluator);
+    }
+
+    pub fn set_external(&mut self, external: External) {
+        self.computer.set_external(external);
+    }
+
+    pub fn set_book(&mut self, book: Book) {
+        self.computer.set_book(book);
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Arc<Tablebase>) {
+        self.computer.set_tablebase(tablebase);
+    }
+
+    pub fn set_syzygy(&mut self, syzygy: Arc<Syzygy>) {
+        self.computer.set_syzygy(syzygy);
+    }
+
+    pub fn set_strength(&mut self, strength: Strength) {
+        self.computer.set_strength(strength);
+    }
+
+    pub fn set_mcts_budget(&mut self, budget: Limits) {
+        self.computer.set_mcts_budget(budget);
+    }
+
+    pub fn set_ponder(&mut self, ponder: bool) {
+        self.computer.set_ponder(ponder);
+    }
+
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.computer.set_multi_pv(multi_pv);
+    }
+
+    pub fn analysis(&self) -> Vec<Line> {
+        self.computer.lines()
+    }
+
+    pub fn hash(&self) -> u64 {
+        zobrist::hash(self)
+    }
+
+    pub fn is_capture(&self, m: Move) -> bool {
+        !self.pieces[m.to].is_none()
+            || (self.pieces[m.from].split().0 == Piece::Pawn && m.to % 8 != m.from % 8)
+    }
+
+    pub fn is_in_check(&mut self) -> bool {
+        let color = self.current_turn;
+
+        self.current_turn = color.ennemy();
+        let is_check = self.is_check(color);
+        self.current_turn = color;
+
+        is_check
+    }
+
+    pub fn get_pseudo_legal_moves(&mut self) -> Vec<Move> {
+        let mut moves = Vec::new();
+
+        for [from, to] in self.get_all_sub_legal_moves() {
+            self.push_with_promotions(Move::new(from, to), &mut moves);
+        }
+
+        moves
+    }
+
+    pub fn get_all_moves(&mut self) -> Vec<Move> {
+        let mut moves = Vec::new();
+
+        for [from, to] in self.get_all_legal_moves() {
+            self.push_with_promotions(Move::new(from, to), &mut moves);
+        }
+
+        moves
+    }
+
+    fn push_with_promotions(&self, m: Move, moves: &mut Vec<Move>) {
+        if self.pieces[m.from].split().0 == Piece::Pawn && (m.to < 8 || m.to >= 56) {
+            for promotion in [Piece::Queen, Piece::LeftKnight, Piece::Rook, Piece::Bishop] {
+                moves.push(m.with_promotion(promotion));
+            }
+        } else {
+            moves.push(m);
+        }
+    }
+
+    pub fn is_pseudo_legal(&mut self, m: Move) -> bool {
+        let is_promotion = self.pieces[m.from].split().0 == Piece::Pawn && (m.to < 8 || m.to >= 56);
+
+        self.pieces[m.from].color() == self.current_turn
+            && is_promotion != m.promotion.is_none()
+            && self
+                .get_sub_legal_moves_square_indices(m.from)
+                .contains(&m.to)
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.pieces_locations
+            .iter()
+            .filter(|(piece, _)| !piece.is_none())
+            .map(|(_, locations)| locations.len())
+            .sum()
+    }
+
+    pub fn has_non_pawn_material(&self, color: Piece) -> bool {
+        [
+            Piece::LeftKnight,
+            Piece::RightKnight,
+            Piece::Bishop,
+            Piece::Rook,
+            Piece::Queen,
+        ]
+        .iter()
+        .any(|piece| {
+            self.pieces_locations
+                .get(&(*piece | color))
+                .map(|locations| !locations.is_empty())
+                .unwrap_or(false)
+        })
+    }
+
+    pub fn make_null_move(&mut self) {
+        self.last_move = [0; 2];
+        self.legal_moves.drain();
+        self.current_turn = self.current_turn.ennemy();
+    }
+
+    pub fn make_move(&mut self, m: Move) {
+        self.force_move_piece(m.from, m.to, true);
+
+        if self.square_in_promotion.is_some() {
+            self.force_promote(
+                if m.promotion.is_none() {
+                    Piece::Queen
+                } else {
+                    m.promotion
+                },
+                true,
+            );
+        }
+    }
+
+    pub fn is_check(&mut self, color: Piece) -> bool {
         let king = self
             .pieces_locations
             .get(&(Piece::King | color))
             .unwrap()
             .first()
-            .unwrap()
-            .clone();
+            .copied()
+            .unwrap();
 
         self.get_all_sub_legal_moves()
             .iter()
-            .find(|[_, to]| *to == king)
-            .is_some()
+            .any(|[_, to]| *to == king)
     }
 
     pub fn is_in_last_move(&self, x: usize, y: usize) -> bool {
-        if self.last_move[0] == self.last_move[1] {
+        let last_move = self
+            .flying_piece
+            .map(|(from, _, to)| [from, to])
+            .unwrap_or(self.last_move);
+        if last_move[0] == last_move[1] {
             return false;
         }
 
         let square_index = y * 8 + x;
-        self.last_move[0] == square_index || self.last_move[1] == square_index
+        last_move[0] == square_index || last_move[1] == square_index
+    }
+
+    pub fn thinking_progress(&self) -> Option<f64> {
+        self.computer
+            .thinking_since()
+            .map(|start| (start.elapsed().as_secs_f64() / COMPUTER_MOVETIME.as_secs_f64()).min(1.))
     }
 
     pub fn piece_has_moved(&self, x: usize, y: usize) -> bool {
@@ -203,8 +796,17 @@ impl Board {
         }
     }
 
+    pub fn get_opponents(&self) -> [Opponent; 2] {
+        [self.white_opponent, self.black_opponent]
+    }
+
+    pub fn set_opponents(&mut self, white_opponent: Opponent, black_opponent: Opponent) {
+        self.white_opponent = white_opponent;
+        self.black_opponent = black_opponent;
+    }
+
     pub fn mouse_press(&mut self, mouse_x: f64, mouse_y: f64) {
-        if self.current_opponent() != Opponent::Player {
+        if self.current_opponent() != Opponent::Player || self.status != Status::Playing {
             return;
         }
 
@@ -212,20 +814,18 @@ impl Board {
         let y = (mouse_y * 8.).floor() as usize;
         let square_index = y * 8 + x;
 
-        if self.square_in_promotion.is_some() {
-            if self.is_in_promotion(x, y) {
-                let realtive_x = mouse_x * 8. - x as f64;
-                let realtive_y = mouse_y * 8. - y as f64;
-                match [
-                    (realtive_x * 2.).floor() as usize,
-                    (realtive_y * 2.).floor() as usize,
-                ] {
-                    [0, 0] => self.promote(Piece::Queen),
-                    [1, 0] => self.promote(Piece::Rook),
-                    [0, 1] => self.promote(Piece::Bishop),
-                    [1, 1] => self.promote(Piece::LeftKnight),
-                    combination => panic!("unexpected combination: {:?}", combination),
-                }
+        if self.square_in_promotion.is_some() && self.is_in_promotion(x, y) {
+            let realtive_x = mouse_x * 8. - x as f64;
+            let realtive_y = mouse_y * 8. - y as f64;
+            match [
+                (realtive_x * 2.).floor() as usize,
+                (realtive_y * 2.).floor() as usize,
+            ] {
+                [0, 0] => self.promote(Piece::Queen),
+                [1, 0] => self.promote(Piece::Rook),
+                [0, 1] => self.promote(Piece::Bishop),
+                [1, 1] => self.promote(Piece::LeftKnight),
+                combination => panic!("unexpected combination: {:?}", combination),
             }
         }
 
@@ -245,7 +845,7 @@ impl Board {
     }
 
     pub fn mouse_relase(&mut self, mouse_x: f64, mouse_y: f64) {
-        if self.current_opponent() != Opponent::Player {
+        if self.current_opponent() != Opponent::Player || self.status != Status::Playing {
             return;
         }
 
@@ -264,12 +864,20 @@ impl Board {
 
     pub fn move_piece(&mut self, from: usize, to: usize) {
         if self.get_legal_moves_square_indices(from).contains(&to) {
+            self.previous = Some(Arc::new(self.clone()));
             self.force_move_piece(from, to, false);
         }
 
+        self.update_status();
+        if self.square_in_promotion.is_none() && self.status != Status::Playing {
+            println!("{:?}", self.status);
+        }
+    }
+
+    pub fn update_status(&mut self) {
         let legal_moves = self.get_all_legal_moves();
-        if legal_moves.len() == 0 {
-            if self.is_check(self.current_turn) {
+        if legal_moves.is_empty() {
+            if self.is_in_check() {
                 self.status = Status::Checkmate
             } else {
                 self.status = Status::Stalemate
@@ -281,21 +889,16 @@ impl Board {
                 .pieces_locations
                 .iter()
                 .filter(|(piece, _)| !piece.is_none() && piece.split().0 != Piece::King)
-                .map(|(piece, locations)| locations.iter().map(move |_| piece.clone()))
-                .flatten()
+                .flat_map(|(piece, locations)| locations.iter().map(move |_| *piece))
                 .collect::<Vec<_>>();
 
-            if remaining_pieces.len() == 0
+            if remaining_pieces.is_empty()
                 || (remaining_pieces.len() == 1
                     && [Piece::Bishop, Piece::LeftKnight, Piece::RightKnight]
                         .contains(&remaining_pieces.first().unwrap().split().0))
             {
                 self.status = Status::Dead
             }
-
-            if self.status != Status::Playing {
-                println!("{:?}", self.status);
-            }
         }
     }
 
@@ -308,6 +911,23 @@ impl Board {
         let is_promotion = piece == Piece::Pawn && [0., 7.].contains(&(to as f64 / 8.).floor());
 
         let mut ate = !self.pieces[to].is_none();
+        self.halfmove = if piece == Piece::Pawn || ate {
+            0
+        } else {
+            self.halfmove + 1
+        };
+        self.played = Some(Arc::new(Played {
+            m: Move::new(from, to),
+            before: self.played.take(),
+        }));
+
+        if let Some(accumulator) = self.accumulator.as_mut() {
+            accumulator.remove(self.pieces[from], from);
+            if ate {
+                accumulator.remove(self.pieces[to], to);
+            }
+            accumulator.add(self.pieces[from], to);
+        }
 
         let piece_locations = self.pieces_locations.get_mut(&self.pieces[from]).unwrap();
         piece_locations.remove(piece_locations.iter().position(|i| *i == from).unwrap());
@@ -330,6 +950,10 @@ impl Board {
                 .unwrap();
             piece_locations.remove(piece_locations.iter().position(|i| *i == from + 3).unwrap());
             piece_locations.push(from + 1);
+            if let Some(accumulator) = self.accumulator.as_mut() {
+                accumulator.remove(self.pieces[from + 3], from + 3);
+                accumulator.add(self.pieces[from + 3], from + 1);
+            }
 
             self.pieces.swap(from + 3, from + 1);
             self.moved_pieces.insert(from + 3);
@@ -341,6 +965,10 @@ impl Board {
                 .unwrap();
             piece_locations.remove(piece_locations.iter().position(|i| *i == from - 4).unwrap());
             piece_locations.push(from - 1);
+            if let Some(accumulator) = self.accumulator.as_mut() {
+                accumulator.remove(self.pieces[from - 4], from - 4);
+                accumulator.add(self.pieces[from - 4], from - 1);
+            }
 
             self.pieces.swap(from - 4, from - 1);
             self.moved_pieces.insert(from - 4);
@@ -357,6 +985,13 @@ impl Board {
                     .unwrap(),
             );
 
+            if let Some(accumulator) = self.accumulator.as_mut() {
+                accumulator.remove(
+                    self.pieces[from + to % 8 - from % 8],
+                    from + to % 8 - from % 8,
+                );
+            }
+
             self.pieces[from + to % 8 - from % 8] = Piece::None;
             self.moved_pieces.insert(from + to % 8 - from % 8);
             ate = true;
@@ -370,41 +1005,62 @@ impl Board {
         if is_promotion {
             let piece_locations = self.pieces_locations.get_mut(&self.pieces[to]).unwrap();
             piece_locations.remove(piece_locations.iter().position(|i| *i == to).unwrap());
+            if let Some(accumulator) = self.accumulator.as_mut() {
+                accumulator.remove(self.pieces[to], to);
+            }
 
             self.pieces[to] = Piece::None;
             self.square_in_promotion = Some(to);
         } else {
             self.current_turn = self.current_turn.ennemy();
+            self.ply += 1;
         }
     }
 
     pub fn promote(&mut self, piece: Piece) {
+        self.force_promote(piece, false);
+    }
+
+    pub fn force_promote(&mut self, piece: Piece, silent: bool) {
         if let Some(square_in_promotion) = self.square_in_promotion {
             self.pieces_locations
                 .get_mut(&(piece | self.current_turn))
                 .unwrap()
                 .push(square_in_promotion);
+            if let Some(accumulator) = self.accumulator.as_mut() {
+                accumulator.add(piece | self.current_turn, square_in_promotion);
+            }
             self.pieces[square_in_promotion] = piece | self.current_turn;
             self.square_in_promotion = None;
+            if let Some(played) = self.played.take() {
+                self.played = Some(Arc::new(Played {
+                    m: played.m.with_promotion(piece),
+                    before: played.before.clone(),
+                }));
+            }
             self.current_turn = self.current_turn.ennemy();
-            self.play_sound("promotion");
+            self.ply += 1;
+            if !silent {
+                self.play_sound("promotion");
+            }
         }
     }
 
     pub fn play_sound(&self, name: &'static str) {
-        thread::spawn(move || {
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+        thread::spawn(move || -> Result<()> {
+            let (_stream, stream_handle) = OutputStream::try_default()?;
 
             let assets = find_folder::Search::ParentsThenKids(3, 3)
                 .for_folder("assets")
-                .unwrap();
-            let file = BufReader::new(
-                File::open(assets.join("sound").join(name).with_extension("ogg")).unwrap(),
-            );
+                .map_err(|_| anyhow!("could not find the assets folder"))?;
+            let file = BufReader::new(File::open(
+                assets.join("sound").join(name).with_extension("ogg"),
+            )?);
 
-            let source = Decoder::new(file).unwrap();
-            stream_handle.play_raw(source.convert_samples()).unwrap();
+            let source = Decoder::new(file)?;
+            stream_handle.play_raw(source.convert_samples())?;
             thread::sleep(Duration::from_secs(1));
+            Ok(())
         });
     }
 
@@ -419,13 +1075,9 @@ impl Board {
     }
 
     pub fn update(&mut self, dt: Duration) {
-        if self.status == Status::Playing && self.current_opponent() == Opponent::Computer {
+        if self.status == Status::Playing && self.current_opponent() != Opponent::Player {
             if self.square_in_promotion.is_some() {
-                self.promote(
-                    *[Piece::Queen, Piece::Rook, Piece::Bishop, Piece::LeftKnight]
-                        .choose(&mut self.rng.clone())
-                        .unwrap(),
-                );
+                self.promote(self.computer_promotion);
             } else if let Some((from, current, to)) = self.flying_piece {
                 let start = [(from as f64 / 8.).floor(), from as f64 % 8.];
                 let target = [(to as f64 / 8.).floor(), to as f64 % 8.];
@@ -444,36 +1096,118 @@ impl Board {
                     let dx = d * dist_x / dist;
                     self.flying_piece = Some((from, [current[0] + dx, current[1] + dy], to));
                 }
-            } else {
-                let legal_moves = self.get_all_legal_moves();
-                if let Some([from, to]) = legal_moves.choose(&mut self.rng).copied() {
-                    self.flying_piece =
-                        Some((from, [(from as f64 / 8.).floor(), from as f64 % 8.], to));
-                    self.last_move = [from, to];
-                }
+            } else if self.current_opponent() == Opponent::Remote {
+                // remote moves come in through animate_move
+            } else if !self.computer.is_thinking() {
+                let board = self.clone();
+                self.computer
+                    .think(&board, Limits::movetime(COMPUTER_MOVETIME));
+            } else if let Some(Some(m)) = self.computer.poll() {
+                self.animate_move(m);
+            }
+        } else if self.status == Status::Playing
+            && self.square_in_promotion.is_none()
+            && self.computer.ponder_move().is_some()
+        {
+            let board = self.clone();
+            self.computer.ponder(&board);
+        }
+    }
+
+    pub fn animate_move(&mut self, m: Move) {
+        self.flying_piece = Some((
+            m.from,
+            [(m.from as f64 / 8.).floor(), m.from as f64 % 8.],
+            m.to,
+        ));
+        self.computer_promotion = if m.promotion.is_none() {
+            Piece::Queen
+        } else {
+            m.promotion
+        };
+    }
+
+    pub fn replay(&mut self, moves: &[Move]) {
+        self.reset();
+        for &m in moves {
+            self.make_move(m);
+        }
+        self.update_status();
+    }
+
+    pub fn end(&mut self) {
+        self.stop_thinking();
+        self.flying_piece = None;
+        self.status = Status::Ended;
+    }
+
+    pub fn stop_thinking(&mut self) {
+        self.computer.cancel();
+    }
+
+    pub fn reset(&mut self) {
+        self.stop_thinking();
+
+        *self = Self {
+            white_opponent: self.white_opponent,
+            black_opponent: self.black_opponent,
+            computer: self.computer.clone(),
+            ..Self::default()
+        };
+    }
+
+    pub fn undo(&mut self) {
+        self.stop_thinking();
+
+        let has_player =
+            self.white_opponent == Opponent::Player || self.black_opponent == Opponent::Player;
+        while let Some(previous) = self.previous.take() {
+            *self = Self {
+                selected: None,
+                dragging: false,
+                flying_piece: None,
+                computer: self.computer.clone(),
+                ..(*previous).clone()
+            };
+
+            if !has_player || self.current_opponent() == Opponent::Player {
+                break;
             }
         }
     }
 }
 
+pub fn square_name(square_index: usize) -> String {
+    format!(
+        "{}{}",
+        (b'a' + (square_index % 8) as u8) as char,
+        8 - square_index / 8
+    )
+}
+
+pub fn square_from_name(name: &str) -> Option<usize> {
+    let mut chars = name.chars();
+    let file = chars.next().filter(|c| ('a'..='h').contains(c))?;
+    let rank = chars.next().filter(|c| ('1'..='8').contains(c))?;
+    if chars.next().is_some() {
+        return None;
+    }
+
+    Some((7 - (rank as usize - '1' as usize)) * 8 + file as usize - 'a' as usize)
+}
+
 impl Default for Board {
     fn default() -> Self {
         let mut pieces = [Piece::None; 64];
 
-        let black_pieces: [Piece; 16] =
-            concat_arrays!(BACK_ROW, FRONT_ROW).map(|p| p | Piece::Black);
-        let white_pieces: [Piece; 16] =
-            concat_arrays!(FRONT_ROW, BACK_ROW).map(|p| p | Piece::White);
-
-        black_pieces.clone().swap_with_slice(&mut pieces[..16]);
-        white_pieces.clone().swap_with_slice(&mut pieces[48..]);
+        pieces[..8].copy_from_slice(&BACK_ROW.map(|p| p | Piece::Black));
+        pieces[8..16].copy_from_slice(&FRONT_ROW.map(|p| p | Piece::Black));
+        pieces[48..56].copy_from_slice(&FRONT_ROW.map(|p| p | Piece::White));
+        pieces[56..].copy_from_slice(&BACK_ROW.map(|p| p | Piece::White));
 
         let mut pieces_locations = HashMap::new();
-        for i in 0..64 {
-            pieces_locations
-                .entry(pieces[i])
-                .or_insert(Vec::new())
-                .push(i);
+        for (i, piece) in pieces.iter().enumerate() {
+            pieces_locations.entry(*piece).or_insert(Vec::new()).push(i);
         }
 
         Self {
@@ -486,11 +1220,56 @@ impl Default for Board {
             current_turn: Piece::White,
             white_opponent: Opponent::Computer,
             black_opponent: Opponent::Computer,
-            rng: thread_rng(),
             flying_piece: None,
             square_in_promotion: None,
             status: Status::Playing,
             pieces_locations,
+            computer: Computer::new(Search::default()),
+            computer_promotion: Piece::Queen,
+            previous: None,
+            accumulator: None,
+            ply: 0,
+            halfmove: 0,
+            start_fen: None,
+            played: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_fen_rejects_impossible_positions() {
+        for fen in [
+            "P3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/p3K3 b - - 0 1",
+            "4k2p/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2P w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - a1 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - e3 0 1",
+        ] {
+            assert!(Board::from_fen(fen).is_err(), "{}", fen);
+        }
+
+        let mut board = Board::from_fen("4k3/P7/8/8/8/8/p7/4K3 w - - 0 1").unwrap();
+        assert!(!board.get_all_moves().is_empty());
+    }
+
+    #[test]
+    fn to_fen_keeps_the_halfmove_clock() {
+        let fen = "r3k3/8/8/8/8/8/4P3/R3K3 w Qq - 7 30";
+        let mut board = Board::from_fen(fen).unwrap();
+        assert_eq!(board.to_fen(), fen);
+
+        board.make_move("a1a2".parse().unwrap());
+        assert!(board.to_fen().ends_with(" 8 30"), "{}", board.to_fen());
+        board.make_move("a8a2".parse().unwrap());
+        assert!(board.to_fen().ends_with(" 0 31"), "{}", board.to_fen());
+        board.make_move("e2e4".parse().unwrap());
+        board.make_move("e8d8".parse().unwrap());
+        board.make_move("e1d1".parse().unwrap());
+        assert!(board.to_fen().ends_with(" 2 32"), "{}", board.to_fen());
+    }
+}
@@ -0,0 +1,266 @@
+use std::{collections::HashMap, fs, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    engine::book::{self, Entry},
+    pgn::{self, Outcome},
+    piece::Piece,
+};
+
+const DEFAULT_PLIES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Weighting {
+    Count,
+    Result,
+}
+
+impl FromStr for Weighting {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "count" => Ok(Self::Count),
+            "result" => Ok(Self::Result),
+            _ => bail!("unknown weighting: {} (expected count or result)", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Side {
+    Both,
+    White,
+    Black,
+}
+
+impl FromStr for Side {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "both" => Ok(Self::Both),
+            "white" => Ok(Self::White),
+            "black" => Ok(Self::Black),
+            _ => bail!("unknown colour: {} (expected both, white or black)", s),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    games: u32,
+    wins: u32,
+    draws: u32,
+}
+
+impl Stats {
+    fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.) / self.games as f64
+    }
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("build") => build(&args[1..]),
+        _ => bail!("usage: book build <pgn>... --output <file> [--plies N] [--min-count N] [--min-score S] [--weight count|result] [--color both|white|black]"),
+    }
+}
+
+fn build(args: &[String]) -> Result<()> {
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut plies = DEFAULT_PLIES;
+    let mut min_count = 1;
+    let mut min_score = 0.;
+    let mut weighting = Weighting::Result;
+    let mut side = Side::Both;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = Some(args.next().context("--output expects a file")?),
+            "--plies" => match args.next().map(|plies| plies.parse()) {
+                Some(Ok(value)) => plies = value,
+                _ => bail!("--plies expects a number"),
+            },
+            "--min-count" => match args.next().map(|count| count.parse()) {
+                Some(Ok(value)) => min_count = value,
+                _ => bail!("--min-count expects a number"),
+            },
+            "--min-score" => match args.next().map(|score| score.parse()) {
+                Some(Ok(value)) => min_score = value,
+                _ => bail!("--min-score expects a number between 0 and 1"),
+            },
+            "--weight" => {
+                weighting = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--weight expects count or result"))?
+                    .parse()?
+            }
+            "--color" => {
+                side = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--color expects both, white or black"))?
+                    .parse()?
+            }
+            arg if arg.starts_with("--") => bail!("unexpected argument: {}", arg),
+            path => inputs.push(path),
+        }
+    }
+
+    let output = output.context("book build needs an --output file")?;
+    if inputs.is_empty() {
+        bail!("book build needs at least one PGN file");
+    }
+
+    let mut stats: HashMap<(u64, u16), Stats> = HashMap::new();
+    let mut game_count = 0;
+    let mut skipped = 0;
+
+    for input in inputs {
+        let text =
+            fs::read_to_string(input).with_context(|| format!("could not read {}", input))?;
+
+        for game in pgn::parse_games(&text) {
+            game_count += 1;
+            let mut board = match game.start_position() {
+                Ok(board) => board,
+                Err(_) => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+
+            for san in game.moves.iter().take(plies) {
+                let m = match pgn::parse_san(&mut board, san) {
+                    Ok(m) => m,
+                    Err(err) => {
+                        eprintln!("{}: game {}: {}", input, game_count, err);
+                        skipped += 1;
+                        break;
+                    }
+                };
+
+                let color = board.get_current_turn();
+                let included = match side {
+                    Side::Both => true,
+                    Side::White => color == Piece::White,
+                    Side::Black => color == Piece::Black,
+                };
+                if included {
+                    let entry = stats
+                        .entry((book::hash(&board), book::encode_move(&board, m)))
+                        .or_default();
+                    entry.games += 1;
+                    match (game.outcome, color) {
+                        (Outcome::WhiteWins, Piece::White) | (Outcome::BlackWins, Piece::Black) => {
+                            entry.wins += 1
+                        }
+                        (Outcome::Draw, _) => entry.draws += 1,
+                        _ => {}
+                    }
+                }
+
+                board.make_move(m);
+            }
+        }
+    }
+
+    let weighted: Vec<((u64, u16), u64)> = stats
+        .into_iter()
+        .filter(|(_, stats)| stats.games >= min_count && stats.score() >= min_score)
+        .map(|(key, stats)| {
+            let weight = match weighting {
+                Weighting::Count => stats.games as u64,
+                Weighting::Result => 2 * stats.wins as u64 + stats.draws as u64,
+            };
+            (key, weight)
+        })
+        .filter(|(_, weight)| *weight > 0)
+        .collect();
+
+    let max_weight = weighted
+        .iter()
+        .map(|(_, weight)| *weight)
+        .max()
+        .unwrap_or(0);
+    let scale = max_weight.div_ceil(u16::MAX as u64).max(1);
+    let entries: Vec<Entry> = weighted
+        .into_iter()
+        .map(|((key, raw_move), weight)| Entry {
+            key,
+            raw_move,
+            weight: (weight / scale).max(1) as u16,
+            learn: 0,
+        })
+        .collect();
+
+    println!(
+        "{} games ({} skipped), {} entries written to {}",
+        game_count,
+        skipped,
+        entries.len(),
+        output
+    );
+    book::write(output, entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::board::{square_from_name, Board, Move};
+    use book::Book;
+
+    const GAMES: &str = "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 1-0\n\n\
+                         [Result \"0-1\"]\n\n1. d4 d5 0-1\n\n\
+                         [Result \"1/2-1/2\"]\n\n1. e4 c5 1/2-1/2\n";
+
+    fn uci(name: &str) -> Move {
+        Move::new(
+            square_from_name(&name[..2]).unwrap(),
+            square_from_name(&name[2..]).unwrap(),
+        )
+    }
+
+    fn build_book(name: &str, options: &[&str]) -> Book {
+        let dir = env::temp_dir().join(format!("chess-ai-book-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pgn = dir.join("games.pgn");
+        let output = dir.join("book.bin");
+        fs::write(&pgn, GAMES).unwrap();
+
+        let mut args: Vec<String> = vec![
+            "build".to_string(),
+            pgn.display().to_string(),
+            "--output".to_string(),
+            output.display().to_string(),
+        ];
+        args.extend(options.iter().map(|option| option.to_string()));
+        run(&args).unwrap();
+
+        let book = Book::open(&output).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        book
+    }
+
+    #[test]
+    fn built_book_can_be_probed() {
+        let book = build_book("result", &[]);
+        let mut board = Board::default();
+        assert_eq!(book.moves(&board), vec![(uci("e2e4"), 3)]);
+        board.make_move(uci("e2e4"));
+        assert_eq!(book.moves(&board), vec![(uci("c7c5"), 1)]);
+
+        let book = build_book("count", &["--weight", "count", "--color", "white"]);
+        let mut moves = book.moves(&Board::default());
+        moves.sort_by_key(|(_, weight)| *weight);
+        assert_eq!(moves, vec![(uci("d2d4"), 1), (uci("e2e4"), 2)]);
+        board = Board::default();
+        board.make_move(uci("e2e4"));
+        assert_eq!(book.moves(&board), vec![]);
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use crate::board::{Board, Move, Opponent};
+
+use super::{
+    book::Book,
+    eval::Evaluator,
+    external::External,
+    mcts::Mcts,
+    search::{Limits, Line, Reporter, Search},
+    strength::Strength,
+    syzygy::Syzygy,
+    tablebase::Tablebase,
+};
+
+type BestAndPonder = (Option<Move>, Option<Move>);
+
+#[derive(Clone)]
+struct Thinking {
+    stop: Arc<AtomicBool>,
+    result: Arc<Mutex<Option<BestAndPonder>>>,
+    start: Instant,
+    deadline: Option<Instant>,
+    limits: Limits,
+    pondering: Option<u64>,
+}
+
+impl Thinking {
+    fn new(pondering: Option<u64>) -> Self {
+        Self {
+            stop: Arc::new(AtomicBool::new(false)),
+            result: Arc::new(Mutex::new(None)),
+            start: Instant::now(),
+            deadline: None,
+            limits: Limits::default(),
+            pondering,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Computer {
+    search: Arc<Mutex<Search>>,
+    mcts: Arc<Mutex<Mcts>>,
+    external: Arc<Mutex<Option<External>>>,
+    book: Option<Arc<Book>>,
+    thinking: Option<Thinking>,
+    ponder: bool,
+    ponder_move: Option<Move>,
+    lines: Arc<Mutex<Vec<Line>>>,
+}
+
+impl Computer {
+    pub fn new(search: Search) -> Self {
+        let mut computer = Self {
+            search: Arc::new(Mutex::new(search)),
+            mcts: Arc::default(),
+            external: Arc::default(),
+            book: None,
+            thinking: None,
+            ponder: true,
+            ponder_move: None,
+            lines: Arc::default(),
+        };
+        computer.set_reporter(Arc::new(|_: &[Line]| ()));
+        computer
+    }
+
+    pub fn set_reporter(&mut self, reporter: Reporter) {
+        let lines = self.lines.clone();
+        self.search
+            .lock()
+            .unwrap()
+            .set_reporter(Arc::new(move |new_lines: &[Line]| {
+                *lines.lock().unwrap() = new_lines.to_vec();
+                reporter(new_lines);
+            }));
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.search.lock().unwrap().set_threads(threads);
+    }
+
+    pub fn set_hash(&mut self, hash_mb: usize) {
+        self.search.lock().unwrap().set_hash(hash_mb);
+    }
+
+    pub fn clear(&mut self) {
+        self.cancel();
+        self.search.lock().unwrap().clear();
+    }
+
+    pub fn set_evaluator(&mut self, evaluator: Arc<dyn Evaluator>) {
+        self.search.lock().unwrap().set_evaluator(evaluator);
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Arc<Tablebase>) {
+        self.search.lock().unwrap().set_tablebase(tablebase);
+    }
+
+    pub fn set_syzygy(&mut self, syzygy: Arc<Syzygy>) {
+        self.search.lock().unwrap().set_syzygy(syzygy);
+    }
+
+    pub fn set_strength(&mut self, strength: Strength) {
+        self.search.lock().unwrap().set_strength(strength);
+    }
+
+    pub fn set_mcts_budget(&mut self, budget: Limits) {
+        self.mcts.lock().unwrap().set_budget(budget);
+    }
+
+    pub fn set_external(&mut self, external: External) {
+        *self.external.lock().unwrap() = Some(external);
+    }
+
+    pub fn set_book(&mut self, book: Book) {
+        self.book = Some(Arc::new(book));
+    }
+
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.search.lock().unwrap().set_multi_pv(multi_pv);
+    }
+
+    pub fn lines(&self) -> Vec<Line> {
+        self.lines.lock().unwrap().clone()
+    }
+
+    pub fn set_ponder(&mut self, ponder: bool) {
+        self.ponder = ponder;
+    }
+
+    pub fn is_thinking(&self) -> bool {
+        self.thinking
+            .as_ref()
+            .is_some_and(|thinking| thinking.pondering.is_none())
+    }
+
+    pub fn thinking_since(&self) -> Option<Instant> {
+        self.thinking
+            .as_ref()
+            .filter(|thinking| thinking.pondering.is_none())
+            .map(|thinking| thinking.start)
+    }
+
+    pub fn ponder_move(&self) -> Option<Move> {
+        self.ponder_move
+    }
+
+    pub fn think(&mut self, board: &Board, limits: Limits) {
+        let personality = match board.current_opponent() {
+            Opponent::Personality(personality) => Some(personality),
+            _ => None,
+        };
+        let book_move = self
+            .book
+            .as_ref()
+            .filter(|_| board.current_opponent() != Opponent::Engine)
+            .and_then(|book| match personality {
+                Some(personality) => book.pick_with(
+                    board,
+                    personality.book_depth().unwrap_or(book.depth()),
+                    personality.book_variety().unwrap_or(book.variety()),
+                ),
+                None => book.pick(board),
+            });
+        if let Some(book_move) = book_move {
+            self.cancel();
+            let thinking = Thinking::new(None);
+            *thinking.result.lock().unwrap() = Some((Some(book_move), None));
+            self.thinking = Some(thinking);
+            return;
+        }
+
+        // the ponder search has no limits of its own, poll enforces them on a hit
+        if let Some(thinking) = self
+            .thinking
+            .as_mut()
+            .filter(|thinking| thinking.pondering == Some(board.hash()))
+        {
+            thinking.pondering = None;
+            thinking.start = Instant::now();
+            thinking.deadline = limits.movetime.map(|movetime| thinking.start + movetime);
+            thinking.limits = limits;
+            return;
+        }
+
+        self.cancel();
+        self.start(board.clone(), limits, None);
+    }
+
+    pub fn ponder(&mut self, board: &Board) {
+        let ponder_move = match self.ponder_move.take() {
+            Some(ponder_move) if self.thinking.is_none() => ponder_move,
+            _ => return,
+        };
+
+        let mut board = board.clone();
+        if !board.get_all_moves().contains(&ponder_move) {
+            return;
+        }
+        board.make_move(ponder_move);
+        if board.current_opponent() == Opponent::Player {
+            return;
+        }
+
+        self.ponder_on(board);
+    }
+
+    pub fn ponder_on(&mut self, board: Board) {
+        self.cancel();
+
+        let pondering = Some(board.hash());
+        self.start(board, Limits::default(), pondering);
+    }
+
+    fn start(&mut self, board: Board, limits: Limits, pondering: Option<u64>) {
+        self.lines.lock().unwrap().clear();
+
+        let thinking = Thinking::new(pondering);
+        let search = self.search.clone();
+        let mcts = self.mcts.clone();
+        let external = self.external.clone();
+        let stop = thinking.stop.clone();
+        let result = thinking.result.clone();
+
+        thread::spawn(move || {
+            let moves = match board.current_opponent() {
+                Opponent::MonteCarlo => (
+                    mcts.lock().unwrap().think_until(&board, limits, &stop),
+                    None,
+                ),
+                Opponent::Engine => {
+                    let mut external = external.lock().unwrap();
+                    match external
+                        .as_mut()
+                        .map(|external| external.think_until(&board, limits, &stop))
+                    {
+                        Some(Ok(best_move)) => (best_move, None),
+                        failure => {
+                            if let Some(Err(err)) = failure {
+                                eprintln!("{:#}, the computer takes over", err);
+                            }
+                            *external = None;
+                            (
+                                search.lock().unwrap().think_until(&board, limits, &stop),
+                                None,
+                            )
+                        }
+                    }
+                }
+                opponent => {
+                    let mut search = search.lock().unwrap();
+                    search.set_personality(match opponent {
+                        Opponent::Personality(personality) => Some(personality),
+                        _ => None,
+                    });
+                    let best_move = search.think_until(&board, limits, &stop);
+                    let ponder_move = best_move.and_then(|m| search.ponder_move(&board, m));
+                    (best_move, ponder_move)
+                }
+            };
+            *result.lock().unwrap() = Some(moves);
+        });
+
+        self.thinking = Some(thinking);
+    }
+
+    pub fn poll(&mut self) -> Option<Option<Move>> {
+        let thinking = self
+            .thinking
+            .as_ref()
+            .filter(|thinking| thinking.pondering.is_none())?;
+        let reached = self.lines.lock().unwrap().first().is_some_and(|line| {
+            thinking
+                .limits
+                .depth
+                .is_some_and(|depth| line.depth >= depth)
+                || thinking
+                    .limits
+                    .nodes
+                    .is_some_and(|nodes| line.nodes >= nodes)
+        });
+        if reached
+            || thinking
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            thinking.stop.store(true, Ordering::Relaxed);
+        }
+
+        let (best_move, ponder_move) = thinking.result.lock().unwrap().take()?;
+        self.thinking = None;
+        self.ponder_move = ponder_move.filter(|_| self.ponder);
+
+        Some(best_move)
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(thinking) = self.thinking.as_mut() {
+            thinking.pondering = None;
+            thinking.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        self.ponder_move = None;
+        if let Some(thinking) = self.thinking.take() {
+            thinking.stop.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn wait(computer: &mut Computer) -> Option<Move> {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while Instant::now() < deadline {
+            if let Some(best_move) = computer.poll() {
+                return best_move;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("the computer never answered");
+    }
+
+    #[test]
+    fn thinks_in_the_background_until_cancelled() {
+        let mut computer = Computer::new(Search::default());
+        let board = Board::default();
+        let movetime = Duration::from_secs(10);
+
+        let start = Instant::now();
+        computer.think(&board, Limits::movetime(movetime));
+        assert!(computer.is_thinking());
+        assert!(computer.thinking_since().is_some());
+        assert_eq!(computer.poll(), None);
+        assert!(start.elapsed() < movetime / 10);
+
+        // the worker holds the search until it returns
+        while computer.search.try_lock().is_ok() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        computer.cancel();
+        assert!(!computer.is_thinking());
+        while computer.search.try_lock().is_err() {
+            assert!(start.elapsed() < movetime / 2, "the search kept running");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn ponder_hit_keeps_the_running_search() {
+        for limits in [
+            Limits::depth(3),
+            Limits::movetime(Duration::from_millis(50)),
+        ] {
+            let mut computer = Computer::new(Search::default());
+            let mut board = Board::default();
+            board.make_move("e2e4".parse().unwrap());
+            computer.ponder_on(board.clone());
+            let pondering = computer.thinking.as_ref().unwrap().stop.clone();
+
+            computer.think(&board, limits);
+            let thinking = computer.thinking.as_ref().unwrap();
+            assert!(Arc::ptr_eq(&thinking.stop, &pondering));
+            assert!(thinking.pondering.is_none());
+
+            let best_move = wait(&mut computer).unwrap();
+            assert!(board.get_all_moves().contains(&best_move));
+        }
+    }
+
+    #[test]
+    fn ponder_miss_discards_the_search() {
+        let mut computer = Computer::new(Search::default());
+        let mut pondered = Board::default();
+        pondered.make_move("e2e4".parse().unwrap());
+        computer.ponder_on(pondered);
+        let pondering = computer.thinking.as_ref().unwrap().stop.clone();
+
+        let mut board = Board::default();
+        board.make_move("d2d4".parse().unwrap());
+        computer.think(&board, Limits::depth(2));
+        assert!(pondering.load(Ordering::Relaxed));
+        assert!(!Arc::ptr_eq(
+            &computer.thinking.as_ref().unwrap().stop,
+            &pondering
+        ));
+
+        let best_move = wait(&mut computer).unwrap();
+        assert!(board.get_all_moves().contains(&best_move));
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{board::Board, piece::Piece};
+
+const MIDGAME_VALUES: [i32; 6] = [82, 337, 365, 477, 1025, 0];
+const ENDGAME_VALUES: [i32; 6] = [94, 281, 297, 512, 936, 0];
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const TOTAL_PHASE: i32 = 24;
+
+#[rustfmt::skip]
+const MIDGAME_TABLES: [[i32; 64]; 6] = [
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         98, 134,  61,  95,  68, 126,  34, -11,
+         -6,   7,  26,  31,  65,  56,  25, -20,
+        -14,  13,   6,  21,  23,  12,  17, -23,
+        -27,  -2,  -5,  12,  17,   6,  10, -25,
+        -26,  -4,  -4, -10,   3,   3,  33, -12,
+        -35,  -1, -20, -23, -15,  24,  38, -22,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    [
+       -167, -89, -34, -49,  61, -97, -15, -107,
+        -73, -41,  72,  36,  23,  62,   7,  -17,
+        -47,  60,  37,  65,  84, 129,  73,   44,
+         -9,  17,  19,  53,  37,  69,  18,   22,
+        -13,   4,  16,  13,  28,  19,  21,   -8,
+        -23,  -9,  12,  10,  19,  17,  25,  -16,
+        -29, -53, -12,  -3,  -1,  18, -14,  -19,
+       -105, -21, -58, -33, -17, -28, -19,  -23,
+    ],
+    [
+        -29,   4, -82, -37, -25, -42,   7,  -8,
+        -26,  16, -18, -13,  30,  59,  18, -47,
+        -16,  37,  43,  40,  35,  50,  37,  -2,
+         -4,   5,  19,  50,  37,  37,   7,  -2,
+         -6,  13,  13,  26,  34,  12,  10,   4,
+          0,  15,  15,  15,  14,  27,  18,  10,
+          4,  15,  16,   0,   7,  21,  33,   1,
+        -33,  -3, -14, -21, -13, -12, -39, -21,
+    ],
+    [
+         32,  42,  32,  51,  63,   9,  31,  43,
+         27,  32,  58,  62,  80,  67,  26,  44,
+         -5,  19,  26,  36,  17,  45,  61,  16,
+        -24, -11,   7,  26,  24,  35,  -8, -20,
+        -36, -26, -12,  -1,   9,  -7,   6, -23,
+        -45, -25, -16, -17,   3,   0,  -5, -33,
+        -44, -16, -20,  -9,  -1,  11,  -6, -71,
+        -19, -13,   1,  17,  16,   7, -37, -26,
+    ],
+    [
+        -28,   0,  29,  12,  59,  44,  43,  45,
+        -24, -39,  -5,   1, -16,  57,  28,  54,
+        -13, -17,   7,   8,  29,  56,  47,  57,
+        -27, -27, -16, -16,  -1,  17,  -2,   1,
+         -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+        -14,   2, -11,  -2,  -5,   2,  14,   5,
+        -35,  -8,  11,   2,   8,  15,  -3,   1,
+         -1, -18,  -9,  10, -15, -25, -31, -50,
+    ],
+    [
+        -65,  23,  16, -15, -56, -34,   2,  13,
+         29,  -1, -20,  -7,  -8,  -4, -38, -29,
+         -9,  24,   2, -16, -20,   6,  22, -22,
+        -17, -20, -12, -27, -30, -25, -14, -36,
+        -49,  -1, -27, -39, -46, -44, -33, -51,
+        -14, -14, -22, -46, -44, -30, -15, -27,
+          1,   7,  -8, -64, -43, -16,   9,   8,
+        -15,  36,  12, -54,   8, -28,  24,  14,
+    ],
+];
+
+#[rustfmt::skip]
+const ENDGAME_TABLES: [[i32; 64]; 6] = [
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+        178, 173, 158, 134, 147, 132, 165, 187,
+         94, 100,  85,  67,  56,  53,  82,  84,
+         32,  24,  13,   5,  -2,   4,  17,  17,
+         13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+          4,   7,  -6,   1,   0,  -5,  -1,  -8,
+         13,   8,   8,  10,  13,   0,   2,  -7,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    [
+        -58, -38, -13, -28, -31, -27, -63, -99,
+        -25,  -8, -25,  -2,  -9, -25, -24, -52,
+        -24, -20,  10,   9,  -1,  -9, -19, -41,
+        -17,   3,  22,  22,  22,  11,   8, -18,
+        -18,  -6,  16,  25,  16,  17,   4, -18,
+        -23,  -3,  -1,  15,  10,  -3, -20, -22,
+        -42, -20, -10,  -5,  -2, -20, -23, -44,
+        -29, -51, -23, -15, -22, -18, -50, -64,
+    ],
+    [
+        -14, -21, -11,  -8,  -7,  -9, -17, -24,
+         -8,  -4,   7, -12,  -3, -13,  -4, -14,
+          2,  -8,   0,  -1,  -2,   6,   0,   4,
+         -3,   9,  12,   9,  14,  10,   3,   2,
+         -6,   3,  13,  19,   7,  10,  -3,  -9,
+        -12,  -3,   8,  10,  13,   3,  -7, -15,
+        -14, -18,  -7,  -1,   4,  -9, -15, -27,
+        -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+    ],
+    [
+         13,  10,  18,  15,  12,  12,   8,   5,
+         11,  13,  13,  11,  -3,   3,   8,   3,
+          7,   7,   7,   5,   4,  -3,  -5,  -3,
+          4,   3,  13,   1,   2,   1,  -1,   2,
+          3,   5,   8,   4,  -5,  -6,  -8, -11,
+         -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+         -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+         -9,   2,   3,  -1,  -5, -13,   4, -20,
+    ],
+    [
+         -9,  22,  22,  27,  27,  19,  10,  20,
+        -17,  20,  32,  41,  58,  25,  30,   0,
+        -20,   6,   9,  49,  47,  35,  19,   9,
+          3,  22,  24,  45,  57,  40,  57,  36,
+        -18,  28,  19,  47,  31,  34,  39,  23,
+        -16, -27,  15,   6,   9,  17,  10,   5,
+        -22, -23, -30, -16, -16, -23, -36, -32,
+        -33, -28, -22, -43,  -5, -32, -20, -41,
+    ],
+    [
+        -74, -35, -18, -18, -11,  15,   4, -17,
+        -12,  17,  14,  17,  17,  38,  23,  11,
+         10,  17,  23,  15,  20,  45,  44,  13,
+         -8,  22,  24,  27,  26,  33,  26,   3,
+        -18,  -4,  21,  24,  27,  23,   9, -11,
+        -19,  -3,  11,  21,  23,  16,   7,  -9,
+        -27, -11,   4,  13,  14,   4,  -5, -17,
+        -53, -34, -21, -11, -28, -14, -24, -43,
+    ],
+];
+
+pub trait Evaluator: Send + Sync {
+    fn prepare(&self, _board: &mut Board) {}
+
+    fn evaluate(&self, board: &Board) -> i32;
+}
+
+pub struct Handcrafted;
+
+impl Evaluator for Handcrafted {
+    fn evaluate(&self, board: &Board) -> i32 {
+        evaluate(board)
+    }
+}
+
+const PIECE_NAMES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+const PHASE_NAMES: [&str; 2] = ["midgame", "endgame"];
+const PHASE_PARAMETERS: usize = 6 + 6 * 64;
+pub const PARAMETERS: usize = 2 * PHASE_PARAMETERS;
+
+static WEIGHTS: OnceLock<Weights> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weights {
+    values: [[i32; 6]; 2],
+    tables: [[[i32; 64]; 6]; 2],
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            values: [MIDGAME_VALUES, ENDGAME_VALUES],
+            tables: [MIDGAME_TABLES, ENDGAME_TABLES],
+        }
+    }
+}
+
+impl Weights {
+    pub fn value_index(phase: usize, piece_index: usize) -> usize {
+        phase * PHASE_PARAMETERS + piece_index
+    }
+
+    pub fn table_index(phase: usize, piece_index: usize, square_index: usize) -> usize {
+        phase * PHASE_PARAMETERS + 6 + piece_index * 64 + square_index
+    }
+
+    pub fn params(&self) -> Vec<i32> {
+        let mut params = vec![0; PARAMETERS];
+        for phase in 0..2 {
+            for piece_index in 0..6 {
+                params[Self::value_index(phase, piece_index)] = self.values[phase][piece_index];
+                for square_index in 0..64 {
+                    params[Self::table_index(phase, piece_index, square_index)] =
+                        self.tables[phase][piece_index][square_index];
+                }
+            }
+        }
+        params
+    }
+
+    pub fn from_params(params: &[i32]) -> Self {
+        let mut weights = Self::default();
+        for phase in 0..2 {
+            for piece_index in 0..6 {
+                weights.values[phase][piece_index] = params[Self::value_index(phase, piece_index)];
+                for square_index in 0..64 {
+                    weights.tables[phase][piece_index][square_index] =
+                        params[Self::table_index(phase, piece_index, square_index)];
+                }
+            }
+        }
+        weights
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("could not read weights {}", path.display()))?;
+
+        let mut params = Self::default().params();
+        let mut section: Option<(usize, usize, usize)> = None;
+        for token in text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .flat_map(str::split_whitespace)
+        {
+            if let Ok(value) = token.parse::<i32>() {
+                match section.as_mut() {
+                    Some((start, len, filled)) if *filled < *len => {
+                        params[*start + *filled] = value;
+                        *filled += 1;
+                    }
+                    Some(_) => bail!("too many values in {}", path.display()),
+                    None => bail!("value outside of a section in {}", path.display()),
+                }
+                continue;
+            }
+
+            if let Some((_, len, filled)) = section {
+                if filled != len {
+                    bail!("incomplete section before {} in {}", token, path.display());
+                }
+            }
+            section = Some(match Self::section(token) {
+                Some((start, len)) => (start, len, 0),
+                None => bail!("unknown section {} in {}", token, path.display()),
+            });
+        }
+
+        if let Some((_, len, filled)) = section {
+            if filled != len {
+                bail!("incomplete last section in {}", path.display());
+            }
+        }
+
+        Ok(Self::from_params(&params))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let params = self.params();
+        let mut text = String::new();
+        for (phase, phase_name) in PHASE_NAMES.iter().enumerate() {
+            text += &format!("{}_values\n", phase_name);
+            for piece_index in 0..6 {
+                text += &format!(" {:4}", params[Self::value_index(phase, piece_index)]);
+            }
+            text += "\n\n";
+
+            for (piece_index, piece_name) in PIECE_NAMES.iter().enumerate() {
+                text += &format!("{}_{}\n", phase_name, piece_name);
+                for rank in 0..8 {
+                    for file in 0..8 {
+                        let index = Self::table_index(phase, piece_index, rank * 8 + file);
+                        text += &format!(" {:4}", params[index]);
+                    }
+                    text += "\n";
+                }
+                text += "\n";
+            }
+        }
+
+        let path = path.as_ref();
+        fs::write(path, text).with_context(|| format!("could not write weights {}", path.display()))
+    }
+
+    fn section(name: &str) -> Option<(usize, usize)> {
+        let (phase_name, rest) = name.split_once('_')?;
+        let phase = PHASE_NAMES.iter().position(|&p| p == phase_name)?;
+        if rest == "values" {
+            return Some((Self::value_index(phase, 0), 6));
+        }
+        let piece_index = PIECE_NAMES.iter().position(|&p| p == rest)?;
+        Some((Self::table_index(phase, piece_index, 0), 64))
+    }
+
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        let mut midgame = 0;
+        let mut endgame = 0;
+        let mut phase = 0;
+
+        for square_index in 0..64 {
+            let piece = board.get_square(square_index);
+            if piece.is_none() {
+                continue;
+            }
+
+            let index = piece.index();
+            let (sign, table_index) = if piece.is_white() {
+                (1, square_index)
+            } else {
+                (-1, square_index ^ 56)
+            };
+
+            midgame += sign * (self.values[0][index] + self.tables[0][index][table_index]);
+            endgame += sign * (self.values[1][index] + self.tables[1][index][table_index]);
+            phase += PHASE_WEIGHTS[index];
+        }
+
+        let phase = phase.min(TOTAL_PHASE);
+        let score = (midgame * phase + endgame * (TOTAL_PHASE - phase)) / TOTAL_PHASE;
+
+        if board.get_current_turn().is_white() {
+            score
+        } else {
+            -score
+        }
+    }
+}
+
+pub fn weights_file() -> Option<PathBuf> {
+    find_folder::Search::ParentsThenKids(3, 3)
+        .for_folder("assets")
+        .ok()
+        .map(|assets| assets.join("weights.txt"))
+}
+
+pub fn set_weights(weights: Weights) {
+    WEIGHTS.get_or_init(|| weights);
+}
+
+pub fn weights() -> &'static Weights {
+    WEIGHTS.get_or_init(Weights::default)
+}
+
+pub fn phase_weight(piece_index: usize) -> i32 {
+    PHASE_WEIGHTS[piece_index]
+}
+
+pub fn piece_value(piece: Piece) -> i32 {
+    weights().values[0][piece.index()]
+}
+
+pub fn evaluate(board: &Board) -> i32 {
+    weights().evaluate(board)
+}
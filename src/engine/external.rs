@@ -0,0 +1,305 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::board::{Board, Move};
+
+use super::search::{Limits, MATE};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct External {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    score: Option<i32>,
+}
+
+impl External {
+    pub fn spawn(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("could not start engine {}", path.display()))?;
+
+        let stdin = child.stdin.take().context("engine has no stdin")?;
+        let stdout = child.stdout.take().context("engine has no stdout")?;
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut external = Self {
+            name: path.display().to_string(),
+            child,
+            stdin,
+            lines,
+            score: None,
+        };
+
+        external.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let line = external.receive(deadline)?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                external.name = name.trim().to_string();
+            } else if line.trim() == "uciok" {
+                break;
+            }
+        }
+        external.new_game()?;
+
+        Ok(external)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn score(&self) -> Option<i32> {
+        self.score
+    }
+
+    pub fn new_game(&mut self) -> Result<()> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        while self.receive(deadline)?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    pub fn think_until(
+        &mut self,
+        board: &Board,
+        limits: Limits,
+        stop: &AtomicBool,
+    ) -> Result<Option<Move>> {
+        self.send(&position(board))?;
+
+        let mut go = String::from("go");
+        if let Some(depth) = limits.depth {
+            go.push_str(&format!(" depth {}", depth));
+        }
+        if let Some(nodes) = limits.nodes {
+            go.push_str(&format!(" nodes {}", nodes));
+        }
+        if let Some(movetime) = limits.movetime {
+            go.push_str(&format!(" movetime {}", movetime.as_millis()));
+        }
+        if go == "go" {
+            go.push_str(" infinite");
+        }
+        self.send(&go)?;
+        self.score = None;
+
+        let mut stopped = false;
+        let line = loop {
+            match self.lines.recv_timeout(POLL_INTERVAL) {
+                Ok(line) if line.starts_with("bestmove") => break line,
+                Ok(line) => self.score = parse_score(&line).or(self.score),
+                Err(RecvTimeoutError::Timeout) => {
+                    if !stopped && stop.load(Ordering::Relaxed) {
+                        self.send("stop")?;
+                        stopped = true;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => bail!("{} exited", self.name),
+            }
+        };
+
+        let name = line
+            .split_whitespace()
+            .nth(1)
+            .ok_or_else(|| anyhow!("{} sent an empty bestmove", self.name))?;
+        let moves = board.clone().get_all_moves();
+        if name == "0000" || name == "(none)" {
+            if !moves.is_empty() {
+                bail!("{} did not return a move", self.name);
+            }
+            return Ok(None);
+        }
+
+        let m: Move = name.parse()?;
+        if !moves.contains(&m) {
+            bail!("{} played an illegal move: {}", self.name, name);
+        }
+        Ok(Some(m))
+    }
+
+    fn send(&mut self, command: &str) -> Result<()> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .with_context(|| format!("could not send {} to {}", command, self.name))
+    }
+
+    fn receive(&mut self, deadline: Instant) -> Result<String> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => bail!("{} is not responding", self.name),
+            Err(RecvTimeoutError::Disconnected) => bail!("{} exited", self.name),
+        }
+    }
+}
+
+fn parse_score(line: &str) -> Option<i32> {
+    let mut tokens = line.split_whitespace();
+    if tokens.next() != Some("info")
+        || (line.contains(" multipv ") && !line.contains(" multipv 1 "))
+    {
+        return None;
+    }
+
+    tokens.find(|&token| token == "score")?;
+    let kind = tokens.next()?;
+    let value: i32 = tokens.next()?.parse().ok()?;
+    match kind {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(MATE - 2 * value + 1),
+        "mate" => Some(-MATE - 2 * value),
+        _ => None,
+    }
+}
+
+// sends the whole game so the engine can see repetitions
+fn position(board: &Board) -> String {
+    let mut position = match board.get_start_fen() {
+        Some(fen) => format!("position fen {}", fen),
+        None => String::from("position startpos"),
+    };
+    let moves = board.get_played_moves();
+    if !moves.is_empty() {
+        position.push_str(" moves");
+        for m in moves {
+            position.push_str(&format!(" {}", m));
+        }
+    }
+    position
+}
+
+impl Drop for External {
+    fn drop(&mut self) {
+        if self.send("quit").is_ok() {
+            let deadline = Instant::now() + QUIT_TIMEOUT;
+            while Instant::now() < deadline {
+                if let Ok(Some(_)) = self.child.try_wait() {
+                    return;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, os::unix::fs::PermissionsExt, path::PathBuf};
+
+    use super::*;
+    use crate::piece::Piece;
+
+    const MOCK: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/engines/mock-uci.sh");
+
+    fn mock(name: &str, moves: &str) -> (External, PathBuf) {
+        let path =
+            env::temp_dir().join(format!("chess-ai-mock-{}-{}.sh", name, std::process::id()));
+        fs::write(
+            &path,
+            format!("#!/bin/sh\nMOCK_MOVES='{}' exec {}\n", moves, MOCK),
+        )
+        .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        (External::spawn(&path).unwrap(), path)
+    }
+
+    fn think(external: &mut External, fen: &str) -> Result<Option<Move>> {
+        let board = Board::from_fen(fen).unwrap();
+        external.think_until(&board, Limits::depth(1), &AtomicBool::new(false))
+    }
+
+    #[test]
+    fn handshake_reads_the_engine_name() {
+        let (external, path) = mock("handshake", "e2e4");
+        assert_eq!(external.name(), "mock");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn parses_bestmove_and_promotions() {
+        let (mut external, path) = mock("bestmove", "e2e4 e7e8n");
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(
+            think(&mut external, start).unwrap(),
+            Some("e2e4".parse().unwrap())
+        );
+        assert_eq!(
+            think(&mut external, "k7/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap(),
+            Some(Move::new(12, 4).with_promotion(Piece::LeftKnight))
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_illegal_moves() {
+        let (mut external, path) = mock("illegal", "e2e5");
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let err = think(&mut external, start).unwrap_err();
+        assert!(err.to_string().contains("illegal move: e2e5"), "{}", err);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sends_the_moves_played_since_the_start() {
+        let mut board = Board::default();
+        assert_eq!(position(&board), "position startpos");
+        for m in ["e2e4", "e7e5", "g1f3"] {
+            board.make_move(m.parse().unwrap());
+        }
+        assert_eq!(position(&board), "position startpos moves e2e4 e7e5 g1f3");
+
+        let fen = "k7/4P3/8/8/8/8/8/K7 w - - 3 40";
+        let mut board = Board::from_fen(fen).unwrap();
+        board.make_move("e7e8".parse().unwrap());
+        board.make_move("a8b7".parse().unwrap());
+        assert_eq!(
+            position(&board),
+            format!("position fen {} moves e7e8q a8b7", fen)
+        );
+    }
+
+    #[test]
+    fn null_move_only_without_legal_moves() {
+        let (mut external, path) = mock("null", "0000");
+        let mated = "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3";
+        assert_eq!(think(&mut external, mated).unwrap(), None);
+
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let err = think(&mut external, start).unwrap_err();
+        assert!(err.to_string().contains("did not return a move"), "{}", err);
+        fs::remove_file(path).unwrap();
+    }
+}
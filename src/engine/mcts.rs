@@ -0,0 +1,278 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Instant,
+};
+
+use rand::{rngs::ThreadRng, seq::SliceRandom, thread_rng, Rng};
+
+use crate::board::{Board, Move};
+
+use super::{eval::evaluate, picker::is_tactical, search::Limits};
+
+const EXPLORATION: f64 = 1.4;
+const ROLLOUT_DEPTH: usize = 8;
+const CAPTURE_PROBABILITY: f64 = 0.8;
+const EVAL_SCALE: f64 = 400.;
+const DEFAULT_ITERATIONS: u64 = 20_000;
+
+#[derive(Clone)]
+struct Node {
+    m: Option<Move>,
+    key: u64,
+    children: Vec<usize>,
+    untried: Option<Vec<Move>>,
+    visits: u32,
+    score: f64,
+}
+
+impl Node {
+    fn new(m: Option<Move>, key: u64) -> Self {
+        Self {
+            m,
+            key,
+            children: Vec::new(),
+            untried: None,
+            visits: 0,
+            score: 0.,
+        }
+    }
+
+    fn uct(&self, parent_visits: u32) -> f64 {
+        if self.visits == 0 {
+            return f64::INFINITY;
+        }
+
+        self.score / self.visits as f64
+            + EXPLORATION * ((parent_visits as f64).ln() / self.visits as f64).sqrt()
+    }
+}
+
+#[derive(Default)]
+pub struct Mcts {
+    nodes: Vec<Node>,
+    iterations: u64,
+    budget: Option<Limits>,
+}
+
+impl Mcts {
+    pub fn set_budget(&mut self, budget: Limits) {
+        self.budget = Some(budget);
+    }
+
+    pub fn think_until(
+        &mut self,
+        board: &Board,
+        limits: Limits,
+        stop: &AtomicBool,
+    ) -> Option<Move> {
+        let start = Instant::now();
+        let mut rng = thread_rng();
+        let limits = self.budget.unwrap_or(limits);
+
+        self.reuse_tree(board.hash());
+        self.iterations = 0;
+
+        let budget = match limits {
+            Limits {
+                nodes: None,
+                movetime: None,
+                ..
+            } => Some(DEFAULT_ITERATIONS),
+            Limits { nodes, .. } => nodes,
+        };
+        while !stop.load(Ordering::Relaxed)
+            && budget.is_none_or(|budget| self.iterations < budget)
+            && limits
+                .movetime
+                .is_none_or(|movetime| start.elapsed() < movetime)
+        {
+            self.iterate(board, &mut rng);
+            self.iterations += 1;
+        }
+
+        self.nodes[0]
+            .children
+            .iter()
+            .max_by_key(|child| self.nodes[**child].visits)
+            .and_then(|child| self.nodes[*child].m)
+    }
+
+    fn iterate(&mut self, board: &Board, rng: &mut ThreadRng) {
+        let mut board = board.clone();
+        let mut path = vec![0];
+        let mut node = 0;
+
+        loop {
+            if self.nodes[node].untried.is_none() {
+                self.nodes[node].untried = Some(legal_moves(&mut board));
+            }
+
+            let untried = self.nodes[node].untried.as_mut().unwrap();
+            if !untried.is_empty() {
+                let m = untried.swap_remove(rng.gen_range(0..untried.len()));
+                board.make_move(m);
+
+                self.nodes.push(Node::new(Some(m), board.hash()));
+                let child = self.nodes.len() - 1;
+                self.nodes[node].children.push(child);
+                path.push(child);
+                break;
+            }
+
+            let parent_visits = self.nodes[node].visits;
+            let Some(child) = self.nodes[node].children.iter().copied().max_by(|a, b| {
+                self.nodes[*a]
+                    .uct(parent_visits)
+                    .total_cmp(&self.nodes[*b].uct(parent_visits))
+            }) else {
+                break;
+            };
+
+            board.make_move(self.nodes[child].m.unwrap());
+            path.push(child);
+            node = child;
+        }
+
+        let mut score = rollout(&mut board, rng);
+        for node in path.into_iter().rev() {
+            score = 1. - score;
+            self.nodes[node].visits += 1;
+            self.nodes[node].score += score;
+        }
+    }
+
+    fn reuse_tree(&mut self, key: u64) {
+        let reusable_root = self.nodes.first().and_then(|root| {
+            root.children
+                .iter()
+                .flat_map(|child| {
+                    let grandchildren = &self.nodes[*child].children;
+                    std::iter::once(child).chain(grandchildren)
+                })
+                .chain(std::iter::once(&0))
+                .find(|node| self.nodes[**node].key == key)
+                .copied()
+        });
+
+        let Some(root) = reusable_root else {
+            self.nodes = vec![Node::new(None, key)];
+            return;
+        };
+
+        let mut nodes = vec![Node {
+            m: None,
+            ..self.nodes[root].clone()
+        }];
+        let mut i = 0;
+        while i < nodes.len() {
+            let children = std::mem::take(&mut nodes[i].children);
+            for child in children {
+                nodes.push(self.nodes[child].clone());
+                let index = nodes.len() - 1;
+                nodes[i].children.push(index);
+            }
+            i += 1;
+        }
+
+        self.nodes = nodes;
+    }
+}
+
+fn legal_moves(board: &mut Board) -> Vec<Move> {
+    let color = board.get_current_turn();
+
+    board
+        .get_pseudo_legal_moves()
+        .into_iter()
+        .filter(|m| {
+            let mut child = board.clone();
+            child.make_move(*m);
+            !child.is_check(color)
+        })
+        .collect()
+}
+
+fn rollout(board: &mut Board, rng: &mut ThreadRng) -> f64 {
+    let color = board.get_current_turn();
+
+    for _ in 0..ROLLOUT_DEPTH {
+        let mut moves = board.get_pseudo_legal_moves();
+        moves.shuffle(rng);
+        if rng.gen_bool(CAPTURE_PROBABILITY) {
+            moves.sort_by_key(|m| !is_tactical(board, *m));
+        }
+
+        let turn = board.get_current_turn();
+        let legal_move = moves.into_iter().find_map(|m| {
+            let mut child = board.clone();
+            child.make_move(m);
+            (!child.is_check(turn)).then_some(child)
+        });
+
+        match legal_move {
+            Some(child) => *board = child,
+            None => {
+                let score = if board.is_in_check() { 0. } else { 0.5 };
+                return if turn == color { score } else { 1. - score };
+            }
+        }
+    }
+
+    let score = 1. / (1. + (-evaluate(board) as f64 / EVAL_SCALE).exp());
+    if board.get_current_turn() == color {
+        score
+    } else {
+        1. - score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn iterations(nodes: u64) -> Limits {
+        Limits {
+            nodes: Some(nodes),
+            ..Limits::default()
+        }
+    }
+
+    fn most_visited(mcts: &Mcts, node: usize) -> usize {
+        *mcts.nodes[node]
+            .children
+            .iter()
+            .max_by_key(|child| mcts.nodes[**child].visits)
+            .unwrap()
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut mcts = Mcts::default();
+        let best_move = mcts.think_until(&board, iterations(2000), &AtomicBool::new(false));
+        assert_eq!(best_move, Some("a1a8".parse().unwrap()));
+    }
+
+    #[test]
+    fn reuses_the_tree_after_two_plies() {
+        let stop = AtomicBool::new(false);
+        let mut board = Board::default();
+        let mut mcts = Mcts::default();
+        mcts.think_until(&board, iterations(2000), &stop);
+
+        let child = most_visited(&mcts, 0);
+        let grandchild = most_visited(&mcts, child);
+        let visits = mcts.nodes[grandchild].visits;
+        assert!(visits > 0);
+        board.make_move(mcts.nodes[child].m.unwrap());
+        board.make_move(mcts.nodes[grandchild].m.unwrap());
+
+        mcts.think_until(&board, iterations(100), &stop);
+        assert_eq!(mcts.nodes[0].key, board.hash());
+        assert_eq!(mcts.nodes[0].visits, visits + 100);
+
+        let elsewhere = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        mcts.think_until(&elsewhere, iterations(10), &stop);
+        assert_eq!(mcts.nodes[0].visits, 10);
+    }
+}
@@ -0,0 +1,15 @@
+pub mod book;
+pub mod computer;
+pub mod eval;
+pub mod external;
+pub mod mcts;
+pub mod nnue;
+pub mod ordering;
+pub mod personality;
+pub mod picker;
+pub mod search;
+pub mod strength;
+pub mod syzygy;
+pub mod tablebase;
+pub mod tt;
+pub mod zobrist;
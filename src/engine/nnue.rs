@@ -0,0 +1,328 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{board::Board, piece::Piece};
+
+use super::eval::Evaluator;
+
+pub const HIDDEN: usize = 128;
+
+const MAGIC: &[u8; 4] = b"CANN";
+const VERSION: u32 = 1;
+const FEATURES: usize = 768;
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+pub struct Network {
+    feature_weights: Vec<[i16; HIDDEN]>,
+    feature_bias: [i16; HIDDEN],
+    output_weights: [[i16; HIDDEN]; 2],
+    output_bias: i16,
+}
+
+impl Network {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut reader = BufReader::new(
+            File::open(path).with_context(|| format!("cannot open {}", path.display()))?,
+        );
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("{} is not a network file", path.display());
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            bail!(
+                "unsupported network version {} in {} (expected {})",
+                version,
+                path.display(),
+                VERSION
+            );
+        }
+
+        let hidden = read_u32(&mut reader)? as usize;
+        if hidden != HIDDEN {
+            bail!(
+                "unsupported hidden layer size {} in {} (expected {})",
+                hidden,
+                path.display(),
+                HIDDEN
+            );
+        }
+
+        let mut feature_weights = vec![[0; HIDDEN]; FEATURES];
+        for weights in feature_weights.iter_mut() {
+            read_i16s(&mut reader, weights)?;
+        }
+        let mut feature_bias = [0; HIDDEN];
+        read_i16s(&mut reader, &mut feature_bias)?;
+        let mut output_weights = [[0; HIDDEN]; 2];
+        for weights in output_weights.iter_mut() {
+            read_i16s(&mut reader, weights)?;
+        }
+        let mut output_bias = [0];
+        read_i16s(&mut reader, &mut output_bias)?;
+
+        if output_weights
+            .iter()
+            .flatten()
+            .any(|weight| weight.abs() > 127)
+        {
+            bail!(
+                "output weights in {} exceed the supported range",
+                path.display()
+            );
+        }
+
+        if reader.read(&mut [0])? != 0 {
+            bail!("trailing data in {}", path.display());
+        }
+
+        Ok(Self {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias: output_bias[0],
+        })
+    }
+
+    fn output(&self, us: &[i16; HIDDEN], them: &[i16; HIDDEN]) -> i32 {
+        let sum = forward(us, &self.output_weights[0]) + forward(them, &self.output_weights[1]);
+
+        (sum / QA + self.output_bias as i32) * SCALE / (QA * QB)
+    }
+}
+
+#[derive(Clone)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    values: [[i16; HIDDEN]; 2],
+}
+
+impl Accumulator {
+    pub fn new(network: Arc<Network>, board: &Board) -> Self {
+        let mut accumulator = Self {
+            values: [network.feature_bias; 2],
+            network,
+        };
+
+        for square_index in 0..64 {
+            let piece = board.get_square(square_index);
+            if !piece.is_none() {
+                accumulator.add(piece, square_index);
+            }
+        }
+
+        accumulator
+    }
+
+    pub fn add(&mut self, piece: Piece, square_index: usize) {
+        for (perspective, values) in self.values.iter_mut().enumerate() {
+            let weights = &self.network.feature_weights[feature(perspective, piece, square_index)];
+            for (value, weight) in values.iter_mut().zip(weights) {
+                *value = value.wrapping_add(*weight);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, piece: Piece, square_index: usize) {
+        for (perspective, values) in self.values.iter_mut().enumerate() {
+            let weights = &self.network.feature_weights[feature(perspective, piece, square_index)];
+            for (value, weight) in values.iter_mut().zip(weights) {
+                *value = value.wrapping_sub(*weight);
+            }
+        }
+    }
+
+    fn evaluate(&self, color: Piece) -> i32 {
+        let [white, black] = &self.values;
+
+        if color.is_white() {
+            self.network.output(white, black)
+        } else {
+            self.network.output(black, white)
+        }
+    }
+}
+
+pub struct Nnue {
+    network: Arc<Network>,
+}
+
+impl Nnue {
+    pub fn new(network: Network) -> Self {
+        Self {
+            network: Arc::new(network),
+        }
+    }
+}
+
+impl Evaluator for Nnue {
+    fn prepare(&self, board: &mut Board) {
+        board.set_accumulator(Some(Accumulator::new(self.network.clone(), board)));
+    }
+
+    fn evaluate(&self, board: &Board) -> i32 {
+        match board.get_accumulator() {
+            Some(accumulator) if Arc::ptr_eq(&accumulator.network, &self.network) => {
+                accumulator.evaluate(board.get_current_turn())
+            }
+            _ => Accumulator::new(self.network.clone(), board).evaluate(board.get_current_turn()),
+        }
+    }
+}
+
+// features are seen from each side's perspective: own pieces first, and squares
+// flipped for black so that both sides share the same weights
+fn feature(perspective: usize, piece: Piece, square_index: usize) -> usize {
+    let (side, square_index) = if perspective == 0 {
+        (!piece.is_white() as usize, square_index ^ 56)
+    } else {
+        (!piece.is_black() as usize, square_index)
+    };
+
+    side * 384 + piece.index() * 64 + square_index
+}
+
+fn forward(values: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        return unsafe { forward_avx2(values, weights) };
+    }
+
+    forward_scalar(values, weights)
+}
+
+fn forward_scalar(values: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    values
+        .iter()
+        .zip(weights)
+        .map(|(value, weight)| {
+            let value = (*value as i32).clamp(0, QA);
+            value * value * *weight as i32
+        })
+        .sum::<i32>()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn forward_avx2(values: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    use std::arch::x86_64::*;
+
+    let zero = _mm256_setzero_si256();
+    let max = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+
+    for i in (0..HIDDEN).step_by(16) {
+        let value = _mm256_loadu_si256(values.as_ptr().add(i) as *const __m256i);
+        let weight = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+
+        let value = _mm256_min_epi16(_mm256_max_epi16(value, zero), max);
+        let weighted = _mm256_mullo_epi16(value, weight);
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(weighted, value));
+    }
+
+    let sum = _mm_add_epi32(
+        _mm256_castsi256_si128(sum),
+        _mm256_extracti128_si256(sum, 1),
+    );
+    let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b01_00_11_10));
+    let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b10_11_00_01));
+
+    _mm_cvtsi128_si32(sum)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_i16s<R: Read>(reader: &mut R, values: &mut [i16]) -> Result<()> {
+    let mut bytes = vec![0; values.len() * 2];
+    reader.read_exact(&mut bytes)?;
+
+    for (value, bytes) in values.iter_mut().zip(bytes.chunks_exact(2)) {
+        *value = i16::from_le_bytes([bytes[0], bytes[1]]);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{thread_rng, Rng};
+
+    use super::*;
+
+    fn random_network() -> Network {
+        let mut rng = thread_rng();
+        let mut feature_weights = vec![[0; HIDDEN]; FEATURES];
+        for weights in feature_weights.iter_mut() {
+            rng.fill(&mut weights[..]);
+        }
+        let mut output_weights = [[0; HIDDEN]; 2];
+        for weight in output_weights.iter_mut().flatten() {
+            *weight = rng.gen_range(-127..=127);
+        }
+
+        Network {
+            feature_weights,
+            feature_bias: [i16::MAX; HIDDEN],
+            output_weights,
+            output_bias: rng.gen(),
+        }
+    }
+
+    #[test]
+    fn accumulator_updates_are_reversible() {
+        let network = Arc::new(random_network());
+        let board = Board::default();
+        let mut accumulator = Accumulator::new(network.clone(), &board);
+        let values = accumulator.values;
+
+        accumulator.add(Piece::WhiteQueen, 36);
+        accumulator.remove(Piece::WhiteQueen, 36);
+        assert_eq!(accumulator.values, values);
+
+        accumulator.remove(Piece::WhitePawn, 52);
+        accumulator.add(Piece::WhitePawn, 36);
+        let mut moved = board.clone();
+        moved.make_move(crate::board::Move::new(52, 36));
+        assert_eq!(accumulator.values, Accumulator::new(network, &moved).values);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_matches_scalar() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        let mut rng = thread_rng();
+        for _ in 0..1000 {
+            let mut values = [0; HIDDEN];
+            rng.fill(&mut values[..]);
+            let mut weights = [0; HIDDEN];
+            for weight in weights.iter_mut() {
+                *weight = rng.gen_range(-127..=127);
+            }
+
+            assert_eq!(
+                unsafe { forward_avx2(&values, &weights) },
+                forward_scalar(&values, &weights)
+            );
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::board::{Board, Move};
+
+use super::search::MAX_PLY;
+
+const HISTORY_MAX: i32 = 16384;
+
+#[derive(Clone)]
+pub struct Heuristics {
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    history: Box<[[[i32; 64]; 64]; 2]>,
+    countermoves: Box<[[Option<Move>; 64]; 32]>,
+}
+
+impl Heuristics {
+    pub fn killers(&self, ply: usize) -> [Option<Move>; 2] {
+        self.killers[ply.min(MAX_PLY - 1)]
+    }
+
+    pub fn countermove(&self, board: &Board, previous: Option<Move>) -> Option<Move> {
+        previous.and_then(|previous| {
+            self.countermoves[board.get_square(previous.to) as usize][previous.to]
+        })
+    }
+
+    pub fn history(&self, board: &Board, m: Move) -> i32 {
+        self.history[Self::side(board)][m.from][m.to]
+    }
+
+    pub fn update(
+        &mut self,
+        board: &Board,
+        best_move: Move,
+        quiets_tried: &[Move],
+        depth: i32,
+        ply: usize,
+        previous: Option<Move>,
+    ) {
+        let killers = &mut self.killers[ply.min(MAX_PLY - 1)];
+        if killers[0] != Some(best_move) {
+            killers[1] = killers[0];
+            killers[0] = Some(best_move);
+        }
+
+        if let Some(previous) = previous {
+            self.countermoves[board.get_square(previous.to) as usize][previous.to] =
+                Some(best_move);
+        }
+
+        let bonus = (depth * depth).min(HISTORY_MAX);
+        let side = Self::side(board);
+        Self::apply_bonus(&mut self.history[side][best_move.from][best_move.to], bonus);
+        for m in quiets_tried.iter().filter(|m| **m != best_move) {
+            Self::apply_bonus(&mut self.history[side][m.from][m.to], -bonus);
+        }
+    }
+
+    pub fn age(&mut self) {
+        self.killers = [[None; 2]; MAX_PLY];
+        self.history
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|score| *score /= 2);
+    }
+
+    fn apply_bonus(score: &mut i32, bonus: i32) {
+        *score += bonus - *score * bonus.abs() / HISTORY_MAX;
+    }
+
+    fn side(board: &Board) -> usize {
+        if board.get_current_turn().is_white() {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Self {
+            killers: [[None; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            countermoves: Box::new([[None; 64]; 32]),
+        }
+    }
+}
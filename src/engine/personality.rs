@@ -0,0 +1,190 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{board::Board, piece::Piece};
+
+use super::eval::{self, Evaluator};
+
+static PERSONALITIES: OnceLock<Vec<Personality>> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Personality {
+    name: String,
+    material: i32,
+    mobility: i32,
+    king_attack: i32,
+    contempt: i32,
+    book_depth: Option<usize>,
+    book_variety: Option<f64>,
+}
+
+impl Personality {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            material: 100,
+            mobility: 0,
+            king_attack: 0,
+            contempt: 0,
+            book_depth: None,
+            book_variety: None,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .with_context(|| format!("invalid personality file name {}", path.display()))?;
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("could not read personality {}", path.display()))?;
+
+        let mut personality = Self::new(name);
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = match line.split_once(char::is_whitespace) {
+                Some((key, value)) => (key, value.trim()),
+                None => bail!("missing value for {} in {}", line, path.display()),
+            };
+            let invalid = || format!("invalid value {} for {} in {}", value, key, path.display());
+            match key {
+                "material" => personality.material = value.parse().with_context(invalid)?,
+                "mobility" => personality.mobility = value.parse().with_context(invalid)?,
+                "king_attack" => personality.king_attack = value.parse().with_context(invalid)?,
+                "contempt" => personality.contempt = value.parse().with_context(invalid)?,
+                "book_depth" => personality.book_depth = Some(value.parse().with_context(invalid)?),
+                "book_variety" => {
+                    personality.book_variety = Some(value.parse().with_context(invalid)?)
+                }
+                _ => bail!("unknown setting {} in {}", key, path.display()),
+            }
+        }
+
+        Ok(personality)
+    }
+
+    pub fn load_all(dir: impl AsRef<Path>) -> Result<Vec<Self>> {
+        let dir = dir.as_ref();
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .with_context(|| format!("could not read personalities in {}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+            .collect();
+        paths.sort();
+
+        paths.iter().map(Self::load).collect()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn contempt(&self) -> i32 {
+        self.contempt
+    }
+
+    pub fn book_depth(&self) -> Option<usize> {
+        self.book_depth
+    }
+
+    pub fn book_variety(&self) -> Option<f64> {
+        self.book_variety
+    }
+
+    pub fn adjust(&self, board: &Board) -> i32 {
+        let mut material = 0;
+        let mut mobility = 0;
+        let mut king_attack = 0;
+
+        let kings = [Piece::White, Piece::Black].map(|color| {
+            (0..64)
+                .find(|&square_index| board.get_square(square_index) == Piece::King | color)
+                .unwrap_or(0)
+        });
+
+        for square_index in 0..64 {
+            let piece = board.get_square(square_index);
+            if piece.is_none() {
+                continue;
+            }
+
+            let (sign, enemy_king) = if piece.is_white() {
+                (1, kings[1])
+            } else {
+                (-1, kings[0])
+            };
+            material += sign * eval::piece_value(piece);
+
+            if matches!(piece.split().0, Piece::Pawn | Piece::King) {
+                continue;
+            }
+            for [x, y] in piece.legal_moves([square_index % 8, square_index / 8], board) {
+                mobility += sign;
+                if x.abs_diff(enemy_king % 8) <= 1 && y.abs_diff(enemy_king / 8) <= 1 {
+                    king_attack += sign;
+                }
+            }
+        }
+
+        let score = material * (self.material - 100) / 100
+            + mobility * self.mobility
+            + king_attack * self.king_attack;
+        if board.get_current_turn().is_white() {
+            score
+        } else {
+            -score
+        }
+    }
+}
+
+pub struct Styled<'a> {
+    base: &'a dyn Evaluator,
+    personality: &'a Personality,
+}
+
+impl<'a> Styled<'a> {
+    pub fn new(base: &'a dyn Evaluator, personality: &'a Personality) -> Self {
+        Self { base, personality }
+    }
+}
+
+impl Evaluator for Styled<'_> {
+    fn prepare(&self, board: &mut Board) {
+        self.base.prepare(board);
+    }
+
+    fn evaluate(&self, board: &Board) -> i32 {
+        self.base.evaluate(board) + self.personality.adjust(board)
+    }
+}
+
+pub fn personalities_dir() -> Option<PathBuf> {
+    find_folder::Search::ParentsThenKids(3, 3)
+        .for_folder("assets")
+        .ok()
+        .map(|assets| assets.join("personalities"))
+}
+
+pub fn set_personalities(personalities: Vec<Personality>) {
+    PERSONALITIES.get_or_init(|| personalities);
+}
+
+pub fn personalities() -> &'static [Personality] {
+    PERSONALITIES.get_or_init(Vec::new)
+}
+
+pub fn find(name: &str) -> Option<&'static Personality> {
+    personalities()
+        .iter()
+        .find(|personality| personality.name == name)
+}
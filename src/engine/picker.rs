@@ -0,0 +1,224 @@
+use crate::{
+    board::{Board, Move},
+    piece::Piece,
+};
+
+use super::{eval::piece_value, ordering::Heuristics};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    HashMove,
+    GenerateCaptures,
+    Captures,
+    Killers,
+    Countermove,
+    GenerateQuiets,
+    Quiets,
+    Done,
+}
+
+pub struct MovePicker {
+    stage: Stage,
+    captures_only: bool,
+    hash_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    killer_index: usize,
+    countermove: Option<Move>,
+    captures: Vec<(Move, i32)>,
+    quiets: Vec<(Move, i32)>,
+}
+
+impl MovePicker {
+    pub fn new(
+        hash_move: Option<Move>,
+        killers: [Option<Move>; 2],
+        countermove: Option<Move>,
+    ) -> Self {
+        Self {
+            stage: Stage::HashMove,
+            captures_only: false,
+            hash_move,
+            killers,
+            killer_index: 0,
+            countermove,
+            captures: Vec::new(),
+            quiets: Vec::new(),
+        }
+    }
+
+    pub fn captures(hash_move: Option<Move>) -> Self {
+        Self {
+            captures_only: true,
+            ..Self::new(hash_move, [None; 2], None)
+        }
+    }
+
+    pub fn next(&mut self, board: &mut Board, heuristics: &Heuristics) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenerateCaptures;
+
+                    if let Some(hash_move) = self.hash_move {
+                        if board.is_pseudo_legal(hash_move)
+                            && (!self.captures_only || is_tactical(board, hash_move))
+                        {
+                            return Some(hash_move);
+                        }
+                    }
+                    self.hash_move = None;
+                }
+                Stage::GenerateCaptures => {
+                    for m in board.get_pseudo_legal_moves() {
+                        if is_tactical(board, m) {
+                            self.captures.push((m, mvv_lva(board, m)));
+                        }
+                    }
+                    self.stage = Stage::Captures;
+                }
+                Stage::Captures => match pick_best(&mut self.captures) {
+                    Some(m) if Some(m) == self.hash_move => (),
+                    Some(m) => return Some(m),
+                    None if self.captures_only => self.stage = Stage::Done,
+                    None => self.stage = Stage::Killers,
+                },
+                Stage::Killers => {
+                    if self.killer_index == self.killers.len() {
+                        self.stage = Stage::Countermove;
+                        continue;
+                    }
+
+                    let killer = self.killers[self.killer_index];
+                    self.killer_index += 1;
+                    if killer != self.hash_move && is_quiet(board, killer) {
+                        return killer;
+                    }
+                }
+                Stage::Countermove => {
+                    self.stage = Stage::GenerateQuiets;
+
+                    let countermove = self.countermove;
+                    if countermove != self.hash_move
+                        && !self.killers.contains(&countermove)
+                        && is_quiet(board, countermove)
+                    {
+                        return countermove;
+                    }
+                }
+                Stage::GenerateQuiets => {
+                    for m in board.get_pseudo_legal_moves() {
+                        if is_tactical(board, m) {
+                            continue;
+                        }
+                        let score = if m.promotion.is_none() {
+                            heuristics.history(board, m)
+                        } else {
+                            i32::MIN
+                        };
+                        self.quiets.push((m, score));
+                    }
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match pick_best(&mut self.quiets) {
+                    Some(m)
+                        if Some(m) == self.hash_move
+                            || self.killers.contains(&Some(m))
+                            || Some(m) == self.countermove => {}
+                    Some(m) => return Some(m),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+fn is_quiet(board: &mut Board, m: Option<Move>) -> bool {
+    m.is_some_and(|m| board.is_pseudo_legal(m) && !is_tactical(board, m))
+}
+
+pub fn is_tactical(board: &Board, m: Move) -> bool {
+    board.is_capture(m) || m.promotion == Piece::Queen
+}
+
+fn mvv_lva(board: &Board, m: Move) -> i32 {
+    let victim = board.get_square(m.to);
+    let victim_value = if victim.is_none() {
+        if m.promotion.is_none() {
+            piece_value(Piece::Pawn)
+        } else {
+            0
+        }
+    } else {
+        piece_value(victim)
+    };
+    let promotion_value = if m.promotion.is_none() {
+        0
+    } else {
+        piece_value(m.promotion)
+    };
+
+    (victim_value + promotion_value) * 16 - piece_value(board.get_square(m.from))
+}
+
+fn pick_best(moves: &mut Vec<(Move, i32)>) -> Option<Move> {
+    let best = moves
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (_, score))| *score)
+        .map(|(i, _)| i)?;
+
+    Some(moves.swap_remove(best).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(names: &[&str]) -> Vec<Move> {
+        names.iter().map(|name| name.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn picks_moves_in_stage_order() {
+        let mut board = Board::from_fen("4k3/8/8/3q1r2/4P3/2p5/8/1N2K3 w - - 0 1").unwrap();
+        let mut heuristics = Heuristics::default();
+        for (name, depth) in [("e1e2", 8), ("b1d2", 4)] {
+            heuristics.update(&board, name.parse().unwrap(), &[], depth, 0, None);
+        }
+
+        let [hash_move, killer, other_killer, countermove] =
+            ["e1f2", "b1a3", "e1d1", "e1f1"].map(|name| name.parse::<Move>().unwrap());
+        let mut picker = MovePicker::new(
+            Some(hash_move),
+            [Some(killer), Some(other_killer)],
+            Some(countermove),
+        );
+        let mut picked = Vec::new();
+        while let Some(m) = picker.next(&mut board, &heuristics) {
+            picked.push(m);
+        }
+
+        let expected = moves(&[
+            "e1f2", "e4d5", "e4f5", "b1c3", "b1a3", "e1d1", "e1f1", "e1e2", "b1d2",
+        ]);
+        assert_eq!(picked[..expected.len()], expected);
+
+        let mut all = board.get_pseudo_legal_moves();
+        all.sort_by_key(|m| (m.from, m.to));
+        picked.sort_by_key(|m| (m.from, m.to));
+        assert_eq!(picked, all);
+    }
+
+    #[test]
+    fn captures_only_skips_quiets() {
+        let mut board = Board::from_fen("4k3/8/8/3q1r2/4P3/2p5/8/1N2K3 w - - 0 1").unwrap();
+        let heuristics = Heuristics::default();
+        let mut picker = MovePicker::captures("e1f2".parse().ok());
+        let mut picked = Vec::new();
+        while let Some(m) = picker.next(&mut board, &heuristics) {
+            picked.push(m);
+        }
+        assert_eq!(picked, moves(&["e4d5", "e4f5", "b1c3"]));
+    }
+}
@@ -0,0 +1,973 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::board::{Board, Move};
+
+use super::{
+    eval::{Evaluator, Handcrafted},
+    ordering::Heuristics,
+    personality::{Personality, Styled},
+    picker::{is_tactical, MovePicker},
+    strength::Strength,
+    syzygy::Syzygy,
+    tablebase::{Tablebase, Wdl, MAX_PIECES},
+    tt::{score_from_tt, score_to_tt, Bound, Entry, TranspositionTable},
+};
+
+pub const INFINITY: i32 = 32000;
+pub const MATE: i32 = 31000;
+pub const MATE_BOUND: i32 = MATE - 1000;
+pub const MAX_PLY: usize = 64;
+pub const TB_WIN: i32 = MATE_BOUND - 1000;
+pub const DEFAULT_HASH_MB: usize = 16;
+
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+const MOVES_TO_GO: u32 = 30;
+const REVERSE_FUTILITY_MARGIN: i32 = 80;
+const FUTILITY_MARGINS: [i32; 4] = [0, 120, 220, 320];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub futility_pruning: bool,
+    pub reverse_futility_pruning: bool,
+    pub check_extensions: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            null_move: true,
+            late_move_reductions: true,
+            futility_pruning: true,
+            reverse_futility_pruning: true,
+            check_extensions: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+}
+
+impl Limits {
+    pub fn depth(depth: i32) -> Self {
+        Self {
+            depth: Some(depth),
+            ..Self::default()
+        }
+    }
+
+    pub fn movetime(movetime: Duration) -> Self {
+        Self {
+            movetime: Some(movetime),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub multipv: usize,
+    pub depth: i32,
+    pub score: i32,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: Vec<Move>,
+}
+
+impl Line {
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score.abs() < MATE_BOUND {
+            return None;
+        }
+
+        let moves = (MATE - self.score.abs() + 1) / 2;
+        Some(if self.score > 0 { moves } else { -moves })
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "depth {} multipv {} score ", self.depth, self.multipv)?;
+        match self.mate_in() {
+            Some(moves) => write!(f, "mate {}", moves)?,
+            None => write!(f, "cp {}", self.score)?,
+        }
+
+        write!(
+            f,
+            " nodes {} nps {} time {} pv",
+            self.nodes,
+            (self.nodes as f64 / self.time.as_secs_f64().max(0.001)) as u64,
+            self.time.as_millis()
+        )?;
+        for m in &self.pv {
+            write!(f, " {}", m)?;
+        }
+        Ok(())
+    }
+}
+
+type Report = dyn Fn(&[Line]) + Send + Sync;
+pub type Reporter = Arc<Report>;
+
+pub struct Search {
+    options: Options,
+    evaluator: Arc<dyn Evaluator>,
+    tablebase: Option<Arc<Tablebase>>,
+    syzygy: Option<Arc<Syzygy>>,
+    strength: Strength,
+    personality: Option<&'static Personality>,
+    multi_pv: usize,
+    reporter: Option<Reporter>,
+    threads: usize,
+    hash_mb: usize,
+    tt: Arc<TranspositionTable>,
+    heuristics: Vec<Heuristics>,
+    nodes: u64,
+    depth: i32,
+}
+
+impl Search {
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    pub fn set_evaluator(&mut self, evaluator: Arc<dyn Evaluator>) {
+        self.evaluator = evaluator;
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Arc<Tablebase>) {
+        self.tablebase = Some(tablebase);
+    }
+
+    pub fn set_syzygy(&mut self, syzygy: Arc<Syzygy>) {
+        self.syzygy = Some(syzygy);
+    }
+
+    pub fn set_strength(&mut self, strength: Strength) {
+        self.strength = strength;
+    }
+
+    pub fn set_personality(&mut self, personality: Option<&'static Personality>) {
+        self.personality = personality;
+    }
+
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv.max(1);
+    }
+
+    pub fn set_reporter(&mut self, reporter: Reporter) {
+        self.reporter = Some(reporter);
+    }
+
+    pub fn set_hash(&mut self, hash_mb: usize) {
+        self.hash_mb = hash_mb.max(1);
+        self.tt = Arc::default();
+    }
+
+    pub fn clear(&mut self) {
+        self.tt = Arc::default();
+        self.heuristics.clear();
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    pub fn depth(&self) -> i32 {
+        self.depth
+    }
+
+    pub fn ponder_move(&self, board: &Board, best_move: Move) -> Option<Move> {
+        let mut board = board.clone();
+        board.make_move(best_move);
+
+        let ponder_move = self
+            .tt
+            .probe(board.hash() ^ tt_salt(self.personality))?
+            .best_move?;
+        board
+            .get_all_moves()
+            .contains(&ponder_move)
+            .then_some(ponder_move)
+    }
+
+    pub fn think(&mut self, board: &Board, limits: Limits) -> Option<Move> {
+        self.think_until(board, limits, &AtomicBool::new(false))
+    }
+
+    pub fn think_until(
+        &mut self,
+        board: &Board,
+        limits: Limits,
+        stop: &AtomicBool,
+    ) -> Option<Move> {
+        let strength = self.strength;
+        let limits = strength.limit(limits);
+
+        let tablebase_move = self
+            .tablebase
+            .as_ref()
+            .and_then(|tablebase| tablebase.best_move(board).map(|(m, _)| m))
+            .or_else(|| {
+                self.syzygy
+                    .as_ref()
+                    .and_then(|syzygy| syzygy.best_move(board).map(|(m, _)| m))
+            });
+        if let Some(best_move) = tablebase_move.filter(|_| !strength.is_limited()) {
+            self.nodes = 0;
+            self.depth = 0;
+            return Some(best_move);
+        }
+
+        if self.tt.is_empty() {
+            self.tt = Arc::new(TranspositionTable::new(self.hash_mb));
+        }
+        self.heuristics
+            .resize_with(self.threads, Heuristics::default);
+        self.heuristics.iter_mut().for_each(Heuristics::age);
+
+        let start = Instant::now();
+        let shared_nodes = AtomicU64::new(0);
+        let options = self.options;
+        let base = self.evaluator.clone();
+        let styled = self
+            .personality
+            .map(|personality| Styled::new(&*base, personality));
+        let evaluator: &dyn Evaluator = match &styled {
+            Some(styled) => styled,
+            None => &*base,
+        };
+        let contempt = self.personality.map_or(0, Personality::contempt);
+        let multi_pv = self.multi_pv;
+        let reporter = self.reporter.as_deref();
+        let tablebase = self.tablebase.as_deref();
+        let syzygy = self.syzygy.as_deref();
+        let tt = &*self.tt;
+        let seed = rand::random();
+        let salt = tt_salt(self.personality);
+        let scoring_stop = AtomicBool::new(false);
+
+        let mut board = board.clone();
+        evaluator.prepare(&mut board);
+        let board = &board;
+
+        let (main_heuristics, helper_heuristics) = self.heuristics.split_first_mut().unwrap();
+        let (best_move, depth, nodes) = thread::scope(|scope| {
+            let helpers: Vec<_> = helper_heuristics
+                .iter_mut()
+                .enumerate()
+                .map(|(i, heuristics)| {
+                    let mut worker = Worker {
+                        id: i + 1,
+                        options,
+                        evaluator,
+                        tablebase,
+                        syzygy,
+                        strength,
+                        seed,
+                        salt,
+                        contempt,
+                        tt,
+                        heuristics,
+                        limits: Limits::default(),
+                        start,
+                        stop,
+                        shared_nodes: &shared_nodes,
+                        nodes: 0,
+                        stopped: false,
+                        root_best_move: None,
+                        multi_pv: 1,
+                        reporter: None,
+                        excluded: Vec::new(),
+                    };
+                    let board = board.clone();
+
+                    scope.spawn(move || {
+                        worker.iterate(&board);
+                        worker.nodes
+                    })
+                })
+                .collect();
+
+            let mut worker = Worker {
+                id: 0,
+                options,
+                evaluator,
+                tablebase,
+                syzygy,
+                strength,
+                seed,
+                salt,
+                contempt,
+                tt,
+                heuristics: main_heuristics,
+                limits,
+                start,
+                stop,
+                shared_nodes: &shared_nodes,
+                nodes: 0,
+                stopped: false,
+                root_best_move: None,
+                multi_pv,
+                reporter,
+                excluded: Vec::new(),
+            };
+            let (mut best_move, depth) = worker.iterate(board);
+            stop.store(true, Ordering::Relaxed);
+
+            let helper_nodes: u64 = helpers
+                .into_iter()
+                .map(|helper| helper.join().unwrap())
+                .sum();
+
+            if strength.is_limited() {
+                // the scoring pass gets a fresh budget of its own, otherwise it
+                // would run unbounded under depth, node or ponder limits
+                let budget = strength.limit(Limits::default());
+                worker.stop = &scoring_stop;
+                worker.stopped = false;
+                worker.limits = Limits {
+                    nodes: budget
+                        .nodes
+                        .map(|nodes| shared_nodes.load(Ordering::Relaxed) + nodes),
+                    movetime: limits.movetime,
+                    ..Limits::default()
+                };
+                let depth = budget.depth.map_or(depth, |limit| depth.min(limit));
+                let scored = worker.score_root_moves(board, depth.max(1), best_move);
+                best_move = strength.pick(&scored).or(best_move);
+            }
+
+            (best_move, depth, worker.nodes + helper_nodes)
+        });
+
+        self.nodes = nodes;
+        self.depth = depth;
+
+        best_move
+    }
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            options: Options::default(),
+            evaluator: Arc::new(Handcrafted),
+            tablebase: None,
+            syzygy: None,
+            strength: Strength::default(),
+            personality: None,
+            multi_pv: 1,
+            reporter: None,
+            threads: 1,
+            hash_mb: DEFAULT_HASH_MB,
+            tt: Arc::default(),
+            heuristics: Vec::new(),
+            nodes: 0,
+            depth: 0,
+        }
+    }
+}
+
+struct Worker<'a> {
+    id: usize,
+    options: Options,
+    evaluator: &'a dyn Evaluator,
+    tablebase: Option<&'a Tablebase>,
+    syzygy: Option<&'a Syzygy>,
+    strength: Strength,
+    seed: u64,
+    salt: u64,
+    contempt: i32,
+    tt: &'a TranspositionTable,
+    heuristics: &'a mut Heuristics,
+    limits: Limits,
+    start: Instant,
+    stop: &'a AtomicBool,
+    shared_nodes: &'a AtomicU64,
+    nodes: u64,
+    stopped: bool,
+    root_best_move: Option<Move>,
+    multi_pv: usize,
+    reporter: Option<&'a Report>,
+    excluded: Vec<Move>,
+}
+
+impl<'a> Worker<'a> {
+    fn iterate(&mut self, board: &Board) -> (Option<Move>, i32) {
+        let mut best_move = None;
+        let mut completed_depth = 0;
+
+        let first_depth = 1 + (self.id % 2) as i32;
+        for depth in first_depth..=self.limits.depth.unwrap_or(MAX_PLY as i32 - 1) {
+            let mut lines = Vec::new();
+            self.excluded.clear();
+            while lines.len() < self.multi_pv {
+                self.root_best_move = None;
+                let score = self.negamax(&mut board.clone(), depth, 0, -INFINITY, INFINITY, None);
+
+                let root_move = match self.root_best_move {
+                    Some(root_move) => root_move,
+                    None => break,
+                };
+                if lines.is_empty() {
+                    best_move = Some(root_move);
+                }
+                if self.stopped {
+                    break;
+                }
+
+                lines.push(Line {
+                    multipv: lines.len() + 1,
+                    depth,
+                    score,
+                    nodes: self.shared_nodes.load(Ordering::Relaxed) + self.nodes % 1024,
+                    time: self.start.elapsed(),
+                    pv: self.principal_variation(board, root_move, depth),
+                });
+                self.excluded.push(root_move);
+            }
+            self.excluded.clear();
+
+            if self.stopped {
+                break;
+            }
+            completed_depth = depth;
+
+            if let Some(reporter) = self.reporter {
+                reporter(&lines);
+            }
+        }
+
+        (best_move, completed_depth)
+    }
+
+    fn principal_variation(&self, board: &Board, first: Move, depth: i32) -> Vec<Move> {
+        let mut board = board.clone();
+        board.make_move(first);
+
+        let mut pv = vec![first];
+        while pv.len() < depth as usize {
+            match self
+                .tt
+                .probe(board.hash() ^ self.salt)
+                .and_then(|entry| entry.best_move)
+            {
+                Some(m) if board.get_all_moves().contains(&m) => {
+                    board.make_move(m);
+                    pv.push(m);
+                }
+                _ => break,
+            }
+        }
+        pv
+    }
+
+    fn score_root_moves(
+        &mut self,
+        board: &Board,
+        depth: i32,
+        best_move: Option<Move>,
+    ) -> Vec<(Move, i32)> {
+        let mut board = board.clone();
+        let mut moves = board.get_all_moves();
+        if let Some(index) = moves.iter().position(|&m| Some(m) == best_move) {
+            moves[..=index].rotate_right(1);
+        }
+
+        let mut scored = Vec::new();
+        for m in moves {
+            let mut child = board.clone();
+            child.make_move(m);
+            let score = -self.negamax(&mut child, depth - 1, 1, -INFINITY, INFINITY, Some(m));
+            if self.stopped {
+                break;
+            }
+            scored.push((m, score));
+        }
+        scored
+    }
+
+    fn evaluate(&self, board: &Board) -> i32 {
+        let score = self.evaluator.evaluate(board);
+        if self.strength.is_limited() {
+            score + self.strength.noise(board.hash() ^ self.seed)
+        } else {
+            score
+        }
+    }
+
+    fn draw_score(&self, ply: usize) -> i32 {
+        if ply.is_multiple_of(2) {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.nodes.is_multiple_of(1024) {
+            let total_nodes = self.shared_nodes.fetch_add(1024, Ordering::Relaxed) + 1024;
+            let out_of_time = self
+                .limits
+                .movetime
+                .map(|movetime| self.start.elapsed() >= movetime)
+                .unwrap_or(false);
+            let out_of_nodes = self
+                .limits
+                .nodes
+                .map(|nodes| total_nodes >= nodes)
+                .unwrap_or(false);
+
+            if out_of_time || out_of_nodes {
+                self.stop.store(true, Ordering::Relaxed);
+            }
+            self.stopped = self.stop.load(Ordering::Relaxed);
+        }
+
+        self.stopped
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        board: &mut Board,
+        mut depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        previous: Option<Move>,
+    ) -> i32 {
+        let color = board.get_current_turn();
+        let in_check = board.is_in_check();
+        if in_check && self.options.check_extensions {
+            depth += 1;
+        }
+
+        if depth <= 0 || ply >= MAX_PLY {
+            return self.quiescence(board, ply, alpha, beta);
+        }
+
+        self.nodes += 1;
+        if ply > 0 && self.should_stop() {
+            return 0;
+        }
+
+        if ply > 0 && board.piece_count() <= MAX_PIECES {
+            if let Some(probe) = self.tablebase.and_then(|tablebase| tablebase.probe(board)) {
+                let mate_in = ply as i32 + probe.dtm as i32;
+                return match probe.wdl {
+                    Wdl::Win => MATE - mate_in,
+                    Wdl::Loss => -MATE + mate_in,
+                    Wdl::Draw => self.draw_score(ply),
+                };
+            }
+        }
+
+        if let Some(syzygy) = self
+            .syzygy
+            .filter(|syzygy| ply > 0 && board.piece_count() <= syzygy.max_pieces())
+        {
+            if let Some(wdl) = syzygy.probe_wdl(board) {
+                return match wdl {
+                    Wdl::Win => TB_WIN - ply as i32,
+                    Wdl::Loss => -TB_WIN + ply as i32,
+                    Wdl::Draw => self.draw_score(ply),
+                };
+            }
+        }
+
+        let key = board.hash() ^ self.salt;
+        let entry = self.tt.probe(key);
+        if let Some(entry) = entry.filter(|entry| ply > 0 && entry.depth >= depth) {
+            let score = score_from_tt(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => (),
+            }
+        }
+
+        let is_pv = beta - alpha > 1;
+        let static_eval = if in_check {
+            -INFINITY
+        } else {
+            self.evaluate(board)
+        };
+
+        if !is_pv && !in_check && beta.abs() < MATE_BOUND {
+            if self.options.reverse_futility_pruning
+                && depth <= 6
+                && static_eval - REVERSE_FUTILITY_MARGIN * depth >= beta
+            {
+                return static_eval;
+            }
+
+            if self.options.null_move
+                && depth >= 3
+                && previous.is_some()
+                && static_eval >= beta
+                && board.has_non_pawn_material(color)
+            {
+                let reduction = 3 + depth / 6;
+                let mut child = board.clone();
+                child.make_null_move();
+
+                let score = -self.negamax(
+                    &mut child,
+                    depth - reduction,
+                    ply + 1,
+                    -beta,
+                    -beta + 1,
+                    None,
+                );
+                if self.stopped {
+                    return 0;
+                }
+                if score >= beta {
+                    return if score >= MATE_BOUND { beta } else { score };
+                }
+            }
+        }
+
+        let can_futility_prune = self.options.futility_pruning
+            && !is_pv
+            && !in_check
+            && (depth as usize) < FUTILITY_MARGINS.len()
+            && static_eval + FUTILITY_MARGINS[depth as usize] <= alpha;
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut legal_moves = 0;
+        let mut quiets_tried = Vec::new();
+
+        let mut picker = MovePicker::new(
+            entry.and_then(|entry| entry.best_move),
+            self.heuristics.killers(ply),
+            self.heuristics.countermove(board, previous),
+        );
+        while let Some(m) = picker.next(board, self.heuristics) {
+            if ply == 0 && self.excluded.contains(&m) {
+                continue;
+            }
+
+            let mut child = board.clone();
+            child.make_move(m);
+            if child.is_check(color) {
+                continue;
+            }
+            legal_moves += 1;
+
+            let is_quiet = !is_tactical(board, m);
+            let gives_check = child.is_in_check();
+
+            if can_futility_prune && is_quiet && !gives_check && legal_moves > 1 {
+                quiets_tried.push(m);
+                continue;
+            }
+
+            let score = if legal_moves == 1 {
+                -self.negamax(&mut child, depth - 1, ply + 1, -beta, -alpha, Some(m))
+            } else {
+                let reduction = if self.options.late_move_reductions
+                    && depth >= 3
+                    && legal_moves > 3
+                    && is_quiet
+                    && !in_check
+                    && !gives_check
+                {
+                    let reduction = late_move_reduction(depth, legal_moves) - is_pv as i32;
+                    reduction.clamp(0, depth - 2)
+                } else {
+                    0
+                };
+
+                let mut score = -self.negamax(
+                    &mut child,
+                    depth - 1 - reduction,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    Some(m),
+                );
+                if score > alpha && reduction > 0 {
+                    score =
+                        -self.negamax(&mut child, depth - 1, ply + 1, -alpha - 1, -alpha, Some(m));
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(&mut child, depth - 1, ply + 1, -beta, -alpha, Some(m));
+                }
+                score
+            };
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(m);
+                if ply == 0 {
+                    self.root_best_move = Some(m);
+                }
+
+                if score > alpha {
+                    alpha = score;
+                    if alpha >= beta {
+                        if is_quiet {
+                            self.heuristics
+                                .update(board, m, &quiets_tried, depth, ply, previous);
+                        }
+                        break;
+                    }
+                }
+            }
+
+            if is_quiet {
+                quiets_tried.push(m);
+            }
+        }
+
+        if legal_moves == 0 {
+            return if in_check {
+                -MATE + ply as i32
+            } else {
+                self.draw_score(ply)
+            };
+        }
+        if ply == 0 && !self.excluded.is_empty() {
+            return best_score;
+        }
+        self.tt.store(Entry {
+            key,
+            best_move,
+            score: score_to_tt(best_score, ply),
+            depth,
+            bound: if best_score >= beta {
+                Bound::Lower
+            } else if best_score > original_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            },
+        });
+
+        best_score
+    }
+
+    fn quiescence(&mut self, board: &mut Board, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+
+        let stand_pat = self.evaluate(board);
+        if stand_pat >= beta || ply >= MAX_PLY {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let color = board.get_current_turn();
+        let mut picker = MovePicker::captures(None);
+        while let Some(m) = picker.next(board, self.heuristics) {
+            let mut child = board.clone();
+            child.make_move(m);
+            if child.is_check(color) {
+                continue;
+            }
+
+            let score = -self.quiescence(&mut child, ply + 1, -beta, -alpha);
+            if self.stopped {
+                return 0;
+            }
+
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+
+        alpha
+    }
+}
+
+// each personality evaluates differently, so its entries are keyed apart from
+// the others instead of clearing the table whenever the side to move changes
+fn tt_salt(personality: Option<&Personality>) -> u64 {
+    personality.map_or(0, |personality| {
+        personality
+            .name()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+            })
+    })
+}
+
+pub fn allocate_time(
+    time: Duration,
+    opponent: Option<Duration>,
+    increment: Duration,
+    moves_to_go: Option<u32>,
+) -> Duration {
+    let moves = moves_to_go.unwrap_or(MOVES_TO_GO).max(1);
+    let mut budget = time / moves + increment * 3 / 4;
+    // play faster when behind on the clock instead of falling further behind
+    if let Some(opponent) = opponent.filter(|&opponent| opponent > time) {
+        budget = budget.mul_f64((time.as_secs_f64() / opponent.as_secs_f64()).max(0.5));
+    }
+
+    budget
+        .min(time.saturating_sub(MOVE_OVERHEAD))
+        .max(Duration::from_millis(1))
+}
+
+fn late_move_reduction(depth: i32, move_number: usize) -> i32 {
+    static TABLE: OnceLock<[[i32; 64]; MAX_PLY]> = OnceLock::new();
+
+    let table = TABLE.get_or_init(|| {
+        let mut table = [[0; 64]; MAX_PLY];
+        for (depth, row) in table.iter_mut().enumerate().skip(1) {
+            for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+                *reduction = (0.75 + (depth as f64).ln() * (move_number as f64).ln() / 2.25) as i32;
+            }
+        }
+        table
+    });
+
+    table[(depth as usize).min(MAX_PLY - 1)][move_number.min(63)]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Mutex};
+
+    use super::*;
+
+    #[test]
+    fn limited_strength_bounds_root_scoring() {
+        let strength = Strength::new(14).unwrap();
+        let budget = strength.limit(Limits::default()).nodes.unwrap();
+        let mut search = Search::default();
+        search.set_strength(strength);
+
+        let board =
+            Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        for limits in [Limits::default(), Limits::depth(30)] {
+            assert!(search.think(&board, limits).is_some());
+            assert!(search.nodes() <= 2 * budget + 4096, "{}", search.nodes());
+        }
+    }
+
+    #[test]
+    fn allocate_time_plays_faster_when_behind() {
+        let minute = Duration::from_secs(60);
+        let even = allocate_time(minute, Some(minute), Duration::ZERO, Some(20));
+        assert_eq!(even, Duration::from_secs(3));
+        assert_eq!(allocate_time(minute, None, Duration::ZERO, Some(20)), even);
+        assert_eq!(
+            allocate_time(minute, Some(minute / 2), Duration::ZERO, Some(20)),
+            even
+        );
+        assert_eq!(
+            allocate_time(minute, Some(minute * 3 / 2), Duration::ZERO, Some(20)),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            allocate_time(minute, Some(minute * 10), Duration::ZERO, Some(20)),
+            Duration::from_millis(1500)
+        );
+    }
+
+    #[test]
+    fn helper_threads_share_the_table() {
+        let board =
+            Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let mut search = Search::default();
+        search.set_threads(4);
+
+        let best_move = search.think(&board, Limits::depth(4)).unwrap();
+        assert!(board.clone().get_all_moves().contains(&best_move));
+        assert_eq!(search.heuristics.len(), 4);
+        assert!(search.tt.probe(board.hash()).is_some());
+
+        // a single thread picks up where the helpers left off
+        let nodes = search.nodes();
+        search.set_threads(1);
+        search.think(&board, Limits::depth(4));
+        assert!(search.nodes() < nodes, "{} >= {}", search.nodes(), nodes);
+    }
+
+    #[test]
+    fn multi_pv_reports_distinct_moves_best_first() {
+        let board =
+            Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reported = reports.clone();
+        let mut search = Search::default();
+        search.set_multi_pv(3);
+        search.set_reporter(Arc::new(move |lines: &[Line]| {
+            reported.lock().unwrap().push(lines.to_vec());
+        }));
+
+        let best_move = search.think(&board, Limits::depth(4));
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 4);
+
+        let legal_moves = board.clone().get_all_moves();
+        for (depth, lines) in (1..).zip(reports.iter()) {
+            assert_eq!(lines.len(), 3);
+            for (multipv, line) in (1..).zip(lines) {
+                assert_eq!((line.multipv, line.depth), (multipv, depth));
+                assert!(legal_moves.contains(&line.pv[0]));
+            }
+            assert!(lines.windows(2).all(|pair| pair[0].score >= pair[1].score));
+            let root_moves: HashSet<_> = lines.iter().map(|line| line.pv[0]).collect();
+            assert_eq!(root_moves.len(), 3);
+        }
+        assert_eq!(best_move, Some(reports[3][0].pv[0]));
+    }
+
+    #[test]
+    fn personalities_share_the_table() {
+        let personality: &'static Personality = Box::leak(Box::new(Personality::new("sharp")));
+        let board = Board::default();
+        let mut search = Search::default();
+
+        search.set_personality(Some(personality));
+        search.think(&board, Limits::depth(3));
+        search.set_personality(None);
+        search.think(&board, Limits::depth(3));
+        search.set_personality(Some(personality));
+
+        let styled = board.hash() ^ tt_salt(Some(personality));
+        assert!(search.tt.probe(styled).is_some());
+        assert!(search.tt.probe(board.hash()).is_some());
+        assert_ne!(styled, board.hash());
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{bail, Result};
+use rand::Rng;
+
+use crate::board::Move;
+
+use super::search::Limits;
+
+pub const MIN_LEVEL: u8 = 1;
+pub const MAX_LEVEL: u8 = 20;
+
+const MIN_ELO: i32 = 800;
+const ELO_PER_LEVEL: i32 = 100;
+const NOISE_PER_LEVEL: i32 = 10;
+const TEMPERATURE_PER_LEVEL: f64 = 15.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Strength {
+    level: u8,
+}
+
+impl Strength {
+    pub fn new(level: u8) -> Result<Self> {
+        if !(MIN_LEVEL..=MAX_LEVEL).contains(&level) {
+            bail!(
+                "strength level must be between {} and {}",
+                MIN_LEVEL,
+                MAX_LEVEL
+            );
+        }
+
+        Ok(Self { level })
+    }
+
+    pub fn from_elo(elo: i32) -> Self {
+        let level = ((elo - MIN_ELO) as f64 / ELO_PER_LEVEL as f64).round() as i32 + 1;
+        Self {
+            level: level.clamp(MIN_LEVEL as i32, MAX_LEVEL as i32) as u8,
+        }
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    pub fn elo(&self) -> i32 {
+        MIN_ELO + (self.level - MIN_LEVEL) as i32 * ELO_PER_LEVEL
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.level < MAX_LEVEL
+    }
+
+    pub fn limit(&self, limits: Limits) -> Limits {
+        if !self.is_limited() {
+            return limits;
+        }
+
+        let depth = self.level as i32 / 2 + 1;
+        let nodes = (50. * 1.6f64.powi(self.level as i32)) as u64;
+        Limits {
+            depth: Some(limits.depth.map_or(depth, |limit| limit.min(depth))),
+            nodes: Some(limits.nodes.map_or(nodes, |limit| limit.min(nodes))),
+            ..limits
+        }
+    }
+
+    pub fn noise(&self, key: u64) -> i32 {
+        let amplitude = (MAX_LEVEL - self.level) as i32 * NOISE_PER_LEVEL;
+        if amplitude == 0 {
+            return 0;
+        }
+
+        let hashed = key.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32;
+        (hashed % (2 * amplitude as u64 + 1)) as i32 - amplitude
+    }
+
+    pub fn pick(&self, scored: &[(Move, i32)]) -> Option<Move> {
+        let best = scored.iter().map(|(_, score)| *score).max()?;
+        if !self.is_limited() {
+            return scored
+                .iter()
+                .find(|(_, score)| *score == best)
+                .map(|(m, _)| *m);
+        }
+
+        let temperature = (MAX_LEVEL - self.level) as f64 * TEMPERATURE_PER_LEVEL;
+        let weights: Vec<f64> = scored
+            .iter()
+            .map(|(_, score)| ((score - best) as f64 / temperature).exp())
+            .collect();
+        let total: f64 = weights.iter().sum();
+
+        let mut choice = rand::thread_rng().gen_range(0.0..total);
+        for ((m, _), weight) in scored.iter().zip(weights) {
+            if choice < weight {
+                return Some(*m);
+            }
+            choice -= weight;
+        }
+        scored.last().map(|(m, _)| *m)
+    }
+}
+
+impl Default for Strength {
+    fn default() -> Self {
+        Self { level: MAX_LEVEL }
+    }
+}
@@ -0,0 +1,1135 @@
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{bail, Context, Result};
+use memmap2::Mmap;
+
+use crate::{
+    board::{Board, Move},
+    piece::Piece,
+};
+
+use super::tablebase::Wdl;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+const PIECE_CHARS: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
+
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+const LOSS: i32 = -2;
+const BLESSED_LOSS: i32 = -1;
+const CURSED_WIN: i32 = 1;
+const WIN: i32 = 2;
+
+pub const MAX_PIECES: usize = 7;
+pub const WDL_EXTENSION: &str = "rtbw";
+pub const DTZ_EXTENSION: &str = "rtbz";
+
+struct Indices {
+    binomial: [[u64; 64]; MAX_PIECES],
+    map_pawns: [usize; 64],
+    lead_pawn_index: [[u64; 64]; MAX_PIECES],
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+    map_b1h1h7: [usize; 64],
+    map_a1d1d4: [usize; 64],
+    map_kk: [[usize; 64]; 10],
+}
+
+impl Indices {
+    fn get() -> &'static Self {
+        static INDICES: OnceLock<Indices> = OnceLock::new();
+        INDICES.get_or_init(Self::new)
+    }
+
+    #[allow(clippy::needless_range_loop)]
+    fn new() -> Self {
+        let mut indices = Self {
+            binomial: [[0; 64]; MAX_PIECES],
+            map_pawns: [0; 64],
+            lead_pawn_index: [[0; 64]; MAX_PIECES],
+            lead_pawns_size: [[0; 4]; MAX_PIECES],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+        };
+
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                indices.map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = Vec::new();
+        code = 0;
+        for square in 0..28 {
+            if square % 8 > 3 {
+                continue;
+            }
+            if off_diagonal(square) < 0 {
+                indices.map_a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            indices.map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        let mut both_on_diagonal = Vec::new();
+        code = 0;
+        for index in 0..10 {
+            for king in 0..28 {
+                if king % 8 > 3 || indices.map_a1d1d4[king] != index || (index == 0 && king != 1) {
+                    continue;
+                }
+
+                for other in 0..64 {
+                    if (king % 8).abs_diff(other % 8) <= 1 && (king / 8).abs_diff(other / 8) <= 1 {
+                        continue;
+                    }
+                    if off_diagonal(king) == 0 && off_diagonal(other) > 0 {
+                        continue;
+                    }
+                    if off_diagonal(king) == 0 && off_diagonal(other) == 0 {
+                        both_on_diagonal.push((index, other));
+                    } else {
+                        indices.map_kk[index][other] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (index, other) in both_on_diagonal {
+            indices.map_kk[index][other] = code;
+            code += 1;
+        }
+
+        indices.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                indices.binomial[k][n] = if k > 0 {
+                    indices.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n { indices.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut available = 47;
+        for count in 1..MAX_PIECES - 1 {
+            for file in 0..4 {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if count == 1 {
+                        indices.map_pawns[square] = available;
+                        indices.map_pawns[square ^ 7] = available - 1;
+                        available = available.saturating_sub(2);
+                    }
+                    indices.lead_pawn_index[count][square] = index;
+                    index += indices.binomial[count - 1][indices.map_pawns[square]];
+                }
+                indices.lead_pawns_size[count][file] = index;
+            }
+        }
+
+        indices
+    }
+}
+
+fn off_diagonal(square: usize) -> isize {
+    (square / 8) as isize - (square % 8) as isize
+}
+
+fn piece_code(piece: Piece) -> u8 {
+    if piece.is_none() {
+        return 0;
+    }
+    piece.index() as u8 + 1 + if piece.is_black() { 8 } else { 0 }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes(bytes.try_into().unwrap())),
+        None => bail!("unexpected end of table"),
+    }
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8> {
+    data.get(offset).copied().context("unexpected end of table")
+}
+
+#[derive(Clone, Default)]
+struct Pairs {
+    flags: u8,
+    pieces: [u8; MAX_PIECES],
+    group_len: [usize; MAX_PIECES + 1],
+    group_index: [u64; MAX_PIECES + 1],
+    block_size: usize,
+    span: u64,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    block_length: usize,
+    block_length_size: usize,
+    blocks: usize,
+    data: usize,
+    min_sym_len: u8,
+    lowest_sym: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u8>,
+    btree: usize,
+    map_index: [usize; 4],
+}
+
+impl Pairs {
+    fn read_sizes(&mut self, data: &[u8], mut offset: usize) -> Result<usize> {
+        self.flags = read_u8(data, offset)?;
+        offset += 1;
+        if self.flags & SINGLE_VALUE != 0 {
+            self.min_sym_len = read_u8(data, offset)?;
+            return Ok(offset + 1);
+        }
+
+        let size = self.group_index[self.group_len.iter().position(|&len| len == 0).unwrap()];
+        self.block_size = 1 << read_u8(data, offset)?;
+        self.span = 1 << read_u8(data, offset + 1)?;
+        self.sparse_index_size = size.div_ceil(self.span) as usize;
+        let padding = read_u8(data, offset + 2)? as usize;
+        self.blocks = match data.get(offset + 3..offset + 7) {
+            Some(bytes) => u32::from_le_bytes(bytes.try_into().unwrap()) as usize,
+            None => bail!("unexpected end of table"),
+        };
+        self.block_length_size = self.blocks + padding;
+        let max_sym_len = read_u8(data, offset + 7)?;
+        self.min_sym_len = read_u8(data, offset + 8)?;
+        if max_sym_len < self.min_sym_len || self.min_sym_len == 0 {
+            bail!("invalid symbol lengths");
+        }
+        offset += 9;
+        self.lowest_sym = offset;
+
+        let lengths = (max_sym_len - self.min_sym_len) as usize + 1;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = read_u16(data, self.lowest_sym + 2 * i)? as u64;
+            let next = read_u16(data, self.lowest_sym + 2 * i + 2)? as u64;
+            self.base64[i] = self.base64[i + 1].wrapping_add(lowest).wrapping_sub(next) / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base
+                .checked_shl((64 - i - self.min_sym_len as usize) as u32)
+                .unwrap_or(0);
+        }
+        offset += 2 * lengths;
+
+        let symbols = read_u16(data, offset)? as usize;
+        offset += 2;
+        self.btree = offset;
+        if data.len() < self.btree + 3 * symbols {
+            bail!("unexpected end of table");
+        }
+
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.symlen[symbol] = self.symbol_length(data, symbol, &mut visited)?;
+            }
+        }
+
+        Ok(offset + 3 * symbols + (symbols & 1))
+    }
+
+    fn symbol_length(&mut self, data: &[u8], symbol: usize, visited: &mut [bool]) -> Result<u8> {
+        visited[symbol] = true;
+        let (left, right) = self.children(data, symbol);
+        if right == 0xfff {
+            return Ok(0);
+        }
+        if left >= visited.len() || right >= visited.len() {
+            bail!("invalid symbol tree");
+        }
+
+        for child in [left, right] {
+            if !visited[child] {
+                self.symlen[child] = self.symbol_length(data, child, visited)?;
+            }
+        }
+        Ok(self.symlen[left]
+            .wrapping_add(self.symlen[right])
+            .wrapping_add(1))
+    }
+
+    fn children(&self, data: &[u8], symbol: usize) -> (usize, usize) {
+        let lr = &data[self.btree + 3 * symbol..self.btree + 3 * symbol + 3];
+        (
+            ((lr[1] as usize & 0xf) << 8) | lr[0] as usize,
+            ((lr[2] as usize) << 4) | (lr[1] as usize >> 4),
+        )
+    }
+
+    fn decompress(&self, data: &[u8], index: u64) -> i32 {
+        if self.flags & SINGLE_VALUE != 0 {
+            return self.min_sym_len as i32;
+        }
+
+        let sparse = self.sparse_index + 6 * (index / self.span) as usize;
+        let mut block = u32::from_le_bytes(data[sparse..sparse + 4].try_into().unwrap()) as usize;
+        let mut offset =
+            u16::from_le_bytes(data[sparse + 4..sparse + 6].try_into().unwrap()) as i64;
+        offset += (index % self.span) as i64 - (self.span / 2) as i64;
+
+        let block_length = |block: usize| {
+            let at = self.block_length + 2 * block;
+            u16::from_le_bytes(data[at..at + 2].try_into().unwrap()) as i64
+        };
+        while offset < 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        let mut at = self.data + block * self.block_size;
+        let mut buffer = u64::from_be_bytes(data[at..at + 8].try_into().unwrap());
+        let mut buffered = 64;
+        at += 8;
+
+        let min_sym_len = self.min_sym_len as usize;
+        let mut symbol;
+        loop {
+            let mut len = 0;
+            while buffer < self.base64[len] {
+                len += 1;
+            }
+
+            symbol = ((buffer - self.base64[len]) >> (64 - len - min_sym_len)) as usize;
+            let lowest = self.lowest_sym + 2 * len;
+            symbol = (symbol
+                + u16::from_le_bytes(data[lowest..lowest + 2].try_into().unwrap()) as usize)
+                & 0xffff;
+
+            if offset < self.symlen[symbol] as i64 + 1 {
+                break;
+            }
+            offset -= self.symlen[symbol] as i64 + 1;
+
+            len += min_sym_len;
+            buffer <<= len;
+            buffered -= len;
+            if buffered <= 32 {
+                buffered += 32;
+                buffer |= (u32::from_be_bytes(data[at..at + 4].try_into().unwrap()) as u64)
+                    << (64 - buffered);
+                at += 4;
+            }
+        }
+
+        while self.symlen[symbol] != 0 {
+            let (left, right) = self.children(data, symbol);
+            if offset < self.symlen[left] as i64 + 1 {
+                symbol = left;
+            } else {
+                offset -= self.symlen[left] as i64 + 1;
+                symbol = right;
+            }
+        }
+
+        self.children(data, symbol).0 as i32
+    }
+}
+
+struct Material {
+    white: String,
+    black: String,
+}
+
+impl Material {
+    fn parse(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let valid = |side: &str| {
+            side.starts_with('K')
+                && side.matches('K').count() == 1
+                && side.chars().all(|c| PIECE_CHARS.contains(&c))
+        };
+        if !valid(white) || !valid(black) || white.len() + black.len() > MAX_PIECES {
+            return None;
+        }
+
+        Some(Self {
+            white: white.to_string(),
+            black: black.to_string(),
+        })
+    }
+
+    fn from_board(board: &Board) -> Self {
+        let side = |color: Piece| {
+            let mut indices: Vec<usize> = (0..64)
+                .map(|square| board.get_square(square))
+                .filter(|piece| !piece.is_none() && piece.color() == color)
+                .map(|piece| piece.index())
+                .collect();
+            indices.sort_unstable_by(|a, b| b.cmp(a));
+            indices
+                .into_iter()
+                .map(|index| PIECE_CHARS[index])
+                .collect()
+        };
+
+        Self {
+            white: side(Piece::White),
+            black: side(Piece::Black),
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("{}v{}", self.white, self.black)
+    }
+
+    fn swapped(&self) -> Self {
+        Self {
+            white: self.black.clone(),
+            black: self.white.clone(),
+        }
+    }
+}
+
+struct Table {
+    mmap: Mmap,
+    symmetric: bool,
+    has_pawns: bool,
+    unique_pieces: bool,
+    pawn_count: [usize; 2],
+    sides: usize,
+    files: usize,
+    pairs: Vec<Pairs>,
+    map: usize,
+}
+
+impl Table {
+    fn load(path: &Path, material: &Material, dtz: bool) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("could not open {}", path.display()))?;
+        let mmap = unsafe { Mmap::map(&file) }
+            .with_context(|| format!("could not map {}", path.display()))?;
+        let magic = if dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if mmap.len() < 5 || mmap[..4] != magic {
+            bail!("{} is not a Syzygy table", path.display());
+        }
+
+        let count = |side: &str, c: char| side.chars().filter(|&p| p == c).count();
+        let white_pawns = count(&material.white, 'P');
+        let black_pawns = count(&material.black, 'P');
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let unique = |side: &str| "QRBNP".chars().any(|c| count(side, c) == 1);
+
+        let mut table = Self {
+            mmap,
+            symmetric: material.white == material.black,
+            has_pawns: white_pawns + black_pawns > 0,
+            unique_pieces: unique(&material.white) || unique(&material.black),
+            pawn_count: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            sides: 1,
+            files: 1,
+            pairs: Vec::new(),
+            map: 0,
+        };
+        table
+            .read(material.white.len() + material.black.len(), dtz)
+            .with_context(|| format!("{} is corrupted", path.display()))?;
+
+        Ok(table)
+    }
+
+    fn read(&mut self, piece_count: usize, dtz: bool) -> Result<()> {
+        let indices = Indices::get();
+        let data = &self.mmap[..];
+
+        let flags = data[4];
+        if (flags & 2 != 0) != self.has_pawns || (!dtz && (flags & 1 != 0) == self.symmetric) {
+            bail!("header does not match the file name");
+        }
+        self.sides = if !dtz && !self.symmetric { 2 } else { 1 };
+        self.files = if self.has_pawns { 4 } else { 1 };
+        self.pairs = vec![Pairs::default(); self.sides * self.files];
+
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut offset = 5;
+        for file in 0..self.files {
+            let first = read_u8(data, offset)?;
+            let second = if both_pawns {
+                read_u8(data, offset + 1)?
+            } else {
+                0xff
+            };
+            let order = [
+                [first & 0xf, if both_pawns { second & 0xf } else { 0xf }],
+                [first >> 4, if both_pawns { second >> 4 } else { 0xf }],
+            ];
+            offset += 1 + usize::from(both_pawns);
+
+            for k in 0..piece_count {
+                let byte = read_u8(data, offset)?;
+                for side in 0..self.sides {
+                    self.pairs[side * self.files + file].pieces[k] =
+                        if side == 1 { byte >> 4 } else { byte & 0xf };
+                }
+                offset += 1;
+            }
+
+            for (side, &order) in order.iter().take(self.sides).enumerate() {
+                let pairs = &mut self.pairs[side * self.files + file];
+                set_groups(
+                    pairs,
+                    piece_count,
+                    order,
+                    file,
+                    self.has_pawns,
+                    self.unique_pieces,
+                    both_pawns,
+                    indices,
+                )?;
+            }
+        }
+        offset += offset & 1;
+
+        for file in 0..self.files {
+            for side in 0..self.sides {
+                offset = self.pairs[side * self.files + file].read_sizes(data, offset)?;
+            }
+        }
+
+        if dtz {
+            self.map = offset;
+            for pairs in &mut self.pairs {
+                if pairs.flags & MAPPED == 0 {
+                    continue;
+                }
+                if pairs.flags & WIDE != 0 {
+                    offset += offset & 1;
+                    for index in &mut pairs.map_index {
+                        *index = offset + 2;
+                        offset += 2 * read_u16(data, offset)? as usize + 2;
+                    }
+                } else {
+                    for index in &mut pairs.map_index {
+                        *index = offset + 1;
+                        offset += read_u8(data, offset)? as usize + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        let (sides, files) = (self.sides, self.files);
+        let order: Vec<usize> = (0..files)
+            .flat_map(|file| (0..sides).map(move |side| side * files + file))
+            .collect();
+        for &i in &order {
+            self.pairs[i].sparse_index = offset;
+            offset += 6 * self.pairs[i].sparse_index_size;
+        }
+        for &i in &order {
+            self.pairs[i].block_length = offset;
+            offset += 2 * self.pairs[i].block_length_size;
+        }
+        for &i in &order {
+            offset = (offset + 0x3f) & !0x3f;
+            self.pairs[i].data = offset;
+            offset += self.pairs[i].blocks * self.pairs[i].block_size;
+        }
+
+        if offset > self.mmap.len() {
+            bail!("unexpected end of table");
+        }
+        Ok(())
+    }
+
+    fn pairs(&self, side: usize, file: usize) -> &Pairs {
+        &self.pairs[(side % self.sides) * self.files + if self.has_pawns { file } else { 0 }]
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn set_groups(
+    pairs: &mut Pairs,
+    piece_count: usize,
+    order: [u8; 2],
+    file: usize,
+    has_pawns: bool,
+    unique_pieces: bool,
+    both_pawns: bool,
+    indices: &Indices,
+) -> Result<()> {
+    let mut n = 0;
+    let mut first_len: i32 = if has_pawns {
+        0
+    } else if unique_pieces {
+        3
+    } else {
+        2
+    };
+    pairs.group_len[0] = 1;
+    for i in 1..piece_count {
+        first_len -= 1;
+        if first_len > 0 || pairs.pieces[i] == pairs.pieces[i - 1] {
+            pairs.group_len[n] += 1;
+        } else {
+            n += 1;
+            pairs.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    pairs.group_len[n] = 0;
+
+    let mut next = if both_pawns { 2 } else { 1 };
+    let mut free = 64 - pairs.group_len[0] - if both_pawns { pairs.group_len[1] } else { 0 };
+    let mut index: u64 = 1;
+    let mut k = 0;
+    while next < n || k == order[0] || k == order[1] {
+        if k == order[0] {
+            pairs.group_index[0] = index;
+            index *= if has_pawns {
+                indices.lead_pawns_size[pairs.group_len[0]][file]
+            } else if unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] {
+            pairs.group_index[1] = index;
+            index *= indices.binomial[pairs.group_len[1]][48 - pairs.group_len[0]];
+        } else {
+            if next >= n {
+                bail!("invalid group order");
+            }
+            pairs.group_index[next] = index;
+            index *= indices.binomial[pairs.group_len[next]][free];
+            free -= pairs.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    pairs.group_index[n] = index;
+
+    Ok(())
+}
+
+enum Lookup {
+    Value(i32),
+    ChangeSide,
+}
+
+struct Slot {
+    path: PathBuf,
+    table: OnceLock<Option<Table>>,
+}
+
+pub struct Syzygy {
+    wdl: HashMap<String, Slot>,
+    dtz: HashMap<String, Slot>,
+    max_pieces: usize,
+}
+
+impl Syzygy {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut syzygy = Self {
+            wdl: HashMap::new(),
+            dtz: HashMap::new(),
+            max_pieces: 0,
+        };
+
+        for entry in fs::read_dir(dir)
+            .with_context(|| format!("could not read Syzygy directory {}", dir.display()))?
+        {
+            let path = entry?.path();
+            let (Some(name), Some(extension)) = (
+                path.file_stem().and_then(|stem| stem.to_str()),
+                path.extension().and_then(|extension| extension.to_str()),
+            ) else {
+                continue;
+            };
+            let Some(material) = Material::parse(name) else {
+                continue;
+            };
+
+            let slot = Slot {
+                path: path.clone(),
+                table: OnceLock::new(),
+            };
+            if extension == WDL_EXTENSION {
+                syzygy.wdl.insert(material.name(), slot);
+                syzygy.max_pieces = syzygy
+                    .max_pieces
+                    .max(material.white.len() + material.black.len());
+            } else if extension == DTZ_EXTENSION {
+                syzygy.dtz.insert(material.name(), slot);
+            }
+        }
+
+        Ok(syzygy)
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        self.search(board, false).map(|(wdl, _)| to_wdl(wdl))
+    }
+
+    pub fn best_move(&self, board: &Board) -> Option<(Move, Wdl)> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        let mut board = board.clone();
+        let mut best: Option<(Move, i32)> = None;
+        for m in board.get_all_moves() {
+            let zeroing = is_zeroing(&board, m);
+            let mut child = board.clone();
+            child.make_move(m);
+
+            let mut dtz = if zeroing {
+                before_zeroing(-self.search(&child, false)?.0)
+            } else {
+                let dtz = -self.dtz(&child)?;
+                dtz + dtz.signum()
+            };
+            if dtz == 2 && child.is_in_check() && child.get_all_moves().is_empty() {
+                dtz = 1;
+            }
+
+            let better = match best {
+                None => true,
+                Some((_, best)) => rank(dtz) > rank(best),
+            };
+            if better {
+                best = Some((m, dtz));
+            }
+        }
+
+        best.map(|(m, dtz)| (m, to_wdl(dtz.signum() * 2)))
+    }
+
+    fn can_probe(&self, board: &Board) -> bool {
+        board.piece_count() <= self.max_pieces && !board.get_castling_rights().contains(&true)
+    }
+
+    fn search(&self, board: &Board, check_zeroing: bool) -> Option<(i32, bool)> {
+        let mut board = board.clone();
+        let moves = board.get_all_moves();
+        let mut best = LOSS;
+        let mut searched = 0;
+
+        for &m in &moves {
+            let zeroing = if check_zeroing {
+                is_zeroing(&board, m)
+            } else {
+                board.is_capture(m)
+            };
+            if !zeroing {
+                continue;
+            }
+            searched += 1;
+
+            let mut child = board.clone();
+            child.make_move(m);
+            let value = -self.search(&child, false)?.0;
+            if value > best {
+                best = value;
+                if value >= WIN {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        let exhausted = searched > 0 && searched == moves.len();
+        let value = if exhausted {
+            best
+        } else {
+            match self.probe_table(&board, false, 0)? {
+                Lookup::Value(value) => value,
+                Lookup::ChangeSide => return None,
+            }
+        };
+
+        if best >= value {
+            Some((best, best > 0 || exhausted))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    fn dtz(&self, board: &Board) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(board, true)?;
+        if wdl == 0 {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(before_zeroing(wdl));
+        }
+
+        match self.probe_table(board, true, wdl)? {
+            Lookup::Value(dtz) => {
+                let cursed = wdl == CURSED_WIN || wdl == BLESSED_LOSS;
+                Some((dtz + if cursed { 100 } else { 0 }) * wdl.signum())
+            }
+            Lookup::ChangeSide => {
+                let mut board = board.clone();
+                let mut min = i32::MAX;
+                for m in board.get_all_moves() {
+                    let zeroing = is_zeroing(&board, m);
+                    let mut child = board.clone();
+                    child.make_move(m);
+
+                    let mut dtz = if zeroing {
+                        -before_zeroing(self.search(&child, false)?.0)
+                    } else {
+                        -self.dtz(&child)?
+                    };
+                    if dtz == 1 && child.is_in_check() && child.get_all_moves().is_empty() {
+                        min = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < min && dtz.signum() == wdl.signum() {
+                        min = dtz;
+                    }
+                }
+
+                Some(if min == i32::MAX { -1 } else { min })
+            }
+        }
+    }
+
+    fn probe_table(&self, board: &Board, dtz: bool, wdl: i32) -> Option<Lookup> {
+        if board.piece_count() == 2 {
+            return Some(Lookup::Value(0));
+        }
+
+        let slots = if dtz { &self.dtz } else { &self.wdl };
+
+        let material = Material::from_board(board);
+        let (slot, swapped) = match slots.get(&material.name()) {
+            Some(slot) => (slot, false),
+            None => (slots.get(&material.swapped().name())?, true),
+        };
+        let table = slot
+            .table
+            .get_or_init(|| {
+                let material = if swapped {
+                    material.swapped()
+                } else {
+                    material
+                };
+                match Table::load(&slot.path, &material, dtz) {
+                    Ok(table) => Some(table),
+                    Err(err) => {
+                        eprintln!("{:#}", err);
+                        None
+                    }
+                }
+            })
+            .as_ref()?;
+
+        Some(lookup(table, board, swapped, dtz, wdl))
+    }
+}
+
+fn lookup(table: &Table, board: &Board, swapped: bool, dtz: bool, wdl: i32) -> Lookup {
+    let (side, file, index) = encode(table, board, swapped);
+    let pairs = table.pairs(side, file);
+    let one_sided = table.has_pawns || !table.symmetric;
+    if dtz && one_sided && pairs.flags & STM != side as u8 {
+        return Lookup::ChangeSide;
+    }
+
+    let value = pairs.decompress(&table.mmap, index);
+    if !dtz {
+        return Lookup::Value(value - 2);
+    }
+    Lookup::Value(map_dtz(table, pairs, value, wdl))
+}
+
+fn encode(table: &Table, board: &Board, swapped: bool) -> (usize, usize, u64) {
+    let indices = Indices::get();
+    let black_to_move = !board.get_current_turn().is_white();
+    let flip = swapped || (table.symmetric && black_to_move);
+    let (flip_color, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+    let side = usize::from(flip != black_to_move);
+
+    let code = |square: usize| piece_code(board.get_square(square ^ 56));
+    let mut squares = [0; MAX_PIECES];
+    let mut pieces = [0; MAX_PIECES];
+    let mut size = 0;
+    let mut lead_pawns = 0;
+    let mut file = 0;
+
+    let lead_pawn = table.pairs[0].pieces[0] ^ flip_color;
+    if table.has_pawns {
+        for square in 0..64 {
+            if code(square) == lead_pawn {
+                squares[size] = square ^ flip_squares;
+                size += 1;
+            }
+        }
+        lead_pawns = size;
+
+        let leading = (0..lead_pawns)
+            .max_by_key(|&i| indices.map_pawns[squares[i]])
+            .unwrap();
+        squares.swap(0, leading);
+        file = (squares[0] % 8).min(7 - squares[0] % 8);
+    }
+
+    let pairs = table.pairs(side, file);
+    for square in 0..64 {
+        let piece = code(square);
+        if piece != 0 && !(table.has_pawns && piece == lead_pawn) {
+            squares[size] = square ^ flip_squares;
+            pieces[size] = piece ^ flip_color;
+            size += 1;
+        }
+    }
+
+    for i in lead_pawns..size.saturating_sub(1) {
+        if let Some(j) = (i + 1..size).find(|&j| pairs.pieces[i] == pieces[j]) {
+            pieces.swap(i, j);
+            squares.swap(i, j);
+        }
+    }
+
+    if squares[0] % 8 > 3 {
+        squares[..size].iter_mut().for_each(|square| *square ^= 7);
+    }
+
+    let mut index;
+    if table.has_pawns {
+        index = indices.lead_pawn_index[lead_pawns][squares[0]];
+        squares[1..lead_pawns].sort_by_key(|&square| indices.map_pawns[square]);
+        for (i, &square) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+            index += indices.binomial[i][indices.map_pawns[square]];
+        }
+    } else {
+        if squares[0] / 8 > 3 {
+            squares[..size].iter_mut().for_each(|square| *square ^= 56);
+        }
+        for i in 0..pairs.group_len[0] {
+            match off_diagonal(squares[i]) {
+                0 => continue,
+                diagonal if diagonal > 0 => squares[i..size]
+                    .iter_mut()
+                    .for_each(|square| *square = ((*square >> 3) | (*square << 3)) & 63),
+                _ => {}
+            }
+            break;
+        }
+
+        index = if table.unique_pieces {
+            unique_index(&squares, indices)
+        } else {
+            indices.map_kk[indices.map_a1d1d4[squares[0]]][squares[1]] as u64
+        };
+    }
+
+    index *= pairs.group_index[0];
+    let mut start = pairs.group_len[0];
+    let mut remaining_pawns = table.has_pawns && table.pawn_count[1] > 0;
+    let mut next = 1;
+    while pairs.group_len[next] != 0 {
+        let len = pairs.group_len[next];
+        squares[start..start + len].sort_unstable();
+
+        let mut n = 0;
+        for i in 0..len {
+            let square = squares[start + i];
+            let adjust = squares[..start]
+                .iter()
+                .filter(|&&other| square > other)
+                .count();
+            n += indices.binomial[i + 1][square - adjust - if remaining_pawns { 8 } else { 0 }];
+        }
+
+        remaining_pawns = false;
+        index += n * pairs.group_index[next];
+        start += len;
+        next += 1;
+    }
+
+    (side, file, index)
+}
+
+fn unique_index(squares: &[usize], indices: &Indices) -> u64 {
+    let rank = |square: usize| square / 8;
+    let adjust1 = usize::from(squares[1] > squares[0]);
+    let adjust2 = usize::from(squares[2] > squares[0]) + usize::from(squares[2] > squares[1]);
+
+    (if off_diagonal(squares[0]) != 0 {
+        (indices.map_a1d1d4[squares[0]] * 63 + squares[1] - adjust1) * 62 + squares[2] - adjust2
+    } else if off_diagonal(squares[1]) != 0 {
+        (6 * 63 + rank(squares[0]) * 28 + indices.map_b1h1h7[squares[1]]) * 62 + squares[2]
+            - adjust2
+    } else if off_diagonal(squares[2]) != 0 {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + rank(squares[0]) * 7 * 28
+            + (rank(squares[1]) - adjust1) * 28
+            + indices.map_b1h1h7[squares[2]]
+    } else {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + 4 * 7 * 28
+            + rank(squares[0]) * 7 * 6
+            + (rank(squares[1]) - adjust1) * 6
+            + (rank(squares[2]) - adjust2)
+    }) as u64
+}
+
+fn map_dtz(table: &Table, pairs: &Pairs, value: i32, wdl: i32) -> i32 {
+    let data = &table.mmap[..];
+    let mut value = value;
+    if pairs.flags & MAPPED != 0 {
+        let map = pairs.map_index[[1, 3, 0, 2, 0][(wdl + 2) as usize]];
+        value = if pairs.flags & WIDE != 0 {
+            let at = map + 2 * value as usize;
+            u16::from_le_bytes([data[at], data[at + 1]]) as i32
+        } else {
+            data[map + value as usize] as i32
+        };
+    }
+
+    if (wdl == WIN && pairs.flags & WIN_PLIES == 0)
+        || (wdl == LOSS && pairs.flags & LOSS_PLIES == 0)
+        || wdl == CURSED_WIN
+        || wdl == BLESSED_LOSS
+    {
+        value *= 2;
+    }
+    value + 1
+}
+
+fn is_zeroing(board: &Board, m: Move) -> bool {
+    board.is_capture(m) || board.get_square(m.from).split().0 == Piece::Pawn
+}
+
+fn before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        WIN => 1,
+        CURSED_WIN => 101,
+        BLESSED_LOSS => -101,
+        LOSS => -1,
+        _ => 0,
+    }
+}
+
+fn rank(dtz: i32) -> (i32, i32) {
+    match dtz.signum() {
+        1 => (1, -dtz),
+        0 => (0, 0),
+        _ => (-1, -dtz),
+    }
+}
+
+fn to_wdl(value: i32) -> Wdl {
+    match value.signum() {
+        1 => Wdl::Win,
+        0 => Wdl::Draw,
+        _ => Wdl::Loss,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // our own encoding of KRvK and KPvK, set SYZYGY_PATH to check the published tables instead
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/syzygy");
+
+    fn open() -> Syzygy {
+        let path = std::env::var("SYZYGY_PATH").unwrap_or_else(|_| FIXTURES.to_string());
+        Syzygy::open(&path).unwrap()
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn probes_wdl() {
+        let syzygy = open();
+        assert!(syzygy.max_pieces() >= 3);
+
+        for (fen, wdl) in [
+            ("6k1/8/6K1/8/8/8/8/R7 w - - 0 1", Wdl::Win),
+            ("k7/8/1K6/8/8/8/8/7R b - - 0 1", Wdl::Loss),
+            ("8/8/8/8/8/8/6Rk/K7 b - - 0 1", Wdl::Draw),
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win),
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Wdl::Loss),
+            ("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1", Wdl::Win),
+            ("k7/8/8/8/8/8/P7/K7 w - - 0 1", Wdl::Draw),
+            ("8/8/8/8/8/3k4/4P3/K7 b - - 0 1", Wdl::Draw),
+        ] {
+            assert_eq!(syzygy.probe_wdl(&board(fen)), Some(wdl), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn probes_dtz() {
+        let syzygy = open();
+        for (fen, dtz) in [
+            ("6k1/8/6K1/8/8/8/8/R7 w - - 0 1", 1),
+            ("k7/8/1K6/8/8/8/8/7R b - - 0 1", -2),
+            ("8/8/8/8/8/8/6Rk/K7 b - - 0 1", 0),
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", 3),
+            ("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1", 3),
+        ] {
+            assert_eq!(syzygy.dtz(&board(fen)), Some(dtz), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn best_move_converts_krk() {
+        let syzygy = open();
+        assert_eq!(
+            syzygy.best_move(&board("6k1/8/6K1/8/8/8/8/R7 w - - 0 1")),
+            Some(("a1a8".parse().unwrap(), Wdl::Win))
+        );
+
+        let mut board = board("8/8/8/4k3/8/8/8/R3K3 w - - 0 1");
+        let dtz = syzygy.dtz(&board).unwrap();
+        let mut plies = 0;
+        while let Some((m, _)) = syzygy.best_move(&board) {
+            board.make_move(m);
+            plies += 1;
+        }
+        assert!(board.is_in_check() && board.get_all_moves().is_empty());
+        assert_eq!(plies, dtz);
+    }
+}
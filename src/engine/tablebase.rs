@@ -0,0 +1,431 @@
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    board::{Board, Move},
+    piece::Piece,
+};
+
+const MAGIC: &[u8; 4] = b"CATB";
+const VERSION: u32 = 1;
+const PIECE_CHARS: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
+const TRIANGLE: [usize; 10] = [56, 57, 58, 59, 49, 50, 51, 42, 43, 35];
+
+pub const MAX_PIECES: usize = 4;
+pub const EXTENSION: &str = "ctb";
+pub const DRAW: u8 = 0;
+pub const INVALID: u8 = 254;
+pub const UNKNOWN: u8 = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    Draw,
+    Win,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    pub wdl: Wdl,
+    pub dtm: u8,
+}
+
+impl Probe {
+    pub fn decode(value: u8) -> Option<Self> {
+        match value {
+            DRAW => Some(Self {
+                wdl: Wdl::Draw,
+                dtm: 0,
+            }),
+            INVALID | UNKNOWN => None,
+            value => {
+                let dtm = value - 1;
+                Some(Self {
+                    wdl: if dtm % 2 == 1 { Wdl::Win } else { Wdl::Loss },
+                    dtm,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub signature: String,
+    pub pieces: Vec<Piece>,
+}
+
+impl Material {
+    pub fn parse(signature: &str) -> Result<Self> {
+        let upper = signature.to_ascii_uppercase();
+        let black_start = match upper[1..].find('K') {
+            Some(i) => i + 1,
+            None => bail!("invalid signature: {}", signature),
+        };
+        if !upper.starts_with('K') || upper.len() > MAX_PIECES || upper.len() < 2 {
+            bail!("invalid signature: {}", signature);
+        }
+
+        let mut pieces = Vec::new();
+        for (i, c) in upper.chars().enumerate() {
+            let color = if i < black_start {
+                Piece::White
+            } else {
+                Piece::Black
+            };
+            let kind = match PIECE_CHARS.iter().position(|&p| p == c) {
+                Some(index) => kind_from_index(index),
+                None => bail!("invalid piece '{}' in signature: {}", c, signature),
+            };
+            if kind == Piece::King && i != 0 && i != black_start {
+                bail!("invalid signature: {}", signature);
+            }
+            pieces.push(kind | color);
+        }
+
+        let mut pieces: Vec<(Piece, usize)> = pieces.into_iter().map(|piece| (piece, 0)).collect();
+        let material = canonical(&mut pieces, &mut true);
+        if material.signature != upper {
+            bail!(
+                "{} is not canonical, use {} instead",
+                signature,
+                material.signature
+            );
+        }
+
+        Ok(material)
+    }
+
+    pub fn has_pawns(&self) -> bool {
+        self.pieces
+            .iter()
+            .any(|piece| piece.split().0 == Piece::Pawn)
+    }
+
+    pub fn full_size(&self) -> usize {
+        2 * 64usize.pow(self.pieces.len() as u32)
+    }
+
+    pub fn reduced_size(&self) -> usize {
+        2 * self.king_squares() * 64usize.pow(self.pieces.len() as u32 - 1)
+    }
+
+    fn king_squares(&self) -> usize {
+        if self.has_pawns() {
+            32
+        } else {
+            TRIANGLE.len()
+        }
+    }
+
+    pub fn full_index(squares: &[usize], white_to_move: bool) -> usize {
+        squares
+            .iter()
+            .fold(usize::from(!white_to_move), |index, &square| {
+                index * 64 + square
+            })
+    }
+
+    pub fn decode_full_index(&self, index: usize, squares: &mut [usize]) -> bool {
+        let mut index = index;
+        for square in squares.iter_mut().rev() {
+            *square = index % 64;
+            index /= 64;
+        }
+        index == 0
+    }
+
+    pub fn reduced_index(&self, squares: &[usize], white_to_move: bool) -> usize {
+        let mut squares = squares.to_vec();
+        self.normalize(&mut squares);
+
+        let king = squares[0];
+        let king_slot = if self.has_pawns() {
+            (king / 8) * 4 + king % 8
+        } else {
+            TRIANGLE.iter().position(|&square| square == king).unwrap()
+        };
+
+        squares[1..].iter().fold(
+            usize::from(!white_to_move) * self.king_squares() + king_slot,
+            |index, &square| index * 64 + square,
+        )
+    }
+
+    pub fn decode_reduced_index(&self, index: usize, squares: &mut [usize]) -> bool {
+        let mut index = index;
+        for square in squares[1..].iter_mut().rev() {
+            *square = index % 64;
+            index /= 64;
+        }
+
+        let king_slot = index % self.king_squares();
+        squares[0] = if self.has_pawns() {
+            (king_slot / 4) * 8 + king_slot % 4
+        } else {
+            TRIANGLE[king_slot]
+        };
+        index / self.king_squares() == 0
+    }
+
+    fn normalize(&self, squares: &mut [usize]) {
+        let flip_file = |square: usize| square ^ 7;
+        let flip_rank = |square: usize| square ^ 56;
+        let transpose = |square: usize| {
+            let (x, r) = (square % 8, 7 - square / 8);
+            (7 - x) * 8 + r
+        };
+
+        if squares[0] % 8 > 3 {
+            squares
+                .iter_mut()
+                .for_each(|square| *square = flip_file(*square));
+        }
+        if self.has_pawns() {
+            return;
+        }
+        if 7 - squares[0] / 8 > 3 {
+            squares
+                .iter_mut()
+                .for_each(|square| *square = flip_rank(*square));
+        }
+        if 7 - squares[0] / 8 > squares[0] % 8 {
+            squares
+                .iter_mut()
+                .for_each(|square| *square = transpose(*square));
+        }
+    }
+}
+
+pub fn kind_from_index(index: usize) -> Piece {
+    [
+        Piece::Pawn,
+        Piece::LeftKnight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+        Piece::King,
+    ][index]
+}
+
+pub fn canonical(pieces: &mut [(Piece, usize)], white_to_move: &mut bool) -> Material {
+    let strength = |color: Piece, pieces: &[(Piece, usize)]| {
+        let mut indices: Vec<usize> = pieces
+            .iter()
+            .filter(|(piece, _)| piece.color() == color)
+            .map(|(piece, _)| piece.index())
+            .collect();
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        indices
+    };
+
+    let white = strength(Piece::White, pieces);
+    let black = strength(Piece::Black, pieces);
+    if (black.len(), &black) > (white.len(), &white) {
+        for (piece, square) in pieces.iter_mut() {
+            *piece = piece.split().0 | piece.color().ennemy();
+            *square ^= 56;
+        }
+        *white_to_move = !*white_to_move;
+    }
+
+    pieces.sort_by_key(|(piece, _)| (piece.is_black(), 5 - piece.index()));
+    for (piece, _) in pieces.iter_mut() {
+        *piece = kind_from_index(piece.index()) | piece.color();
+    }
+
+    let signature = pieces
+        .iter()
+        .map(|(piece, _)| PIECE_CHARS[piece.index()])
+        .collect();
+    Material {
+        signature,
+        pieces: pieces.iter().map(|(piece, _)| *piece).collect(),
+    }
+}
+
+pub struct Table {
+    material: Material,
+    data: Vec<u8>,
+}
+
+impl Table {
+    pub fn new(material: Material, full: &[u8]) -> Self {
+        let mut squares = vec![0; material.pieces.len()];
+        let data = (0..material.reduced_size())
+            .map(|index| {
+                let white_to_move = material.decode_reduced_index(index, &mut squares);
+                full[Material::full_index(&squares, white_to_move)]
+            })
+            .collect();
+
+        Self { material, data }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes =
+            fs::read(path).with_context(|| format!("could not read table {}", path.display()))?;
+        if bytes.len() < 9 || &bytes[..4] != MAGIC {
+            bail!("{} is not a tablebase file", path.display());
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != VERSION {
+            bail!(
+                "unsupported tablebase version {} in {}",
+                version,
+                path.display()
+            );
+        }
+
+        let signature_len = bytes[8] as usize;
+        if 9 + signature_len > bytes.len() {
+            bail!("{} is truncated", path.display());
+        }
+        let signature = String::from_utf8_lossy(&bytes[9..9 + signature_len]);
+        let material = Material::parse(&signature)?;
+        let data = bytes[9 + signature_len..].to_vec();
+        if data.len() != material.reduced_size() {
+            bail!("{} is truncated", path.display());
+        }
+
+        Ok(Self { material, data })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.push(self.material.signature.len() as u8);
+        bytes.extend_from_slice(self.material.signature.as_bytes());
+        bytes.extend_from_slice(&self.data);
+
+        let path = path.as_ref();
+        fs::write(path, bytes).with_context(|| format!("could not write table {}", path.display()))
+    }
+
+    pub fn get(&self, squares: &[usize], white_to_move: bool) -> u8 {
+        self.data[self.material.reduced_index(squares, white_to_move)]
+    }
+}
+
+pub struct Tablebase {
+    tables: HashMap<String, (PathBuf, OnceLock<Option<Table>>)>,
+}
+
+impl Tablebase {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut tables = HashMap::new();
+        for entry in fs::read_dir(dir)
+            .with_context(|| format!("could not read tablebase directory {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == EXTENSION)
+            {
+                if let Some(signature) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    tables.insert(
+                        signature.to_ascii_uppercase(),
+                        (path.clone(), OnceLock::new()),
+                    );
+                }
+            }
+        }
+
+        Ok(Self { tables })
+    }
+
+    pub fn probe(&self, board: &Board) -> Option<Probe> {
+        if board.piece_count() > MAX_PIECES || board.get_castling_rights().contains(&true) {
+            return None;
+        }
+        if let Some(square) = board.get_en_passant_square() {
+            let pushed = square ^ 8;
+            let pawn = Piece::Pawn | board.get_current_turn();
+            if (pushed % 8 > 0 && board.get_square(pushed - 1) == pawn)
+                || (pushed % 8 < 7 && board.get_square(pushed + 1) == pawn)
+            {
+                return None;
+            }
+        }
+
+        let mut pieces: Vec<(Piece, usize)> = (0..64)
+            .map(|square| (board.get_square(square), square))
+            .filter(|(piece, _)| !piece.is_none())
+            .collect();
+        let mut white_to_move = board.get_current_turn().is_white();
+        let material = canonical(&mut pieces, &mut white_to_move);
+        if material.pieces.len() == 2 {
+            return Probe::decode(DRAW);
+        }
+
+        let (path, table) = self.tables.get(&material.signature)?;
+        let table = table
+            .get_or_init(|| match Table::load(path) {
+                Ok(table) => Some(table),
+                Err(err) => {
+                    eprintln!("{:#}", err);
+                    None
+                }
+            })
+            .as_ref()?;
+
+        let squares: Vec<usize> = pieces.iter().map(|(_, square)| *square).collect();
+        Probe::decode(table.get(&squares, white_to_move))
+    }
+
+    pub fn best_move(&self, board: &Board) -> Option<(Move, Probe)> {
+        let root = self.probe(board)?;
+
+        let mut best: Option<(Move, Probe)> = None;
+        for m in board.clone().get_all_moves() {
+            let mut child = board.clone();
+            child.make_move(m);
+            let probe = self.probe(&child)?;
+
+            let better = match best {
+                None => true,
+                Some((_, best)) => match (probe.wdl, best.wdl) {
+                    (a, b) if a != b => a < b,
+                    (Wdl::Loss, _) => probe.dtm < best.dtm,
+                    (Wdl::Win, _) => probe.dtm > best.dtm,
+                    _ => false,
+                },
+            };
+            if better {
+                best = Some((m, probe));
+            }
+        }
+
+        best.map(|(m, _)| (m, root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn load_rejects_a_truncated_signature() {
+        let path = env::temp_dir().join(format!("chess-ai-truncated-{}.ctb", std::process::id()));
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.push(200);
+        bytes.extend(b"KQK");
+        fs::write(&path, bytes).unwrap();
+
+        let err = Table::load(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(err.to_string().contains("truncated"), "{}", err);
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    mem::size_of,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{board::Move, piece::Piece};
+
+use super::search::MATE_BOUND;
+
+const PROMOTIONS: [Piece; 5] = [
+    Piece::None,
+    Piece::LeftKnight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub key: u64,
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+impl Entry {
+    fn pack(&self) -> u64 {
+        let best_move = self
+            .best_move
+            .map(|m| {
+                let promotion = PROMOTIONS
+                    .iter()
+                    .position(|p| *p == m.promotion.split().0)
+                    .unwrap_or(0);
+                1 << 15 | (promotion as u64) << 12 | (m.from as u64) << 6 | m.to as u64
+            })
+            .unwrap_or(0);
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+
+        best_move
+            | (self.score as i16 as u16 as u64) << 16
+            | (self.depth as i8 as u8 as u64) << 32
+            | bound << 40
+    }
+
+    fn unpack(key: u64, data: u64) -> Self {
+        let best_move = if data & 1 << 15 != 0 {
+            Some(
+                Move::new((data >> 6 & 63) as usize, (data & 63) as usize)
+                    .with_promotion(PROMOTIONS[(data >> 12 & 7) as usize % PROMOTIONS.len()]),
+            )
+        } else {
+            None
+        };
+
+        Self {
+            key,
+            best_move,
+            score: (data >> 16) as u16 as i16 as i32,
+            depth: (data >> 32) as u8 as i8 as i32,
+            bound: match data >> 40 & 3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TranspositionTable {
+    entries: Vec<[AtomicU64; 2]>,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let len = (size_mb * 1024 * 1024 / size_of::<[AtomicU64; 2]>()).max(1);
+
+        Self {
+            entries: (0..len)
+                .map(|_| [AtomicU64::new(0), AtomicU64::new(0)])
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let [checked_key, data] = &self.entries[self.index(key)];
+        let data = data.load(Ordering::Relaxed);
+        if checked_key.load(Ordering::Relaxed) ^ data != key || data == 0 {
+            return None;
+        }
+
+        Some(Entry::unpack(key, data))
+    }
+
+    pub fn store(&self, entry: Entry) {
+        if self.is_empty() {
+            return;
+        }
+
+        let keep_move = if entry.best_move.is_none() {
+            self.probe(entry.key).and_then(|old| old.best_move)
+        } else {
+            None
+        };
+        let data = Entry {
+            best_move: entry.best_move.or(keep_move),
+            ..entry
+        }
+        .pack();
+
+        let [checked_key, stored_data] = &self.entries[self.index(entry.key)];
+        checked_key.store(entry.key ^ data, Ordering::Relaxed);
+        stored_data.store(data, Ordering::Relaxed);
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+}
+
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::OnceLock;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::board::Board;
+
+struct Keys {
+    pieces: [[u64; 64]; 12],
+    castling: [u64; 4],
+    en_passant: [u64; 8],
+    black_to_move: u64,
+}
+
+fn keys() -> &'static Keys {
+    static KEYS: OnceLock<Keys> = OnceLock::new();
+
+    KEYS.get_or_init(|| {
+        let mut rng = StdRng::seed_from_u64(0x0c4e55a1);
+        let mut keys = Keys {
+            pieces: [[0; 64]; 12],
+            castling: [0; 4],
+            en_passant: [0; 8],
+            black_to_move: rng.gen(),
+        };
+
+        keys.pieces
+            .iter_mut()
+            .flatten()
+            .chain(keys.castling.iter_mut())
+            .chain(keys.en_passant.iter_mut())
+            .for_each(|key| *key = rng.gen());
+
+        keys
+    })
+}
+
+pub fn hash(board: &Board) -> u64 {
+    let keys = keys();
+    let mut hash = 0;
+
+    for square_index in 0..64 {
+        let piece = board.get_square(square_index);
+        if !piece.is_none() {
+            let color = if piece.is_white() { 0 } else { 6 };
+            hash ^= keys.pieces[color + piece.index()][square_index];
+        }
+    }
+
+    for (right, key) in board.get_castling_rights().iter().zip(keys.castling) {
+        if *right {
+            hash ^= key;
+        }
+    }
+
+    if let Some(square_index) = board.get_en_passant_square() {
+        hash ^= keys.en_passant[square_index % 8];
+    }
+
+    if board.get_current_turn().is_black() {
+        hash ^= keys.black_to_move;
+    }
+
+    hash
+}
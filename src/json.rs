@@ -0,0 +1,255 @@
+use std::{iter::Peekable, str::Chars};
+
+use anyhow::{anyhow, bail, Result};
+
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+pub fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+    };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if let Some(c) = parser.chars.next() {
+        bail!("unexpected {:?} after the JSON value", c);
+    }
+    Ok(value)
+}
+
+pub fn string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+pub fn array<T>(items: impl IntoIterator<Item = T>, item: impl Fn(T) -> String) -> String {
+    let items: Vec<String> = items.into_iter().map(item).collect();
+    format!("[{}]", items.join(","))
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("JSON nested too deeply");
+        }
+
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => {
+                self.chars.next();
+                let mut fields = Vec::new();
+                if self.consume('}') {
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value(depth + 1)?));
+                    if self.consume('}') {
+                        return Ok(Value::Object(fields));
+                    }
+                    self.expect(',')?;
+                }
+            }
+            Some('[') => {
+                self.chars.next();
+                let mut items = Vec::new();
+                if self.consume(']') {
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    if self.consume(']') {
+                        return Ok(Value::Array(items));
+                    }
+                    self.expect(',')?;
+                }
+            }
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('n') => self.keyword("null", Value::Null),
+            Some(&c) if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !(c.is_ascii_digit() || "+-.eE".contains(c)) {
+                        break;
+                    }
+                    number.push(c);
+                    self.chars.next();
+                }
+                number
+                    .parse()
+                    .map(Value::Number)
+                    .map_err(|_| anyhow!("invalid number {}", number))
+            }
+            Some(c) => bail!("unexpected {:?} in JSON", c),
+            None => bail!("unexpected end of JSON"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        if self.chars.next() != Some('"') {
+            bail!("expected a JSON string");
+        }
+
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => {
+                        let code: String = self.chars.by_ref().take(4).collect();
+                        let code = u32::from_str_radix(&code, 16)
+                            .map_err(|_| anyhow!("invalid escape \\u{}", code))?;
+                        s.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    c => bail!("invalid escape {:?} in JSON string", c),
+                },
+                Some(c) => s.push(c),
+                None => bail!("unterminated JSON string"),
+            }
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value> {
+        for expected in keyword.chars() {
+            if self.chars.next() != Some(expected) {
+                bail!("invalid JSON literal, expected {}", keyword);
+            }
+        }
+        Ok(value)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        if !self.consume(expected) {
+            bail!("expected {:?} in JSON", expected);
+        }
+        Ok(())
+    }
+
+    fn consume(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&expected).is_some()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values() {
+        assert_eq!(
+            parse(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "d"}} "#).unwrap(),
+            Value::Object(vec![
+                (
+                    "a".to_string(),
+                    Value::Array(vec![
+                        Value::Number(1.),
+                        Value::Number(-25.),
+                        Value::Bool(true),
+                        Value::Null,
+                    ])
+                ),
+                (
+                    "b".to_string(),
+                    Value::Object(vec![("c".to_string(), Value::String("d".to_string()))])
+                ),
+            ])
+        );
+        assert_eq!(parse("[]").unwrap(), Value::Array(Vec::new()));
+        assert_eq!(parse("{}").unwrap(), Value::Object(Vec::new()));
+    }
+
+    #[test]
+    fn parses_numbers() {
+        for (text, number) in [
+            ("0", 0.),
+            ("-7", -7.),
+            ("3.25", 3.25),
+            ("1e3", 1000.),
+            ("2E-2", 0.02),
+        ] {
+            assert_eq!(parse(text).unwrap(), Value::Number(number), "{}", text);
+        }
+        for text in ["-", "1.2.3", "1e", "--1", "+1", ".5"] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(
+            parse(r#""a\"b\\c\/d\n\t\u00e9\u0041""#).unwrap(),
+            Value::String("a\"b\\c/d\n\t\u{e9}A".to_string())
+        );
+        for text in [r#""\x""#, r#""\u12""#, r#""\uzzzz""#, r#""open"#] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+
+        let original = "quote \" backslash \\ bell \u{7}";
+        assert_eq!(
+            parse(&string(original)).unwrap(),
+            Value::String(original.to_string())
+        );
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 2)).is_err());
+    }
+
+    #[test]
+    fn rejects_trailing_garbage() {
+        for text in [
+            "{} x",
+            "[1] [2]",
+            "nulls",
+            "true false",
+            "\"a\"\"b\"",
+            "[1,]",
+            "{\"a\":1,}",
+            "",
+        ] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+}
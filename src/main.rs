@@ -1,77 +1,351 @@
-use std::time::Duration;
+// num-derive 0.3 generates its FromPrimitive impl inside a const block
+#![allow(non_local_definitions)]
 
-use anyhow::Result;
-use board::Board;
+use std::{env, sync::Arc, thread, time::Duration};
+
+use anyhow::{bail, Result};
+use board::{Board, Opponent};
+use engine::{
+    book::{self, Book},
+    eval::{self, Weights},
+    external::External,
+    nnue::{Network, Nnue},
+    personality::{self, Personality},
+    search::Limits,
+    strength::Strength,
+    syzygy::Syzygy,
+    tablebase::Tablebase,
+};
+use net::{Clock, Peer};
+use piece::Piece;
 use piston_window::{
-    Button, ButtonState, Event, EventSettings, Events, Input, Motion, MouseButton, RenderEvent,
-    UpdateEvent,
+    AdvancedWindow, Button, ButtonState, Event, EventSettings, Events, Input, Key, Motion,
+    MouseButton, RenderEvent, UpdateEvent,
 };
-use render::{piece::texture_bank, Render};
+use render::{analysis, piece::texture_bank, Render};
+use spectate::Spectators;
 use window::window;
 
 extern crate find_folder;
+extern crate memmap2;
 extern crate num;
 #[macro_use]
 extern crate num_derive;
-#[macro_use]
-extern crate concat_arrays;
 extern crate anyhow;
 extern crate piston_window;
 extern crate rand;
 extern crate rodio;
 
+mod api;
+mod bench;
 mod board;
+mod book_build;
+mod engine;
+mod json;
+mod match_play;
+mod net;
+mod pgn;
 mod piece;
 mod render;
+mod server;
+mod spectate;
+mod tablebase_gen;
+mod tournament;
+mod tui;
+mod tune;
+mod uci;
 mod window;
+mod xboard;
 
 fn main() -> Result<()> {
-    let mut window = window()?;
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    let mut board = Board::default();
+    if let Some(path) = eval::weights_file().filter(|path| path.exists()) {
+        eval::set_weights(Weights::load(path)?);
+    }
+    if let Some(dir) = personality::personalities_dir().filter(|dir| dir.exists()) {
+        personality::set_personalities(Personality::load_all(dir)?);
+    }
+
+    match args.first().map(String::as_str) {
+        Some("bench") => bench::run(&args[1..]),
+        Some("book") => book_build::run(&args[1..]),
+        Some("match") => match_play::run(&args[1..]),
+        Some("serve") => api::run(&args[1..]),
+        Some("server") => server::run(&args[1..]),
+        Some("tablebase") => tablebase_gen::run(&args[1..]),
+        Some("tournament") => tournament::run(&args[1..]),
+        Some("tune") => tune::run(&args[1..]),
+        Some("uci") => uci::run(&args[1..]),
+        Some("xboard") => xboard::run(&args[1..]),
+        _ => play(&args),
+    }
+}
+
+fn play(args: &[String]) -> Result<()> {
+    let mut white_opponent = Opponent::Computer;
+    let mut black_opponent = Opponent::Computer;
+    let mut network = None;
+    let mut book_path = None;
+    let mut engine_path = None;
+    let mut tablebase = None;
+    let mut syzygy = None;
+    let mut strength = Strength::default();
+    let mut ponder = true;
+    let mut multi_pv = 1;
+    let mut mcts_iterations = None;
+    let mut mcts_time = None;
+    let mut book_depth = book::DEFAULT_DEPTH;
+    let mut book_variety = book::DEFAULT_VARIETY;
+    let mut host = None;
+    let mut connect = None;
+    let mut color = Piece::White;
+    let mut clock = None;
+    let mut spectate = None;
+    let mut tui = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--white" => white_opponent = opponent_arg(args.next())?,
+            "--black" => black_opponent = opponent_arg(args.next())?,
+            "--nnue" => match args.next() {
+                Some(path) => network = Some(Network::load(path)?),
+                None => bail!("--nnue expects a network file"),
+            },
+            "--tablebases" => match args.next() {
+                Some(dir) => tablebase = Some(Arc::new(Tablebase::open(dir)?)),
+                None => bail!("--tablebases expects a directory"),
+            },
+            "--syzygy" => match args.next() {
+                Some(dir) => syzygy = Some(Arc::new(Syzygy::open(dir)?)),
+                None => bail!("--syzygy expects a directory"),
+            },
+            "--level" => match args.next().map(|level| level.parse()) {
+                Some(Ok(level)) => strength = Strength::new(level)?,
+                _ => bail!("--level expects a number"),
+            },
+            "--elo" => match args.next().map(|elo| elo.parse()) {
+                Some(Ok(elo)) => strength = Strength::from_elo(elo),
+                _ => bail!("--elo expects a rating"),
+            },
+            "--no-ponder" => ponder = false,
+            "--multipv" => match args.next().map(|multi_pv| multi_pv.parse()) {
+                Some(Ok(value)) => multi_pv = value,
+                _ => bail!("--multipv expects a number of lines"),
+            },
+            "--mcts-iterations" => match args.next().map(|iterations| iterations.parse()) {
+                Some(Ok(iterations)) => mcts_iterations = Some(iterations),
+                _ => bail!("--mcts-iterations expects a number of playouts"),
+            },
+            "--mcts-time" => match args.next().map(|movetime| movetime.parse()) {
+                Some(Ok(movetime)) => mcts_time = Some(Duration::from_millis(movetime)),
+                _ => bail!("--mcts-time expects a number of milliseconds"),
+            },
+            "--engine" => match args.next() {
+                Some(path) => engine_path = Some(path),
+                None => bail!("--engine expects a UCI engine executable"),
+            },
+            "--book" => match args.next() {
+                Some(path) => book_path = Some(path),
+                None => bail!("--book expects a Polyglot book file"),
+            },
+            "--book-depth" => match args.next().map(|depth| depth.parse()) {
+                Some(Ok(depth)) => book_depth = depth,
+                _ => bail!("--book-depth expects a number of plies"),
+            },
+            "--book-variety" => match args.next().map(|variety| variety.parse()) {
+                Some(Ok(variety)) => book_variety = variety,
+                _ => bail!("--book-variety expects a number between 0 and 1"),
+            },
+            "--host" => match args.next() {
+                Some(address) => host = Some(address),
+                None => bail!("--host expects an address such as 0.0.0.0:7878"),
+            },
+            "--connect" => match args.next() {
+                Some(address) => connect = Some(address),
+                None => bail!("--connect expects an address such as 192.168.1.2:7878"),
+            },
+            "--color" => match args.next().map(String::as_str) {
+                Some("white") => color = Piece::White,
+                Some("black") => color = Piece::Black,
+                _ => bail!("--color expects white or black"),
+            },
+            "--clock" => match args.next().map(|tc| Clock::parse(tc)) {
+                Some(Some(value)) => clock = Some(value),
+                _ => bail!("--clock expects minutes[+increment]"),
+            },
+            "--spectate" => match args.next() {
+                Some(address) => spectate = Some(address),
+                None => bail!("--spectate expects an address such as 0.0.0.0:8080"),
+            },
+            "--tui" => tui = true,
+            arg => bail!("unexpected argument: {}", arg),
+        }
+    }
+
+    let book = match book_path {
+        Some(path) => {
+            let mut book = Book::open(path)?;
+            book.set_depth(book_depth);
+            book.set_variety(book_variety);
+            Some(book)
+        }
+        None => None,
+    };
+
+    let external = match engine_path {
+        Some(path) => Some(External::spawn(path)?),
+        None if [white_opponent, black_opponent].contains(&Opponent::Engine) => {
+            bail!("the engine opponent needs --engine")
+        }
+        None => None,
+    };
+
+    let mut game = match (host, connect) {
+        (Some(_), Some(_)) => bail!("--host and --connect cannot be used together"),
+        (Some(address), None) => Some(net::Game::new(Peer::host(address)?, color, clock)),
+        (None, Some(address)) => Some(net::Game::new(Peer::connect(address)?, color, clock)),
+        (None, None) => None,
+    };
+    if game.is_some() {
+        white_opponent = Opponent::Remote;
+        black_opponent = Opponent::Remote;
+    }
+
+    let mut board = Board::with_opponents(white_opponent, black_opponent);
+    board.set_threads(thread::available_parallelism().map_or(1, |n| n.get()));
+    if let Some(network) = network {
+        board.set_evaluator(Arc::new(Nnue::new(network)));
+    }
+    if let Some(book) = book {
+        board.set_book(book);
+    }
+    if let Some(external) = external {
+        println!("playing against {}", external.name());
+        board.set_external(external);
+    }
+    if let Some(tablebase) = tablebase {
+        board.set_tablebase(tablebase);
+    }
+    if let Some(syzygy) = syzygy {
+        board.set_syzygy(syzygy);
+    }
+    if strength.is_limited() {
+        println!(
+            "computer playing at level {} (about {} Elo)",
+            strength.level(),
+            strength.elo()
+        );
+    }
+    board.set_strength(strength);
+    board.set_ponder(ponder);
+    board.set_multi_pv(multi_pv);
+    if mcts_iterations.is_some() || mcts_time.is_some() {
+        board.set_mcts_budget(Limits {
+            depth: None,
+            nodes: mcts_iterations,
+            movetime: mcts_time,
+        });
+    }
+
+    let mut spectators = match spectate {
+        Some(address) => Some(Spectators::listen(address, &board)?),
+        None => None,
+    };
+    let mut show_analysis = multi_pv > 1;
+
+    if tui {
+        return tui::run(board, game, spectators, show_analysis);
+    }
+    let mut window = match window() {
+        Ok(window) => window,
+        Err(err) => {
+            eprintln!("{:#}, falling back to the terminal", err);
+            return tui::run(board, game, spectators, show_analysis);
+        }
+    };
 
     let mut texture_context = window.create_texture_context();
     let texture_bank = texture_bank(&mut texture_context);
+    let mut glyphs = analysis::glyphs(&mut window)?;
 
     let mut events = Events::new(EventSettings::new());
     let mut mouse_pos = [0.0; 2];
     let mut window_size = window::SIZE;
+    let mut title = String::new();
     while let Some(e) = events.next(&mut window) {
         if let Some(args) = e.render_args() {
-            window.draw_2d(&e, |c, g, _| {
+            window.draw_2d(&e, |c, g, device| {
                 board.render(args, c, g, &texture_bank, mouse_pos);
+                if show_analysis {
+                    analysis::render(&board.analysis(), args.window_size[0], c, g, &mut glyphs);
+                    glyphs.factory.encoder.flush(device);
+                }
             });
         }
 
         if let Some(args) = e.update_args() {
+            if let Some(game) = game.as_mut() {
+                game.update(&mut board);
+                let new_title = game.title();
+                if new_title != title {
+                    window.set_title(new_title.clone());
+                    title = new_title;
+                }
+            }
             board.update(Duration::from_secs_f64(args.dt));
+            if let Some(spectators) = spectators.as_mut() {
+                spectators.update(&board, game.as_ref().and_then(|game| game.clocks()));
+            }
         }
 
         if let Event::Input(input, _) = e {
             match input {
                 Input::Resize(args) => window_size = args.window_size,
-                Input::Button(args) => {
-                    if args.button == Button::Mouse(MouseButton::Left) {
-                        match args.state {
-                            ButtonState::Press => board.mouse_press(
-                                mouse_pos[0] / window_size[0],
-                                mouse_pos[1] / window_size[1],
-                            ),
-                            ButtonState::Release => board.mouse_relase(
-                                mouse_pos[0] / window_size[0],
-                                mouse_pos[1] / window_size[1],
-                            ),
-                        }
+                Input::Button(args) if args.button == Button::Mouse(MouseButton::Left) => {
+                    match args.state {
+                        ButtonState::Press => board.mouse_press(
+                            mouse_pos[0] / window_size[0],
+                            mouse_pos[1] / window_size[1],
+                        ),
+                        ButtonState::Release => board.mouse_relase(
+                            mouse_pos[0] / window_size[0],
+                            mouse_pos[1] / window_size[1],
+                        ),
                     }
                 }
-                Input::Move(motion) => match motion {
-                    Motion::MouseCursor(pos) => mouse_pos = pos,
+                Input::Button(args) if args.state == ButtonState::Press => match args.button {
+                    Button::Keyboard(key) if game.is_some() => {
+                        let game = game.as_mut().unwrap();
+                        match key {
+                            Key::U | Key::Backspace => game.takeback(&mut board),
+                            Key::D => game.offer_draw(&mut board),
+                            Key::G => game.resign(&mut board),
+                            Key::N => game.decline(),
+                            Key::A => show_analysis = !show_analysis,
+                            _ => (),
+                        }
+                    }
+                    Button::Keyboard(Key::R) => board.reset(),
+                    Button::Keyboard(Key::U | Key::Backspace) => board.undo(),
+                    Button::Keyboard(Key::A) => show_analysis = !show_analysis,
                     _ => (),
                 },
+                Input::Move(Motion::MouseCursor(pos)) => mouse_pos = pos,
                 _ => (),
             }
         }
     }
 
+    board.stop_thinking();
+
     Ok(())
 }
+
+fn opponent_arg(arg: Option<&String>) -> Result<Opponent> {
+    match arg {
+        Some(arg) => arg.parse(),
+        None => bail!("expected an opponent: player, computer, mcts, engine or a personality"),
+    }
+}
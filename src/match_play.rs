@@ -0,0 +1,840 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    board::{Board, Move, Status},
+    engine::{
+        eval::{Evaluator, Handcrafted},
+        external::External,
+        mcts::Mcts,
+        nnue::{Network, Nnue},
+        personality::{self, Personality},
+        search::{self, Limits, Line, Options, Search, DEFAULT_HASH_MB},
+        strength::Strength,
+        syzygy::Syzygy,
+        tablebase::{Tablebase, Wdl},
+    },
+    pgn::{self, Game, Outcome},
+    piece::Piece,
+};
+
+const DEFAULT_GAMES: usize = 100;
+const DEFAULT_MOVETIME: u64 = 100;
+const DEFAULT_MAX_PLIES: usize = 400;
+const DEFAULT_PGN: &str = "match.pgn";
+const FIFTY_MOVE_PLIES: usize = 100;
+const DRAW_MIN_PLIES: usize = 60;
+const SPRT_ALPHA: f64 = 0.05;
+const SPRT_BETA: f64 = 0.05;
+const CONFIDENCE: f64 = 1.96;
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+enum Kind {
+    Search(Option<&'static Personality>),
+    MonteCarlo,
+    External(PathBuf),
+}
+
+pub struct Spec {
+    pub name: String,
+    kind: Kind,
+    options: Options,
+    evaluator: Arc<dyn Evaluator>,
+    strength: Strength,
+    hash_mb: usize,
+}
+
+impl Spec {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut parts = spec.split(',');
+        let kind = match parts.next().unwrap_or_default() {
+            "computer" => Kind::Search(None),
+            "mcts" => Kind::MonteCarlo,
+            kind => match kind.split_once('=') {
+                Some(("uci", path)) => Kind::External(PathBuf::from(path)),
+                _ => match personality::find(kind) {
+                    Some(personality) => Kind::Search(Some(personality)),
+                    None => bail!(
+                        "unknown engine: {} (expected computer, mcts, uci=<path> or a personality)",
+                        kind
+                    ),
+                },
+            },
+        };
+
+        let mut parsed = Self {
+            name: spec.to_string(),
+            kind,
+            options: Options::default(),
+            evaluator: Arc::new(Handcrafted),
+            strength: Strength::default(),
+            hash_mb: DEFAULT_HASH_MB,
+        };
+        for part in parts {
+            let (key, value) = part.split_once('=').unwrap_or((part, ""));
+            let invalid = || format!("invalid value {} for {} in {}", value, key, spec);
+            match key {
+                "name" => parsed.name = value.to_string(),
+                "nnue" => parsed.evaluator = Arc::new(Nnue::new(Network::load(value)?)),
+                "level" => parsed.strength = Strength::new(value.parse().with_context(invalid)?)?,
+                "elo" => parsed.strength = Strength::from_elo(value.parse().with_context(invalid)?),
+                "hash" => parsed.hash_mb = value.parse().with_context(invalid)?,
+                "no-null-move" => parsed.options.null_move = false,
+                "no-lmr" => parsed.options.late_move_reductions = false,
+                "no-futility" => parsed.options.futility_pruning = false,
+                "no-reverse-futility" => parsed.options.reverse_futility_pruning = false,
+                "no-check-extensions" => parsed.options.check_extensions = false,
+                _ => bail!("unknown engine option {} in {}", key, spec),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+pub enum Player {
+    Search(Box<Search>, Arc<Mutex<Option<i32>>>),
+    MonteCarlo(Box<Mcts>),
+    External(External),
+}
+
+impl Player {
+    pub fn new(spec: &Spec) -> Result<Self> {
+        match &spec.kind {
+            Kind::Search(personality) => {
+                let score = Arc::new(Mutex::new(None));
+                let reported = score.clone();
+
+                let mut search = Search::new(spec.options);
+                search.set_evaluator(spec.evaluator.clone());
+                search.set_strength(spec.strength);
+                search.set_personality(*personality);
+                search.set_hash(spec.hash_mb);
+                search.set_reporter(Arc::new(move |lines: &[Line]| {
+                    *reported.lock().unwrap() = lines.first().map(|line| line.score);
+                }));
+                Ok(Self::Search(Box::new(search), score))
+            }
+            Kind::MonteCarlo => Ok(Self::MonteCarlo(Box::default())),
+            Kind::External(path) => Ok(Self::External(External::spawn(path)?)),
+        }
+    }
+
+    pub fn new_game(&mut self) -> Result<()> {
+        match self {
+            Self::Search(search, _) => search.clear(),
+            Self::MonteCarlo(mcts) => **mcts = Mcts::default(),
+            Self::External(external) => external.new_game()?,
+        }
+        Ok(())
+    }
+
+    pub fn think(&mut self, board: &Board, limits: Limits) -> Result<(Option<Move>, Option<i32>)> {
+        let stop = AtomicBool::new(false);
+        match self {
+            Self::Search(search, score) => {
+                *score.lock().unwrap() = None;
+                let best_move = search.think_until(board, limits, &stop);
+                Ok((best_move, *score.lock().unwrap()))
+            }
+            Self::MonteCarlo(mcts) => Ok((mcts.think_until(board, limits, &stop), None)),
+            Self::External(external) => {
+                let best_move = external.think_until(board, limits, &stop)?;
+                Ok((best_move, external.score()))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TimeControl {
+    PerMove(Limits),
+    Clock(Duration, Duration),
+}
+
+pub struct Settings {
+    time_control: TimeControl,
+    max_plies: usize,
+    resign: Option<(usize, i32)>,
+    draw: Option<(usize, i32)>,
+    tablebase: Option<Arc<Tablebase>>,
+    syzygy: Option<Arc<Syzygy>>,
+    pub concurrency: usize,
+    openings_path: Option<PathBuf>,
+    opening_plies: Option<usize>,
+}
+
+impl Settings {
+    pub fn parse_arg<'a>(
+        &mut self,
+        arg: &str,
+        args: &mut impl Iterator<Item = &'a String>,
+    ) -> Result<bool> {
+        match arg {
+            "--concurrency" => match args.next().map(|concurrency| concurrency.parse()) {
+                Some(Ok(value)) if value > 0 => self.concurrency = value,
+                _ => bail!("--concurrency expects a positive number"),
+            },
+            "--movetime" => match args.next().map(|movetime| movetime.parse()) {
+                Some(Ok(movetime)) => {
+                    self.time_control =
+                        TimeControl::PerMove(Limits::movetime(Duration::from_millis(movetime)))
+                }
+                _ => bail!("--movetime expects a number of milliseconds"),
+            },
+            "--depth" => match args.next().map(|depth| depth.parse()) {
+                Some(Ok(depth)) => self.time_control = TimeControl::PerMove(Limits::depth(depth)),
+                _ => bail!("--depth expects a number"),
+            },
+            "--nodes" => match args.next().map(|nodes| nodes.parse()) {
+                Some(Ok(nodes)) => {
+                    self.time_control = TimeControl::PerMove(Limits {
+                        nodes: Some(nodes),
+                        ..Limits::default()
+                    })
+                }
+                _ => bail!("--nodes expects a number"),
+            },
+            "--tc" => match args.next().map(|tc| parse_clock(tc)) {
+                Some(Some((base, increment))) => {
+                    self.time_control = TimeControl::Clock(base, increment)
+                }
+                _ => bail!("--tc expects seconds[+increment]"),
+            },
+            "--openings" => match args.next() {
+                Some(path) => self.openings_path = Some(PathBuf::from(path)),
+                None => bail!("--openings expects an EPD or PGN file"),
+            },
+            "--opening-plies" => match args.next().map(|plies| plies.parse()) {
+                Some(Ok(plies)) => self.opening_plies = Some(plies),
+                _ => bail!("--opening-plies expects a number"),
+            },
+            "--max-plies" => match args.next().map(|plies| plies.parse()) {
+                Some(Ok(plies)) => self.max_plies = plies,
+                _ => bail!("--max-plies expects a number"),
+            },
+            "--resign" => match adjudication_arg(args) {
+                Some(resign) => self.resign = Some(resign),
+                None => bail!("--resign expects a number of moves and a score"),
+            },
+            "--draw" => match adjudication_arg(args) {
+                Some(draw) => self.draw = Some(draw),
+                None => bail!("--draw expects a number of moves and a score"),
+            },
+            "--tablebases" => match args.next() {
+                Some(dir) => self.tablebase = Some(Arc::new(Tablebase::open(dir)?)),
+                None => bail!("--tablebases expects a directory"),
+            },
+            "--syzygy" => match args.next() {
+                Some(dir) => self.syzygy = Some(Arc::new(Syzygy::open(dir)?)),
+                None => bail!("--syzygy expects a directory"),
+            },
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    pub fn openings(&self) -> Result<Vec<Opening>> {
+        let openings = match &self.openings_path {
+            Some(path) => load_openings(path, self.opening_plies)?,
+            None => vec![Opening {
+                fen: START_FEN.to_string(),
+                moves: Vec::new(),
+            }],
+        };
+        if openings.is_empty() {
+            bail!("no openings found");
+        }
+
+        Ok(openings)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            time_control: TimeControl::PerMove(Limits::movetime(Duration::from_millis(
+                DEFAULT_MOVETIME,
+            ))),
+            max_plies: DEFAULT_MAX_PLIES,
+            resign: None,
+            draw: None,
+            tablebase: None,
+            syzygy: None,
+            concurrency: 1,
+            openings_path: None,
+            opening_plies: None,
+        }
+    }
+}
+
+pub struct Opening {
+    fen: String,
+    moves: Vec<Move>,
+}
+
+struct Finished {
+    round: usize,
+    game: Game,
+    termination: String,
+    first_score: f64,
+}
+
+#[derive(Default)]
+struct Stats {
+    wins: usize,
+    draws: usize,
+    losses: usize,
+}
+
+impl Stats {
+    fn add(&mut self, score: f64) {
+        match score {
+            score if score > 0.5 => self.wins += 1,
+            score if score < 0.5 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+    }
+
+    fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.) / self.games() as f64
+    }
+
+    fn variance(&self) -> f64 {
+        let score = self.score();
+        (self.wins as f64 * (1. - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / self.games() as f64
+    }
+
+    fn elo(&self) -> (f64, f64) {
+        let score = self.score();
+        let deviation = (self.variance() / self.games() as f64).sqrt();
+        let low = elo(score - CONFIDENCE * deviation);
+        let high = elo(score + CONFIDENCE * deviation);
+        (elo(score), (high - low) / 2.)
+    }
+
+    fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        let variance = self.variance();
+        if self.games() == 0 || variance == 0. {
+            return 0.;
+        }
+
+        let score0 = expected_score(elo0);
+        let score1 = expected_score(elo1);
+        self.games() as f64 * (score1 - score0) * (2. * self.score() - score0 - score1)
+            / (2. * variance)
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (elo, error) = self.elo();
+        write!(
+            f,
+            "+{} ={} -{} score {:.1}% elo {:.1} +/- {:.1}",
+            self.wins,
+            self.draws,
+            self.losses,
+            self.score() * 100.,
+            elo,
+            error
+        )
+    }
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut first = "computer".to_string();
+    let mut second = "computer".to_string();
+    let mut games = DEFAULT_GAMES;
+    let mut pgn_path = PathBuf::from(DEFAULT_PGN);
+    let mut sprt = None;
+    let mut settings = Settings::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--first" => match args.next() {
+                Some(spec) => first = spec.clone(),
+                None => bail!("--first expects an engine"),
+            },
+            "--second" => match args.next() {
+                Some(spec) => second = spec.clone(),
+                None => bail!("--second expects an engine"),
+            },
+            "--games" => match args.next().map(|games| games.parse()) {
+                Some(Ok(value)) => games = value,
+                _ => bail!("--games expects a number"),
+            },
+            "--sprt" => match (
+                args.next().map(|elo0| elo0.parse()),
+                args.next().map(|elo1| elo1.parse()),
+            ) {
+                (Some(Ok(elo0)), Some(Ok(elo1))) => sprt = Some((elo0, elo1)),
+                _ => bail!("--sprt expects two Elo bounds"),
+            },
+            "--pgn" => match args.next() {
+                Some(path) => pgn_path = PathBuf::from(path),
+                None => bail!("--pgn expects a file"),
+            },
+            arg => {
+                if !settings.parse_arg(arg, &mut args)? {
+                    bail!("unexpected argument: {}", arg);
+                }
+            }
+        }
+    }
+
+    let specs = [Spec::parse(&first)?, Spec::parse(&second)?];
+    let openings = settings.openings()?;
+
+    let mut output = BufWriter::new(
+        File::create(&pgn_path)
+            .with_context(|| format!("could not create {}", pgn_path.display()))?,
+    );
+    println!(
+        "{} vs {}: {} games, {} openings, concurrency {}",
+        specs[0].name,
+        specs[1].name,
+        games,
+        openings.len(),
+        settings.concurrency
+    );
+
+    let next = AtomicUsize::new(0);
+    let abort = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    let mut stats = Stats::default();
+
+    let result = thread::scope(|scope| -> Result<()> {
+        for _ in 0..settings.concurrency.min(games) {
+            let sender = sender.clone();
+            let (specs, openings, settings) = (&specs, &openings, &settings);
+            let (next, abort) = (&next, &abort);
+            scope.spawn(move || {
+                let result = worker(specs, openings, settings, games, next, abort, &sender);
+                if let Err(err) = result {
+                    let _ = sender.send(Err(err));
+                }
+            });
+        }
+        drop(sender);
+
+        for finished in receiver {
+            let finished = match finished {
+                Ok(finished) => finished,
+                Err(err) => {
+                    abort.store(true, Ordering::Relaxed);
+                    return Err(err);
+                }
+            };
+
+            stats.add(finished.first_score);
+            writeln!(output, "{}", finished.game)?;
+            output.flush()?;
+
+            println!(
+                "game {:>4}: {} - {} {} ({}) | {}",
+                finished.round,
+                finished.game.tag("White").unwrap_or("?"),
+                finished.game.tag("Black").unwrap_or("?"),
+                finished.game.outcome.token(),
+                finished.termination,
+                stats
+            );
+
+            if let Some((elo0, elo1)) = sprt {
+                let llr = stats.llr(elo0, elo1);
+                let lower = (SPRT_BETA / (1. - SPRT_ALPHA)).ln();
+                let upper = ((1. - SPRT_BETA) / SPRT_ALPHA).ln();
+                println!("sprt: llr {:.2} ({:.2}, {:.2})", llr, lower, upper);
+                if llr <= lower || llr >= upper {
+                    println!(
+                        "sprt: {} accepted after {} games",
+                        if llr >= upper { "H1" } else { "H0" },
+                        stats.games()
+                    );
+                    abort.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    });
+    result?;
+
+    if stats.games() > 0 {
+        println!("{} vs {}: {}", specs[0].name, specs[1].name, stats);
+    }
+    println!("games written to {}", pgn_path.display());
+
+    Ok(())
+}
+
+fn worker(
+    specs: &[Spec; 2],
+    openings: &[Opening],
+    settings: &Settings,
+    games: usize,
+    next: &AtomicUsize,
+    abort: &AtomicBool,
+    sender: &mpsc::Sender<Result<Finished>>,
+) -> Result<()> {
+    let mut players = [Player::new(&specs[0])?, Player::new(&specs[1])?];
+
+    loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        if index >= games || abort.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let opening = &openings[index / 2 % openings.len()];
+        let swapped = index % 2 == 1;
+        let [first, second] = &mut players;
+        let (seats, names) = if swapped {
+            ([second, first], [&specs[1].name, &specs[0].name])
+        } else {
+            ([first, second], [&specs[0].name, &specs[1].name])
+        };
+        let finished = match play_game(seats, names.map(String::as_str), opening, settings, abort)?
+        {
+            Some((mut game, termination)) => {
+                game.tags
+                    .insert(1, ("Round".to_string(), (index + 1).to_string()));
+                let white_score = white_score(game.outcome);
+                Finished {
+                    round: index + 1,
+                    game,
+                    termination,
+                    first_score: if swapped {
+                        1. - white_score
+                    } else {
+                        white_score
+                    },
+                }
+            }
+            None => return Ok(()),
+        };
+
+        if sender.send(Ok(finished)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+pub fn play_game(
+    mut players: [&mut Player; 2],
+    names: [&str; 2],
+    opening: &Opening,
+    settings: &Settings,
+    abort: &AtomicBool,
+) -> Result<Option<(Game, String)>> {
+    for player in players.iter_mut() {
+        player.new_game()?;
+    }
+
+    let mut board = Board::from_fen(&opening.fen)?;
+    let mut moves = Vec::new();
+    let mut repetitions: HashMap<u64, usize> = HashMap::new();
+    let mut quiet_plies = 0;
+    let mut play = |board: &mut Board, m: Move| {
+        moves.push(pgn::to_san(board, m));
+        let zeroing = board.is_capture(m) || board.get_square(m.from).split().0 == Piece::Pawn;
+        quiet_plies = if zeroing { 0 } else { quiet_plies + 1 };
+        board.make_move(m);
+        board.update_status();
+        *repetitions.entry(board.hash()).or_default() += 1;
+        (quiet_plies, repetitions[&board.hash()])
+    };
+
+    let mut state = (0, 1);
+    for &m in &opening.moves {
+        state = play(&mut board, m);
+    }
+
+    let mut clocks = match settings.time_control {
+        TimeControl::Clock(base, _) => [base; 2],
+        TimeControl::PerMove(_) => [Duration::ZERO; 2],
+    };
+    let mut resign_counts = [0; 2];
+    let mut draw_count = 0;
+    let start_ply = board.get_ply();
+
+    let (outcome, termination) = loop {
+        let turn = board.get_current_turn();
+        let side = usize::from(!turn.is_white());
+        let wins = if turn.is_white() {
+            Outcome::WhiteWins
+        } else {
+            Outcome::BlackWins
+        };
+        let loses = if turn.is_white() {
+            Outcome::BlackWins
+        } else {
+            Outcome::WhiteWins
+        };
+
+        match board.get_status() {
+            Status::Checkmate => break (loses, "checkmate".to_string()),
+            Status::Stalemate => break (Outcome::Draw, "stalemate".to_string()),
+            Status::Dead => break (Outcome::Draw, "insufficient material".to_string()),
+            Status::Playing | Status::Ended => (),
+        }
+        if state.1 >= 3 {
+            break (Outcome::Draw, "threefold repetition".to_string());
+        }
+        if state.0 >= FIFTY_MOVE_PLIES {
+            break (Outcome::Draw, "fifty move rule".to_string());
+        }
+        if board.get_ply() - start_ply >= settings.max_plies {
+            break (Outcome::Draw, "adjudication: maximum length".to_string());
+        }
+        if let Some(wdl) = probe(settings, &board) {
+            let outcome = match wdl {
+                Wdl::Win => wins,
+                Wdl::Draw => Outcome::Draw,
+                Wdl::Loss => loses,
+            };
+            break (outcome, "adjudication: tablebase".to_string());
+        }
+
+        let limits = match settings.time_control {
+            TimeControl::PerMove(limits) => limits,
+            TimeControl::Clock(_, increment) => Limits::movetime(search::allocate_time(
+                clocks[side],
+                Some(clocks[1 - side]),
+                increment,
+                None,
+            )),
+        };
+        if abort.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let thinking_start = Instant::now();
+        let (best_move, score) = players[side].think(&board, limits)?;
+
+        if let TimeControl::Clock(_, increment) = settings.time_control {
+            let elapsed = thinking_start.elapsed();
+            if elapsed > clocks[side] {
+                break (loses, "time forfeit".to_string());
+            }
+            clocks[side] = clocks[side] - elapsed + increment;
+        }
+
+        let best_move = match best_move {
+            Some(best_move) => best_move,
+            None => break (loses, "no move returned".to_string()),
+        };
+
+        if let Some((moves, threshold)) = settings.resign {
+            resign_counts[side] = match score {
+                Some(score) if score <= -threshold => resign_counts[side] + 1,
+                _ => 0,
+            };
+            if resign_counts[side] >= moves {
+                break (loses, "adjudication: resignation".to_string());
+            }
+        }
+        if let Some((moves, threshold)) = settings.draw {
+            draw_count = match score {
+                Some(score) if score.abs() <= threshold && board.get_ply() >= DRAW_MIN_PLIES => {
+                    draw_count + 1
+                }
+                _ => 0,
+            };
+            if draw_count >= moves * 2 {
+                break (Outcome::Draw, "adjudication: draw".to_string());
+            }
+        }
+
+        state = play(&mut board, best_move);
+    };
+
+    let mut tags = vec![
+        ("Event".to_string(), "chess-ai match".to_string()),
+        ("White".to_string(), names[0].to_string()),
+        ("Black".to_string(), names[1].to_string()),
+        ("Result".to_string(), outcome.token().to_string()),
+        ("Termination".to_string(), termination.clone()),
+    ];
+    if opening.fen != START_FEN {
+        tags.push(("SetUp".to_string(), "1".to_string()));
+        tags.push(("FEN".to_string(), opening.fen.clone()));
+    }
+
+    Ok(Some((
+        Game {
+            tags,
+            moves,
+            outcome,
+        },
+        termination,
+    )))
+}
+
+pub fn white_score(outcome: Outcome) -> f64 {
+    match outcome {
+        Outcome::WhiteWins => 1.,
+        Outcome::BlackWins => 0.,
+        _ => 0.5,
+    }
+}
+
+fn probe(settings: &Settings, board: &Board) -> Option<Wdl> {
+    settings
+        .tablebase
+        .as_ref()
+        .and_then(|tablebase| tablebase.probe(board).map(|probe| probe.wdl))
+        .or_else(|| {
+            settings
+                .syzygy
+                .as_ref()
+                .and_then(|syzygy| syzygy.probe_wdl(board))
+        })
+}
+
+fn load_openings(path: &Path, plies: Option<usize>) -> Result<Vec<Opening>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("could not read openings {}", path.display()))?;
+
+    if path.extension().is_some_and(|extension| extension == "pgn") {
+        return pgn::parse_games(&text)
+            .iter()
+            .map(|game| {
+                let mut board = game.start_position()?;
+                let fen = board.to_fen();
+                let mut moves = Vec::new();
+                for san in game.moves.iter().take(plies.unwrap_or(usize::MAX)) {
+                    let m = pgn::parse_san(&mut board, san)?;
+                    board.make_move(m);
+                    moves.push(m);
+                }
+                Ok(Opening { fen, moves })
+            })
+            .collect();
+    }
+
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let counters = fields.len() >= 6
+                && fields[4].parse::<usize>().is_ok()
+                && fields[5].parse::<usize>().is_ok();
+            let fen = fields[..if counters { 6 } else { fields.len().min(4) }].join(" ");
+            let board = Board::from_fen(&fen)?;
+            Ok(Opening {
+                fen: board.to_fen(),
+                moves: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+fn parse_clock(tc: &str) -> Option<(Duration, Duration)> {
+    let (base, increment) = tc.split_once('+').unwrap_or((tc, "0"));
+    let base: f64 = base.parse().ok().filter(|&base| base > 0.)?;
+    let increment: f64 = increment.parse().ok()?;
+    Some((
+        Duration::try_from_secs_f64(base).ok()?,
+        Duration::try_from_secs_f64(increment).ok()?,
+    ))
+}
+
+fn adjudication_arg<'a>(args: &mut impl Iterator<Item = &'a String>) -> Option<(usize, i32)> {
+    let moves = args.next()?.parse().ok()?;
+    let score = args.next()?.parse().ok()?;
+    Some((moves, score))
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1. / (1. + 10f64.powf(-elo / 400.))
+}
+
+fn elo(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1. - 1e-6);
+    400. * (score / (1. - score)).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(wins: usize, draws: usize, losses: usize) -> Stats {
+        Stats {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn elo_from_known_results() {
+        let (elo, error) = stats(60, 20, 20).elo();
+        assert_close(elo, 147.190714);
+        assert_close(error, 66.014639);
+
+        let (elo, error) = stats(1000, 1200, 900).elo();
+        assert_close(elo, 11.211489);
+        assert_close(error, 9.579416);
+
+        assert_eq!(
+            stats(10, 0, 10).to_string(),
+            "+10 =0 -10 score 50.0% elo 0.0 +/- 163.3"
+        );
+    }
+
+    #[test]
+    fn llr_from_known_results() {
+        assert_close(stats(60, 20, 20).llr(0., 10.), 1.733713);
+        assert_close(stats(1000, 1200, 900).llr(0., 5.), 1.827285);
+        assert_close(stats(10, 0, 10).llr(-5., 5.), 0.);
+        assert_eq!(stats(0, 10, 0).llr(0., 5.), 0.);
+    }
+
+    #[test]
+    fn parses_time_controls() {
+        assert_eq!(
+            parse_clock("10+0.1"),
+            Some((Duration::from_secs(10), Duration::from_millis(100)))
+        );
+        assert_eq!(
+            parse_clock("60"),
+            Some((Duration::from_secs(60), Duration::ZERO))
+        );
+        for tc in ["-10", "0", "nan", "inf", "1e300", "10+-1", "10+nan", "ten"] {
+            assert_eq!(parse_clock(tc), None, "{}", tc);
+        }
+    }
+}
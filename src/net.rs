@@ -0,0 +1,845 @@
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    board::{Board, Move, Opponent, Status},
+    pgn::Outcome,
+    piece::Piece,
+};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+const FLAG_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reply {
+    Offer,
+    Accept,
+    Decline,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Hello(u32),
+    Sync {
+        color: Piece,
+        clock: Option<Clock>,
+        outcome: Outcome,
+        moves: Vec<Move>,
+    },
+    Move(Move, Option<Duration>),
+    Resign,
+    Draw(Reply),
+    Takeback(Reply),
+    Timeout,
+    Ping,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Hello(version) => write!(f, "hello {}", version),
+            Self::Sync {
+                color,
+                clock,
+                outcome,
+                moves,
+            } => {
+                write!(f, "sync {} ", color_name(*color))?;
+                match clock {
+                    Some(clock) => write!(
+                        f,
+                        "{} {} {}",
+                        clock.remaining[0].as_millis(),
+                        clock.remaining[1].as_millis(),
+                        clock.increment.as_millis()
+                    )?,
+                    None => write!(f, "- - -")?,
+                }
+                write!(f, " {}", outcome.token())?;
+                for m in moves {
+                    write!(f, " {}", m)?;
+                }
+                Ok(())
+            }
+            Self::Move(m, remaining) => match remaining {
+                Some(remaining) => write!(f, "move {} {}", m, remaining.as_millis()),
+                None => write!(f, "move {}", m),
+            },
+            Self::Resign => write!(f, "resign"),
+            Self::Draw(reply) => write!(f, "draw {}", reply_name(*reply)),
+            Self::Takeback(reply) => write!(f, "takeback {}", reply_name(*reply)),
+            Self::Timeout => write!(f, "timeout"),
+            Self::Ping => write!(f, "ping"),
+        }
+    }
+}
+
+impl FromStr for Message {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        let millis = |token: &str| -> Result<Duration> {
+            Ok(Duration::from_millis(token.parse().with_context(|| {
+                format!("invalid time {} in {}", token, s)
+            })?))
+        };
+        let reply = |token: Option<&&str>| match token.copied() {
+            Some("offer") => Ok(Reply::Offer),
+            Some("accept") => Ok(Reply::Accept),
+            Some("decline") => Ok(Reply::Decline),
+            _ => Err(anyhow!("invalid reply in {}", s)),
+        };
+
+        match tokens.as_slice() {
+            ["hello", version] => Ok(Self::Hello(
+                version
+                    .parse()
+                    .with_context(|| format!("invalid version in {}", s))?,
+            )),
+            ["sync", color, white, black, increment, outcome, moves @ ..] => {
+                let clock = match [white, black, increment] {
+                    [&"-", &"-", &"-"] => None,
+                    _ => Some(Clock {
+                        remaining: [millis(white)?, millis(black)?],
+                        increment: millis(increment)?,
+                    }),
+                };
+                Ok(Self::Sync {
+                    color: match *color {
+                        "white" => Piece::White,
+                        "black" => Piece::Black,
+                        _ => bail!("invalid color in {}", s),
+                    },
+                    clock,
+                    outcome: Outcome::parse(outcome)
+                        .ok_or_else(|| anyhow!("invalid outcome in {}", s))?,
+                    moves: moves
+                        .iter()
+                        .map(|name| name.parse())
+                        .collect::<Result<_>>()?,
+                })
+            }
+            ["move", m] => Ok(Self::Move(m.parse()?, None)),
+            ["move", m, remaining] => Ok(Self::Move(m.parse()?, Some(millis(remaining)?))),
+            ["resign"] => Ok(Self::Resign),
+            ["draw", ..] => Ok(Self::Draw(reply(tokens.get(1))?)),
+            ["takeback", ..] => Ok(Self::Takeback(reply(tokens.get(1))?)),
+            ["timeout"] => Ok(Self::Timeout),
+            ["ping"] => Ok(Self::Ping),
+            _ => bail!("unknown message: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clock {
+    remaining: [Duration; 2],
+    increment: Duration,
+}
+
+impl Clock {
+    pub fn parse(tc: &str) -> Option<Self> {
+        let (minutes, increment) = tc.split_once('+').unwrap_or((tc, "0"));
+        let minutes: f64 = minutes.parse().ok().filter(|&minutes| minutes > 0.)?;
+        let base = Duration::try_from_secs_f64(minutes * 60.).ok()?;
+        Some(Self {
+            remaining: [base; 2],
+            increment: Duration::try_from_secs_f64(increment.parse().ok()?).ok()?,
+        })
+    }
+}
+
+pub enum Event {
+    Connected,
+    Disconnected(String),
+    Message(Message),
+}
+
+struct Connection {
+    stream: TcpStream,
+    lines: Receiver<String>,
+    last_seen: Instant,
+    last_ping: Instant,
+    greeted: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Result<Self> {
+        stream.set_nodelay(true)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines().map_while(|line| line.ok()) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut connection = Self {
+            stream,
+            lines,
+            last_seen: Instant::now(),
+            last_ping: Instant::now(),
+            greeted: false,
+        };
+        connection.send(&Message::Hello(PROTOCOL_VERSION))?;
+        Ok(connection)
+    }
+
+    fn send(&mut self, message: &Message) -> Result<()> {
+        writeln!(self.stream, "{}", message)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+pub struct Peer {
+    address: String,
+    listener: Option<TcpListener>,
+    connection: Option<Connection>,
+    last_attempt: Instant,
+}
+
+impl Peer {
+    pub fn host(address: &str) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("could not listen on {}", address))?;
+        listener.set_nonblocking(true)?;
+        println!("waiting for an opponent on {}", listener.local_addr()?);
+
+        Ok(Self {
+            address: address.to_string(),
+            listener: Some(listener),
+            connection: None,
+            last_attempt: Instant::now(),
+        })
+    }
+
+    pub fn connect(address: &str) -> Result<Self> {
+        let mut peer = Self {
+            address: address.to_string(),
+            listener: None,
+            connection: None,
+            last_attempt: Instant::now(),
+        };
+        peer.connection = Some(Connection::new(peer.dial()?)?);
+        Ok(peer)
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection
+            .as_ref()
+            .is_some_and(|connection| connection.greeted)
+    }
+
+    pub fn send(&mut self, message: &Message) {
+        if let Some(connection) = self.connection.as_mut() {
+            if connection.send(message).is_err() {
+                connection.last_seen -= TIMEOUT;
+            }
+        }
+    }
+
+    pub fn poll(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        if let Some(listener) = &self.listener {
+            if let Ok((stream, _)) = listener.accept() {
+                if self.is_connected() {
+                    events.push(Event::Disconnected("replaced by a new connection".into()));
+                }
+                self.connection = stream
+                    .set_nonblocking(false)
+                    .map_err(Into::into)
+                    .and_then(|_| Connection::new(stream))
+                    .ok();
+            }
+        } else if self.connection.is_none() && self.last_attempt.elapsed() >= RECONNECT_INTERVAL {
+            self.last_attempt = Instant::now();
+            self.connection = self.dial().and_then(Connection::new).ok();
+        }
+
+        let connection = match self.connection.as_mut() {
+            Some(connection) => connection,
+            None => return events,
+        };
+
+        let mut lost = None;
+        loop {
+            match connection.lines.try_recv() {
+                Ok(line) => {
+                    connection.last_seen = Instant::now();
+                    match line.parse() {
+                        Ok(Message::Hello(version)) if version == PROTOCOL_VERSION => {
+                            connection.greeted = true;
+                            events.push(Event::Connected);
+                        }
+                        Ok(Message::Hello(version)) => {
+                            lost = Some(format!(
+                                "opponent speaks protocol version {}, expected {}",
+                                version, PROTOCOL_VERSION
+                            ));
+                            break;
+                        }
+                        Ok(Message::Ping) => (),
+                        Ok(message) if connection.greeted => events.push(Event::Message(message)),
+                        Ok(_) => (),
+                        Err(err) => eprintln!("{:#}", err),
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    lost = Some("connection closed".to_string());
+                    break;
+                }
+            }
+        }
+
+        if lost.is_none() && connection.last_seen.elapsed() >= TIMEOUT {
+            lost = Some("connection timed out".to_string());
+        }
+        if lost.is_none() && connection.last_ping.elapsed() >= PING_INTERVAL {
+            connection.last_ping = Instant::now();
+            if connection.send(&Message::Ping).is_err() {
+                lost = Some("connection closed".to_string());
+            }
+        }
+
+        if let Some(reason) = lost {
+            if connection.greeted {
+                events.push(Event::Disconnected(reason));
+            } else {
+                eprintln!("{}", reason);
+            }
+            self.connection = None;
+            self.last_attempt = Instant::now();
+        }
+
+        events
+    }
+
+    fn dial(&self) -> Result<TcpStream> {
+        let address = self
+            .address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("could not resolve {}", self.address))?;
+        TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+            .with_context(|| format!("could not connect to {}", self.address))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Offer {
+    Draw,
+    Takeback,
+}
+
+pub struct Game {
+    peer: Peer,
+    color: Option<Piece>,
+    moves: Vec<Move>,
+    position: Board,
+    clock: Option<Clock>,
+    turn_start: Instant,
+    received: Option<Offer>,
+    sent: Option<Offer>,
+    outcome: Outcome,
+}
+
+impl Game {
+    pub fn new(peer: Peer, color: Piece, clock: Option<Clock>) -> Self {
+        Self {
+            color: peer.is_host().then_some(color),
+            peer,
+            moves: Vec::new(),
+            position: Board::default(),
+            clock,
+            turn_start: Instant::now(),
+            received: None,
+            sent: None,
+            outcome: Outcome::Unknown,
+        }
+    }
+
+    pub fn update(&mut self, board: &mut Board) {
+        for event in self.peer.poll() {
+            match event {
+                Event::Connected => {
+                    println!("opponent connected");
+                    self.turn_start = Instant::now();
+                    if let Some(color) = self.color.filter(|_| self.peer.is_host()) {
+                        self.peer.send(&Message::Sync {
+                            color: color.ennemy(),
+                            clock: self.clock,
+                            outcome: self.outcome,
+                            moves: self.moves.clone(),
+                        });
+                    }
+                }
+                Event::Disconnected(reason) => {
+                    println!("opponent disconnected: {}", reason);
+                    self.charge_clock();
+                    self.received = None;
+                    self.sent = None;
+                }
+                Event::Message(message) => self.receive(message, board),
+            }
+        }
+
+        self.detect_local_move(board);
+        self.check_flag(board);
+        self.update_opponents(board);
+    }
+
+    pub fn resign(&mut self, board: &mut Board) {
+        if let Some(color) = self.playing_color() {
+            self.peer.send(&Message::Resign);
+            self.finish(board, winner(color.ennemy()), "you resigned");
+        }
+    }
+
+    pub fn offer_draw(&mut self, board: &mut Board) {
+        if self.playing_color().is_none() {
+            return;
+        }
+
+        if self.received == Some(Offer::Draw) {
+            self.peer.send(&Message::Draw(Reply::Accept));
+            self.finish(board, Outcome::Draw, "draw agreed");
+        } else if self.sent.is_none() {
+            self.sent = Some(Offer::Draw);
+            self.peer.send(&Message::Draw(Reply::Offer));
+            println!("draw offered");
+        }
+    }
+
+    pub fn takeback(&mut self, board: &mut Board) {
+        let color = match self.playing_color() {
+            Some(color) => color,
+            None => return,
+        };
+
+        if self.received == Some(Offer::Takeback) {
+            self.peer.send(&Message::Takeback(Reply::Accept));
+            self.received = None;
+            self.take_back(board, color.ennemy());
+        } else if self.sent.is_none() && self.plies_to_take_back(color) > 0 {
+            self.sent = Some(Offer::Takeback);
+            self.peer.send(&Message::Takeback(Reply::Offer));
+            println!("takeback requested");
+        }
+    }
+
+    pub fn decline(&mut self) {
+        match self.received.take() {
+            Some(Offer::Draw) => self.peer.send(&Message::Draw(Reply::Decline)),
+            Some(Offer::Takeback) => self.peer.send(&Message::Takeback(Reply::Decline)),
+            None => (),
+        }
+    }
+
+    pub fn title(&self) -> String {
+        let mut title = match self.color {
+            Some(color) => format!("chess-ai - playing {}", color_name(color)),
+            None => "chess-ai".to_string(),
+        };
+
+        if let Some(remaining) = self.clocks() {
+            title.push_str(&format!(
+                " - white {} black {}",
+                format_clock(remaining[0]),
+                format_clock(remaining[1])
+            ));
+        }
+
+        if self.outcome != Outcome::Unknown {
+            title.push_str(&format!(" - {}", self.outcome.token()));
+        } else if !self.peer.is_connected() {
+            title.push_str(" - waiting for opponent");
+        } else if let Some(offer) = self.received {
+            title.push_str(match offer {
+                Offer::Draw => " - draw offered: D to accept, N to decline",
+                Offer::Takeback => " - takeback requested: U to accept, N to decline",
+            });
+        } else if let Some(offer) = self.sent {
+            title.push_str(match offer {
+                Offer::Draw => " - draw offer sent",
+                Offer::Takeback => " - takeback request sent",
+            });
+        }
+        title
+    }
+
+    pub fn clocks(&self) -> Option<[Duration; 2]> {
+        let mut remaining = self.clock?.remaining;
+        if self.is_running() {
+            let side = side(self.position.get_current_turn());
+            remaining[side] = remaining[side].saturating_sub(self.turn_start.elapsed());
+        }
+        Some(remaining)
+    }
+
+    fn receive(&mut self, message: Message, board: &mut Board) {
+        match message {
+            Message::Sync {
+                color,
+                clock,
+                outcome,
+                moves,
+            } if !self.peer.is_host() => {
+                self.color = Some(color);
+                self.clock = clock;
+                self.turn_start = Instant::now();
+                if moves != self.moves || board.get_status() == Status::Ended {
+                    self.moves = moves;
+                    board.replay(&self.moves);
+                    self.position = board.clone();
+                }
+                if outcome != Outcome::Unknown {
+                    self.finish(board, outcome, "game over");
+                }
+            }
+            Message::Move(m, remaining) => {
+                let remote = self.playing_color().map(|color| color.ennemy());
+                if remote != Some(self.position.get_current_turn())
+                    || !self.position.get_all_moves().contains(&m)
+                {
+                    eprintln!("ignoring unexpected move from opponent: {}", m);
+                    return;
+                }
+
+                if let (Some(clock), Some(remaining)) = (self.clock.as_mut(), remaining) {
+                    clock.remaining[side(self.position.get_current_turn())] = remaining;
+                }
+                self.moves.push(m);
+                self.position.make_move(m);
+                self.position.update_status();
+                self.turn_start = Instant::now();
+                self.sent = None;
+                self.received = None;
+                board.animate_move(m);
+                self.report_result();
+            }
+            Message::Resign => {
+                if let Some(color) = self.playing_color() {
+                    self.finish(board, winner(color), "opponent resigned");
+                }
+            }
+            Message::Draw(Reply::Offer) => {
+                self.received = Some(Offer::Draw);
+                println!("opponent offers a draw: press D to accept or N to decline");
+            }
+            Message::Draw(Reply::Accept) if self.sent == Some(Offer::Draw) => {
+                self.finish(board, Outcome::Draw, "draw agreed");
+            }
+            Message::Takeback(Reply::Offer) => {
+                self.received = Some(Offer::Takeback);
+                println!("opponent asks for a takeback: press U to accept or N to decline");
+            }
+            Message::Takeback(Reply::Accept) if self.sent == Some(Offer::Takeback) => {
+                self.sent = None;
+                if let Some(color) = self.color {
+                    self.take_back(board, color);
+                }
+            }
+            Message::Draw(Reply::Decline) | Message::Takeback(Reply::Decline) => {
+                self.sent = None;
+                println!("opponent declined");
+            }
+            Message::Timeout if self.outcome == Outcome::Unknown => {
+                // only trust the claim once our own clock agrees
+                let flagged = self.position.get_current_turn();
+                let expired = self.clock.is_some_and(|clock| {
+                    self.turn_start.elapsed() >= clock.remaining[side(flagged)] + FLAG_GRACE
+                });
+                if expired {
+                    self.finish(board, winner(flagged.ennemy()), "time forfeit");
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn detect_local_move(&mut self, board: &Board) {
+        let color = match self.playing_color() {
+            Some(color) => color,
+            None => return,
+        };
+        if self.position.get_current_turn() != color
+            || board.get_ply() != self.position.get_ply() + 1
+        {
+            return;
+        }
+
+        let played = self.position.get_all_moves().into_iter().find(|&m| {
+            let mut child = self.position.clone();
+            child.make_move(m);
+            child.hash() == board.hash()
+        });
+        let m = match played {
+            Some(m) => m,
+            None => return,
+        };
+
+        let elapsed = self.turn_start.elapsed();
+        let remaining = self.clock.as_mut().map(|clock| {
+            let remaining = &mut clock.remaining[side(color)];
+            *remaining = remaining.saturating_sub(elapsed) + clock.increment;
+            *remaining
+        });
+        self.peer.send(&Message::Move(m, remaining));
+        self.moves.push(m);
+        self.position.make_move(m);
+        self.position.update_status();
+        self.turn_start = Instant::now();
+        self.sent = None;
+        self.received = None;
+        self.report_result();
+    }
+
+    fn check_flag(&mut self, board: &mut Board) {
+        let clock = match self.clock.filter(|_| self.is_running()) {
+            Some(clock) => clock,
+            None => return,
+        };
+
+        let turn = self.position.get_current_turn();
+        let elapsed = self.turn_start.elapsed();
+        let remaining = clock.remaining[side(turn)];
+        if Some(turn) == self.color && elapsed >= remaining {
+            self.peer.send(&Message::Timeout);
+            self.finish(board, winner(turn.ennemy()), "you lost on time");
+        } else if Some(turn) != self.color && elapsed >= remaining + FLAG_GRACE {
+            self.peer.send(&Message::Timeout);
+            self.finish(board, winner(turn.ennemy()), "opponent lost on time");
+        }
+    }
+
+    fn update_opponents(&self, board: &mut Board) {
+        let opponent = |color: Piece| {
+            if self.playing_color() == Some(color) && self.peer.is_connected() {
+                Opponent::Player
+            } else {
+                Opponent::Remote
+            }
+        };
+        board.set_opponents(opponent(Piece::White), opponent(Piece::Black));
+    }
+
+    fn take_back(&mut self, board: &mut Board, requester: Piece) {
+        let plies = self.plies_to_take_back(requester);
+        self.moves.truncate(self.moves.len() - plies);
+        board.replay(&self.moves);
+        self.position = board.clone();
+        self.turn_start = Instant::now();
+        println!("took back {} move(s)", plies);
+    }
+
+    fn plies_to_take_back(&self, requester: Piece) -> usize {
+        let plies = if self.position.get_current_turn() == requester {
+            2
+        } else {
+            1
+        };
+        plies.min(self.moves.len())
+    }
+
+    fn finish(&mut self, board: &mut Board, outcome: Outcome, reason: &str) {
+        self.outcome = outcome;
+        self.received = None;
+        self.sent = None;
+        board.end();
+        println!("{} {}", outcome.token(), reason);
+    }
+
+    fn report_result(&mut self) {
+        let turn = self.position.get_current_turn();
+        let outcome = match self.position.get_status() {
+            Status::Checkmate => winner(turn.ennemy()),
+            Status::Stalemate | Status::Dead => Outcome::Draw,
+            Status::Playing | Status::Ended => return,
+        };
+        self.outcome = outcome;
+    }
+
+    fn playing_color(&self) -> Option<Piece> {
+        self.color
+            .filter(|_| self.outcome == Outcome::Unknown && self.peer.is_connected())
+    }
+
+    fn is_running(&self) -> bool {
+        self.outcome == Outcome::Unknown && self.peer.is_connected()
+    }
+
+    fn charge_clock(&mut self) {
+        let side = side(self.position.get_current_turn());
+        let elapsed = self.turn_start.elapsed();
+        if let Some(clock) = self.clock.as_mut() {
+            if self.outcome == Outcome::Unknown {
+                clock.remaining[side] = clock.remaining[side].saturating_sub(elapsed);
+            }
+        }
+        self.turn_start = Instant::now();
+    }
+}
+
+fn side(color: Piece) -> usize {
+    usize::from(!color.is_white())
+}
+
+fn winner(color: Piece) -> Outcome {
+    if color.is_white() {
+        Outcome::WhiteWins
+    } else {
+        Outcome::BlackWins
+    }
+}
+
+fn color_name(color: Piece) -> &'static str {
+    if color.is_white() {
+        "white"
+    } else {
+        "black"
+    }
+}
+
+fn reply_name(reply: Reply) -> &'static str {
+    match reply {
+        Reply::Offer => "offer",
+        Reply::Accept => "accept",
+        Reply::Decline => "decline",
+    }
+}
+
+fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let clock = Clock {
+            remaining: [Duration::from_millis(61500), Duration::from_millis(42)],
+            increment: Duration::from_secs(2),
+        };
+        let messages = [
+            Message::Hello(PROTOCOL_VERSION),
+            Message::Sync {
+                color: Piece::Black,
+                clock: Some(clock),
+                outcome: Outcome::Unknown,
+                moves: vec!["e2e4".parse().unwrap(), "e7e5".parse().unwrap()],
+            },
+            Message::Sync {
+                color: Piece::White,
+                clock: None,
+                outcome: Outcome::Draw,
+                moves: Vec::new(),
+            },
+            Message::Move("e7e8n".parse().unwrap(), None),
+            Message::Move("a2a4".parse().unwrap(), Some(Duration::from_millis(1234))),
+            Message::Resign,
+            Message::Draw(Reply::Offer),
+            Message::Draw(Reply::Accept),
+            Message::Takeback(Reply::Offer),
+            Message::Takeback(Reply::Decline),
+            Message::Timeout,
+            Message::Ping,
+        ];
+
+        for message in messages {
+            let line = message.to_string();
+            assert_eq!(line.parse::<Message>().unwrap(), message, "{}", line);
+        }
+    }
+
+    #[test]
+    fn parses_clocks() {
+        assert_eq!(
+            Clock::parse("5+3"),
+            Some(Clock {
+                remaining: [Duration::from_secs(300); 2],
+                increment: Duration::from_secs(3),
+            })
+        );
+        for tc in [
+            "-5", "0", "nan", "inf", "1e300", "5+-1", "5+nan", "5+inf", "five",
+        ] {
+            assert_eq!(Clock::parse(tc), None, "{}", tc);
+        }
+    }
+
+    #[test]
+    fn checks_timeout_claims_against_the_local_clock() {
+        let peer = Peer::host("127.0.0.1:0").unwrap();
+        let mut game = Game::new(peer, Piece::White, Clock::parse("1"));
+        let mut board = Board::default();
+        game.receive(Message::Timeout, &mut board);
+        assert_eq!(game.outcome, Outcome::Unknown);
+
+        if let Some(clock) = game.clock.as_mut() {
+            clock.remaining[side(Piece::White)] = Duration::ZERO;
+        }
+        game.turn_start = Instant::now() - FLAG_GRACE / 2;
+        game.receive(Message::Timeout, &mut board);
+        assert_eq!(game.outcome, Outcome::Unknown);
+
+        game.turn_start = Instant::now() - FLAG_GRACE * 2;
+        game.receive(Message::Timeout, &mut board);
+        assert_eq!(game.outcome, Outcome::BlackWins);
+
+        let peer = Peer::host("127.0.0.1:0").unwrap();
+        let mut game = Game::new(peer, Piece::White, None);
+        game.receive(Message::Timeout, &mut board);
+        assert_eq!(game.outcome, Outcome::Unknown);
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        for line in [
+            "",
+            "hello",
+            "hello one",
+            "sync red - - - *",
+            "sync white 1 2 x *",
+            "move e2",
+            "move e2e4 soon",
+            "draw",
+            "takeback maybe",
+            "castle",
+        ] {
+            assert!(line.parse::<Message>().is_err(), "{}", line);
+        }
+    }
+}
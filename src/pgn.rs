@@ -0,0 +1,328 @@
+use std::fmt;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    board::{square_from_name, square_name, Board, Move},
+    piece::Piece,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    Unknown,
+}
+
+impl Outcome {
+    pub fn parse(token: &str) -> Option<Self> {
+        match token {
+            "1-0" => Some(Self::WhiteWins),
+            "0-1" => Some(Self::BlackWins),
+            "1/2-1/2" => Some(Self::Draw),
+            "*" => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn token(self) -> &'static str {
+        match self {
+            Self::WhiteWins => "1-0",
+            Self::BlackWins => "0-1",
+            Self::Draw => "1/2-1/2",
+            Self::Unknown => "*",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    pub outcome: Outcome,
+}
+
+impl Game {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn start_position(&self) -> Result<Board> {
+        match self.tag("FEN") {
+            Some(fen) => Board::from_fen(fen),
+            None => Ok(Board::default()),
+        }
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in &self.tags {
+            writeln!(f, "[{} \"{}\"]", name, value.replace('"', "'"))?;
+        }
+        writeln!(f)?;
+
+        let first_ply = self.start_position().map_or(0, |board| board.get_ply());
+        let mut line = String::new();
+        for (i, san) in self.moves.iter().enumerate() {
+            let ply = first_ply + i;
+            let token = if ply.is_multiple_of(2) {
+                format!("{}. {}", ply / 2 + 1, san)
+            } else if i == 0 {
+                format!("{}... {}", ply / 2 + 1, san)
+            } else {
+                san.clone()
+            };
+
+            if !line.is_empty() && line.len() + token.len() >= 80 {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        writeln!(f, "{}{}", line, self.outcome.token())
+    }
+}
+
+pub fn parse_games(text: &str) -> Vec<Game> {
+    let mut games = Vec::new();
+    let mut game = Game {
+        tags: Vec::new(),
+        moves: Vec::new(),
+        outcome: Outcome::Unknown,
+    };
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if !game.moves.is_empty() {
+                    games.push(game.clone());
+                    game.moves.clear();
+                    game.tags.clear();
+                    game.outcome = Outcome::Unknown;
+                }
+                if let Some((name, value)) = tag.trim().split_once(char::is_whitespace) {
+                    game.tags
+                        .push((name.to_string(), value.trim().trim_matches('"').to_string()));
+                }
+            }
+            '{' => while chars.next().is_some_and(|c| c != '}') {},
+            ';' => while chars.next().is_some_and(|c| c != '\n') {},
+            '(' => {
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        Some('(') => depth += 1,
+                        Some(')') => depth -= 1,
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]{}();".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+
+                if let Some(outcome) = Outcome::parse(&token) {
+                    game.outcome = outcome;
+                    games.push(game.clone());
+                    game.moves.clear();
+                    game.tags.clear();
+                    game.outcome = Outcome::Unknown;
+                    continue;
+                }
+
+                let san = match token.rfind('.') {
+                    Some(i) => &token[i + 1..],
+                    None => &token,
+                };
+                if !san.is_empty() && !san.starts_with('$') {
+                    game.moves.push(san.to_string());
+                }
+            }
+        }
+    }
+
+    if !game.moves.is_empty() {
+        games.push(game);
+    }
+
+    games
+}
+
+pub fn parse_san(board: &mut Board, san: &str) -> Result<Move> {
+    let san = san.trim_end_matches(|c| "+#!?".contains(c));
+    let color = board.get_current_turn();
+    let legal_moves = board.get_all_moves();
+
+    let castle = match san {
+        "O-O" | "0-0" => Some(2),
+        "O-O-O" | "0-0-0" => Some(-2),
+        _ => None,
+    };
+    if let Some(offset) = castle {
+        return legal_moves
+            .into_iter()
+            .find(|m| {
+                board.get_square(m.from) == Piece::King | color
+                    && m.to as isize - m.from as isize == offset
+            })
+            .ok_or_else(|| anyhow!("illegal castling: {}", san));
+    }
+
+    let (san, promotion) = match san.split_once('=') {
+        Some((san, promotion)) => (san, promotion_piece(promotion)?),
+        None => match san.char_indices().last() {
+            Some((i, c)) if "NBRQ".contains(c) && san.starts_with(|c: char| c.is_lowercase()) => {
+                (&san[..i], promotion_piece(&san[i..])?)
+            }
+            _ => (san, Piece::None),
+        },
+    };
+
+    let (piece, rest) = match san.chars().next() {
+        Some('N') => (Piece::LeftKnight, &san[1..]),
+        Some('B') => (Piece::Bishop, &san[1..]),
+        Some('R') => (Piece::Rook, &san[1..]),
+        Some('Q') => (Piece::Queen, &san[1..]),
+        Some('K') => (Piece::King, &san[1..]),
+        Some(_) => (Piece::Pawn, san),
+        None => bail!("empty move"),
+    };
+
+    let rest = rest.replace('x', "");
+    if rest.len() < 2 || !rest.is_char_boundary(rest.len() - 2) {
+        bail!("invalid move: {}", san);
+    }
+    let (disambiguation, destination) = rest.split_at(rest.len() - 2);
+    let to = square_from_name(destination).ok_or_else(|| anyhow!("invalid square in {}", san))?;
+
+    let candidates: Vec<Move> = legal_moves
+        .into_iter()
+        .filter(|m| {
+            let moving = board.get_square(m.from).split().0;
+            let name = square_name(m.from);
+            m.to == to
+                && same_kind(moving, piece)
+                && same_kind(m.promotion, promotion)
+                && disambiguation.chars().all(|c| name.contains(c))
+        })
+        .collect();
+
+    match candidates.as_slice() {
+        [m] => Ok(*m),
+        [] => bail!("illegal move: {}", san),
+        _ => bail!("ambiguous move: {}", san),
+    }
+}
+
+pub fn parse_move(board: &mut Board, name: &str) -> Result<Move> {
+    if let Ok(m) = name.parse::<Move>() {
+        if board.get_all_moves().contains(&m) {
+            return Ok(m);
+        }
+    }
+    parse_san(board, name)
+}
+
+pub fn to_san(board: &mut Board, m: Move) -> String {
+    let piece = board.get_square(m.from).split().0;
+    let mut san = String::new();
+
+    if piece == Piece::King && m.from.abs_diff(m.to) == 2 {
+        san.push_str(if m.to > m.from { "O-O" } else { "O-O-O" });
+    } else {
+        let from = square_name(m.from);
+        if piece == Piece::Pawn {
+            if board.is_capture(m) {
+                san.push_str(&from[..1]);
+            }
+        } else {
+            san.push_str(piece_letter(piece));
+
+            let others: Vec<Move> = board
+                .get_all_moves()
+                .into_iter()
+                .filter(|other| {
+                    other.to == m.to
+                        && other.from != m.from
+                        && same_kind(board.get_square(other.from).split().0, piece)
+                })
+                .collect();
+            if others.iter().all(|other| other.from % 8 != m.from % 8) {
+                if !others.is_empty() {
+                    san.push_str(&from[..1]);
+                }
+            } else if others.iter().all(|other| other.from / 8 != m.from / 8) {
+                san.push_str(&from[1..]);
+            } else {
+                san.push_str(&from);
+            }
+        }
+
+        if board.is_capture(m) {
+            san.push('x');
+        }
+        san.push_str(&square_name(m.to));
+        if !m.promotion.is_none() {
+            san.push('=');
+            san.push_str(piece_letter(m.promotion.split().0));
+        }
+    }
+
+    let mut child = board.clone();
+    child.make_move(m);
+    if child.is_in_check() {
+        san.push(if child.get_all_moves().is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+
+    san
+}
+
+fn piece_letter(piece: Piece) -> &'static str {
+    match piece {
+        Piece::LeftKnight | Piece::RightKnight => "N",
+        Piece::Bishop => "B",
+        Piece::Rook => "R",
+        Piece::Queen => "Q",
+        Piece::King => "K",
+        _ => "",
+    }
+}
+
+fn promotion_piece(name: &str) -> Result<Piece> {
+    match name {
+        "N" => Ok(Piece::LeftKnight),
+        "B" => Ok(Piece::Bishop),
+        "R" => Ok(Piece::Rook),
+        "Q" => Ok(Piece::Queen),
+        _ => bail!("invalid promotion: {}", name),
+    }
+}
+
+fn same_kind(a: Piece, b: Piece) -> bool {
+    a == b || (!a.is_none() && !b.is_none() && a.index() == 1 && b.index() == 1)
+}
@@ -242,6 +242,18 @@ impl Piece {
         }
     }
 
+    pub fn index(&self) -> usize {
+        match self.split().0 {
+            Self::Pawn => 0,
+            Self::LeftKnight | Self::RightKnight => 1,
+            Self::Bishop => 2,
+            Self::Rook => 3,
+            Self::Queen => 4,
+            Self::King => 5,
+            _ => panic!("Unexpected piece: {:?}", self),
+        }
+    }
+
     pub fn name(&self) -> &str {
         match self {
             Self::Pawn => "pawn",
@@ -0,0 +1,62 @@
+use anyhow::{Context as _, Result};
+use piston_window::{rectangle, text, Context, G2d, Glyphs, PistonWindow, Transformed};
+
+use crate::engine::search::Line;
+
+const FONT_SIZE: u32 = 16;
+const LINE_HEIGHT: f64 = 24.;
+const PADDING: f64 = 10.;
+const PV_MOVES: usize = 8;
+
+pub fn glyphs(window: &mut PistonWindow) -> Result<Glyphs> {
+    let assets = find_folder::Search::ParentsThenKids(3, 3)
+        .for_folder("assets")
+        .context("could not find the assets folder")?;
+    window
+        .load_font(assets.join("FiraSans-Regular.ttf"))
+        .context("could not load the analysis font")
+}
+
+pub fn render(lines: &[Line], width: f64, c: Context, g: &mut G2d, glyphs: &mut Glyphs) {
+    let height = PADDING * 2. + LINE_HEIGHT * lines.len().max(1) as f64;
+    rectangle([0., 0., 0., 0.75], [0., 0., width, height], c.transform, g);
+
+    let labels: Vec<String> = if lines.is_empty() {
+        vec!["waiting for the engine...".to_string()]
+    } else {
+        lines.iter().map(label).collect()
+    };
+
+    let text = text::Text::new_color([1.; 4], FONT_SIZE);
+    for (i, label) in labels.iter().enumerate() {
+        let baseline = PADDING + LINE_HEIGHT * (i + 1) as f64 - LINE_HEIGHT / 4.;
+        let _ = text.draw(
+            label,
+            glyphs,
+            &c.draw_state,
+            c.transform.trans(PADDING, baseline),
+            g,
+        );
+    }
+}
+
+pub fn label(line: &Line) -> String {
+    let score = match line.mate_in() {
+        Some(moves) => format!("#{}", moves),
+        None => format!("{:+.2}", line.score as f64 / 100.),
+    };
+    let pv: Vec<String> = line
+        .pv
+        .iter()
+        .take(PV_MOVES)
+        .map(|m| m.to_string())
+        .collect();
+
+    format!(
+        "{}.  depth {}  {}  {}",
+        line.multipv,
+        line.depth,
+        score,
+        pv.join(" ")
+    )
+}
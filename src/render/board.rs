@@ -35,10 +35,7 @@ impl Render for Board {
                     .unwrap_or(false);
                 let is_in_promotion = self.is_in_promotion(x, y);
 
-                let c = c
-                    .clone()
-                    .scale(square_side, square_side)
-                    .trans(x as f64, y as f64);
+                let c = c.scale(square_side, square_side).trans(x as f64, y as f64);
 
                 if is_light_square {
                     rectangle(
@@ -74,12 +71,12 @@ impl Render for Board {
 
                 if is_in_promotion {
                     let color = if y == 0 { Piece::White } else { Piece::Black };
-                    let c = c.clone().scale(0.5, 0.5);
+                    let c = c.scale(0.5, 0.5);
 
                     (Piece::Queen | color).render(args, c, g, texture_bank, mouse_pos);
-                    #[rustfmt::skip] (Piece::Rook | color).render(args, c.clone().trans(1., 0.), g, texture_bank, mouse_pos);
-                    #[rustfmt::skip] (Piece::Bishop | color).render(args, c.clone().trans(0., 1.), g, texture_bank, mouse_pos);
-                    #[rustfmt::skip] (Piece::LeftKnight | color).render(args, c.clone().trans(1., 1.), g, texture_bank, mouse_pos);
+                    #[rustfmt::skip] (Piece::Rook | color).render(args, c.trans(1., 0.), g, texture_bank, mouse_pos);
+                    #[rustfmt::skip] (Piece::Bishop | color).render(args, c.trans(0., 1.), g, texture_bank, mouse_pos);
+                    #[rustfmt::skip] (Piece::LeftKnight | color).render(args, c.trans(1., 1.), g, texture_bank, mouse_pos);
                 }
 
                 if (!is_selected || !is_dragging) && !is_flying {
@@ -92,8 +89,7 @@ impl Render for Board {
         if is_dragging {
             self.get_selected().render(
                 args,
-                c.clone()
-                    .trans_pos(mouse_pos)
+                c.trans_pos(mouse_pos)
                     .scale(square_side, square_side)
                     .trans(-0.5, -0.5),
                 g,
@@ -102,11 +98,26 @@ impl Render for Board {
             );
         }
 
+        if let Some(progress) = self.thinking_progress() {
+            let height = square_side / 12.;
+            let y = if self.get_current_turn().is_white() {
+                args.window_size[1] - height
+            } else {
+                0.
+            };
+
+            rectangle(
+                self.rgba::<u8>(247, 127, 0, 0.9),
+                [0., y, args.window_size[0] * progress, height],
+                c.transform,
+                g,
+            );
+        }
+
         if let Some(([x, y], [current_y, current_x], _)) = flying_piece {
             self.get_piece(x, y).render(
                 args,
-                c.clone()
-                    .scale(square_side, square_side)
+                c.scale(square_side, square_side)
                     .trans(current_x, current_y),
                 g,
                 texture_bank,
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use piston_window::{types::Color, Context, G2d, G2dTexture, RenderArgs};
 
+pub mod analysis;
 mod board;
 pub mod piece;
 
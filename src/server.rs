@@ -0,0 +1,1036 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    board::{Board, Move, Status},
+    engine::search::{self, Limits},
+    match_play::{Player, Spec},
+    pgn::{self, Outcome},
+    piece::Piece,
+};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:7879";
+const TICK: Duration = Duration::from_millis(50);
+const FIFTY_MOVE_PLIES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeControl {
+    base: Duration,
+    increment: Duration,
+}
+
+impl FromStr for TimeControl {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (minutes, increment) = s.split_once('+').unwrap_or((s, "0"));
+        let invalid = || format!("invalid time control {} (expected minutes[+increment])", s);
+        let minutes: f64 = minutes.parse().with_context(invalid)?;
+        let increment: f64 = increment.parse().with_context(invalid)?;
+        if !minutes.is_finite() || !increment.is_finite() || minutes <= 0. || increment < 0. {
+            bail!(invalid());
+        }
+
+        Ok(Self {
+            base: Duration::try_from_secs_f64(minutes * 60.).with_context(invalid)?,
+            increment: Duration::try_from_secs_f64(increment).with_context(invalid)?,
+        })
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}+{}",
+            self.base.as_secs_f64() / 60.,
+            self.increment.as_secs_f64()
+        )
+    }
+}
+
+enum Event {
+    Connected(TcpStream),
+    Line(usize, String),
+    Disconnected(usize),
+    BotMove(usize, Player, Result<Option<Move>>),
+}
+
+struct Client {
+    name: Option<String>,
+    stream: Option<TcpStream>,
+    spec: Option<Spec>,
+    bot: bool,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        if let Some(stream) = &self.stream {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+struct Offer {
+    from: String,
+    to: Option<String>,
+    time_control: TimeControl,
+    color: Option<Piece>,
+}
+
+impl Offer {
+    fn describe(&self, id: usize) -> String {
+        let color = self.color.map_or("random", color_name);
+        match &self.to {
+            Some(to) => format!(
+                "challenge {} {} {} {} {}",
+                id, self.from, to, self.time_control, color
+            ),
+            None => format!("seek {} {} {} {}", id, self.from, self.time_control, color),
+        }
+    }
+}
+
+struct Game {
+    players: [String; 2],
+    bots: [Option<Player>; 2],
+    time_control: TimeControl,
+    board: Board,
+    moves: Vec<Move>,
+    san: Vec<String>,
+    repetitions: HashMap<u64, usize>,
+    quiet_plies: usize,
+    clock: [Duration; 2],
+    turn_start: Instant,
+    draw_offer: Option<usize>,
+}
+
+impl Game {
+    fn side(&self) -> usize {
+        side(self.board.get_current_turn())
+    }
+
+    fn remaining(&self, side: usize) -> Duration {
+        if side == self.side() {
+            self.clock[side].saturating_sub(self.turn_start.elapsed())
+        } else {
+            self.clock[side]
+        }
+    }
+
+    fn seat(&self, name: &str) -> Option<usize> {
+        self.players.iter().position(|player| player == name)
+    }
+
+    fn describe(&self, id: usize) -> String {
+        format!(
+            "game {} {} {} {} {}",
+            id,
+            self.players[0],
+            self.players[1],
+            self.time_control,
+            self.board.get_ply()
+        )
+    }
+
+    fn position(&self, id: usize) -> String {
+        let mut line = format!(
+            "position {} {} {}",
+            id,
+            self.remaining(0).as_millis(),
+            self.remaining(1).as_millis()
+        );
+        for m in &self.moves {
+            line.push_str(&format!(" {}", m));
+        }
+        line
+    }
+}
+
+struct Server {
+    events: Sender<Event>,
+    clients: BTreeMap<usize, Client>,
+    offers: BTreeMap<usize, Offer>,
+    games: BTreeMap<usize, Game>,
+    next_id: usize,
+    pgn: Option<PathBuf>,
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut address = DEFAULT_ADDRESS.to_string();
+    let mut bots = Vec::new();
+    let mut pgn = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => match args.next() {
+                Some(value) => address = value.clone(),
+                None => bail!("--listen expects an address such as 0.0.0.0:7879"),
+            },
+            "--bot" => match args.next() {
+                Some(spec) => bots.push(Spec::parse(spec)?),
+                None => bail!("--bot expects an engine such as computer,level=5"),
+            },
+            "--pgn" => match args.next() {
+                Some(path) => pgn = Some(PathBuf::from(path)),
+                None => bail!("--pgn expects a file"),
+            },
+            arg => bail!("unexpected argument: {}", arg),
+        }
+    }
+
+    let (mut server, events, address) = Server::bind(&address)?;
+    println!("listening on {}", address);
+    server.pgn = pgn;
+    for spec in bots {
+        server.add_bot(spec)?;
+    }
+
+    server.serve(events);
+    Ok(())
+}
+
+impl Server {
+    fn bind(address: &str) -> Result<(Self, Receiver<Event>, SocketAddr)> {
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("could not listen on {}", address))?;
+        let address = listener.local_addr()?;
+
+        let (sender, events) = mpsc::channel();
+        let server = Server {
+            events: sender.clone(),
+            clients: BTreeMap::new(),
+            offers: BTreeMap::new(),
+            games: BTreeMap::new(),
+            next_id: 1,
+            pgn: None,
+        };
+        thread::spawn(move || {
+            for stream in listener.incoming().map_while(|stream| stream.ok()) {
+                if sender.send(Event::Connected(stream)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok((server, events, address))
+    }
+
+    fn serve(&mut self, events: Receiver<Event>) {
+        loop {
+            match events.recv_timeout(TICK) {
+                Ok(Event::Connected(stream)) => self.connect(stream),
+                Ok(Event::Line(id, line)) => {
+                    if let Err(err) = self.command(id, &line) {
+                        self.send(id, &format!("error {:#}", err));
+                    }
+                }
+                Ok(Event::Disconnected(id)) => self.disconnect(id),
+                Ok(Event::BotMove(id, player, result)) => self.bot_move(id, player, result),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            self.check_flags();
+        }
+    }
+
+    fn add_bot(&mut self, spec: Spec) -> Result<()> {
+        validate_name(&spec.name).context("pick a bot name with name=")?;
+        if self.find(&spec.name).is_some() {
+            bail!("duplicate bot name: {}", spec.name);
+        }
+        Player::new(&spec).with_context(|| format!("could not start bot {}", spec.name))?;
+
+        println!("bot {} ready", spec.name);
+        let id = self.next_id();
+        self.clients.insert(
+            id,
+            Client {
+                name: Some(spec.name.clone()),
+                stream: None,
+                spec: Some(spec),
+                bot: true,
+            },
+        );
+        Ok(())
+    }
+
+    fn connect(&mut self, stream: TcpStream) {
+        let reader = match stream.try_clone() {
+            Ok(reader) => BufReader::new(reader),
+            Err(_) => return,
+        };
+        let _ = stream.set_nodelay(true);
+
+        let id = self.next_id();
+        let events = self.events.clone();
+        thread::spawn(move || {
+            for line in reader.lines().map_while(|line| line.ok()) {
+                if events.send(Event::Line(id, line)).is_err() {
+                    return;
+                }
+            }
+            let _ = events.send(Event::Disconnected(id));
+        });
+
+        self.clients.insert(
+            id,
+            Client {
+                name: None,
+                stream: Some(stream),
+                spec: None,
+                bot: false,
+            },
+        );
+        self.send(id, &format!("hello {}", PROTOCOL_VERSION));
+    }
+
+    fn disconnect(&mut self, id: usize) {
+        let name = match self
+            .clients
+            .remove(&id)
+            .and_then(|client| client.name.clone())
+        {
+            Some(name) => name,
+            None => return,
+        };
+        println!("{} disconnected", name);
+
+        let offers: Vec<usize> = self
+            .offers
+            .iter()
+            .filter(|(_, offer)| offer.from == name || offer.to.as_ref() == Some(&name))
+            .map(|(&id, _)| id)
+            .collect();
+        for offer in offers {
+            self.cancel_offer(offer);
+        }
+
+        let games: Vec<(usize, String)> = self
+            .games
+            .iter()
+            .filter_map(|(&game_id, game)| {
+                let seat = game.seat(&name)?;
+                Some((game_id, game.players[1 - seat].clone()))
+            })
+            .collect();
+        for (game_id, opponent) in games {
+            self.send_to(&opponent, &format!("disconnected {} {}", game_id, name));
+        }
+    }
+
+    fn command(&mut self, id: usize, line: &str) -> Result<()> {
+        // lines can still be queued after quit has dropped the client
+        let client = match self.clients.get(&id) {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let name = match (tokens.as_slice(), client.name.clone()) {
+            ([], _) => return Ok(()),
+            (["hello", version, name, rest @ ..], None) => {
+                return self.login(id, version, name, rest)
+            }
+            (["hello", ..], Some(_)) => bail!("already logged in"),
+            (_, None) => bail!("expected hello {} <name> [bot]", PROTOCOL_VERSION),
+            (_, Some(name)) => name,
+        };
+
+        match tokens.as_slice() {
+            ["seek", time_control, color @ ..] => {
+                self.seek(name, time_control.parse()?, color_arg(color)?)
+            }
+            ["challenge", opponent, time_control, color @ ..] => {
+                self.challenge(name, opponent, time_control.parse()?, color_arg(color)?)
+            }
+            ["accept", offer] => self.accept(&name, id_arg(offer)?),
+            ["decline", offer] => {
+                let offer = id_arg(offer)?;
+                match self.offers.get(&offer) {
+                    Some(found) if found.to.as_ref() == Some(&name) => {
+                        let found = self.offers.remove(&offer).unwrap();
+                        let line = format!("declined {}", offer);
+                        self.send_to(&found.from, &line);
+                        self.send_to(&name, &line);
+                        Ok(())
+                    }
+                    _ => bail!("no challenge {} for you", offer),
+                }
+            }
+            ["cancel", offer] => {
+                let offer = id_arg(offer)?;
+                match self.offers.get(&offer) {
+                    Some(found) if found.from == name => {
+                        self.cancel_offer(offer);
+                        Ok(())
+                    }
+                    _ => bail!("no offer {} of yours", offer),
+                }
+            }
+            ["players"] => {
+                let lines: Vec<String> = self
+                    .clients
+                    .values()
+                    .filter_map(|client| {
+                        let name = client.name.as_ref()?;
+                        Some(if client.bot {
+                            format!("player {} bot", name)
+                        } else {
+                            format!("player {}", name)
+                        })
+                    })
+                    .collect();
+                self.send_lines(id, &lines);
+                Ok(())
+            }
+            ["seeks"] => {
+                let lines: Vec<String> = self
+                    .offers
+                    .iter()
+                    .filter(|(_, offer)| offer.to.is_none())
+                    .map(|(&offer_id, offer)| offer.describe(offer_id))
+                    .collect();
+                self.send_lines(id, &lines);
+                Ok(())
+            }
+            ["games"] => {
+                let lines: Vec<String> = self
+                    .games
+                    .iter()
+                    .map(|(&game_id, game)| game.describe(game_id))
+                    .collect();
+                self.send_lines(id, &lines);
+                Ok(())
+            }
+            ["move", game, m] => {
+                let game_id = id_arg(game)?;
+                let m = m.parse()?;
+                let seat = self.seat(game_id, &name)?;
+                self.play(game_id, seat, m)
+            }
+            ["resign", game] => {
+                let game_id = id_arg(game)?;
+                let seat = self.seat(game_id, &name)?;
+                self.finish(game_id, winner(1 - seat), "resignation");
+                Ok(())
+            }
+            ["draw", game, reply] => {
+                let game_id = id_arg(game)?;
+                let seat = self.seat(game_id, &name)?;
+                self.draw(game_id, seat, reply)
+            }
+            ["quit"] => {
+                self.disconnect(id);
+                Ok(())
+            }
+            _ => bail!("unknown command: {}", line),
+        }
+    }
+
+    fn login(&mut self, id: usize, version: &str, name: &str, rest: &[&str]) -> Result<()> {
+        if version.parse::<u32>().ok() != Some(PROTOCOL_VERSION) {
+            bail!(
+                "unsupported protocol version {}, expected {}",
+                version,
+                PROTOCOL_VERSION
+            );
+        }
+        validate_name(name)?;
+        if self.find(name).is_some() {
+            bail!("name {} is already taken", name);
+        }
+        let bot = match rest {
+            [] => false,
+            ["bot"] => true,
+            _ => bail!("expected hello {} <name> [bot]", PROTOCOL_VERSION),
+        };
+
+        let client = self.clients.get_mut(&id).unwrap();
+        client.name = Some(name.to_string());
+        client.bot = bot;
+        println!("{} connected", name);
+        self.send(id, &format!("welcome {}", name));
+
+        let resumed: Vec<(usize, String, String)> = self
+            .games
+            .iter()
+            .filter_map(|(&game_id, game)| {
+                let seat = game.seat(name)?;
+                Some((
+                    game_id,
+                    game.players[1 - seat].clone(),
+                    format!(
+                        "start {} {} {} {}\n{}",
+                        game_id,
+                        game.players[0],
+                        game.players[1],
+                        game.time_control,
+                        game.position(game_id)
+                    ),
+                ))
+            })
+            .collect();
+        for (game_id, opponent, lines) in resumed {
+            self.send(id, &lines);
+            self.send_to(&opponent, &format!("reconnected {} {}", game_id, name));
+        }
+        Ok(())
+    }
+
+    fn seek(
+        &mut self,
+        name: String,
+        time_control: TimeControl,
+        color: Option<Piece>,
+    ) -> Result<()> {
+        let matching = self.offers.iter().find(|(_, offer)| {
+            offer.to.is_none()
+                && offer.from != name
+                && offer.time_control == time_control
+                && (offer.color.is_none() || offer.color != color)
+        });
+        if let Some((&offer, _)) = matching {
+            return self.accept(&name, offer);
+        }
+
+        let id = self.next_id();
+        let offer = Offer {
+            from: name,
+            to: None,
+            time_control,
+            color,
+        };
+        let line = offer.describe(id);
+        self.offers.insert(id, offer);
+        self.broadcast(&line);
+        Ok(())
+    }
+
+    fn challenge(
+        &mut self,
+        name: String,
+        opponent: &str,
+        time_control: TimeControl,
+        color: Option<Piece>,
+    ) -> Result<()> {
+        if opponent == name {
+            bail!("you cannot challenge yourself");
+        }
+        let target = self
+            .find(opponent)
+            .ok_or_else(|| anyhow!("no player named {}", opponent))?;
+
+        let id = self.next_id();
+        let offer = Offer {
+            from: name.clone(),
+            to: Some(opponent.to_string()),
+            time_control,
+            color,
+        };
+        let line = offer.describe(id);
+        self.offers.insert(id, offer);
+
+        if self.clients[&target].spec.is_some() {
+            return self.accept(opponent, id);
+        }
+        self.send_to(&name, &line);
+        self.send_to(opponent, &line);
+        Ok(())
+    }
+
+    fn accept(&mut self, name: &str, id: usize) -> Result<()> {
+        let offer = match self.offers.get(&id) {
+            Some(offer) if offer.from == name => bail!("you cannot accept your own offer"),
+            Some(offer) if offer.to.as_ref().is_some_and(|to| to != name) => {
+                bail!("challenge {} is not for you", id)
+            }
+            Some(_) => self.offers.remove(&id).unwrap(),
+            None => bail!("no offer {}", id),
+        };
+        if offer.to.is_none() {
+            self.broadcast(&format!("cancelled {}", id));
+        }
+
+        let white_first = match offer.color {
+            Some(color) => color.is_white(),
+            None => rand::random(),
+        };
+        let players = if white_first {
+            [offer.from, name.to_string()]
+        } else {
+            [name.to_string(), offer.from]
+        };
+        self.start(players, offer.time_control)
+    }
+
+    fn start(&mut self, players: [String; 2], time_control: TimeControl) -> Result<()> {
+        let mut bots = [None, None];
+        for (bot, name) in bots.iter_mut().zip(&players) {
+            let spec = self
+                .find(name)
+                .and_then(|id| self.clients[&id].spec.as_ref());
+            if let Some(spec) = spec {
+                let mut player = Player::new(spec)?;
+                player.new_game()?;
+                *bot = Some(player);
+            }
+        }
+
+        let board = Board::default();
+        let id = self.next_id();
+        let game = Game {
+            repetitions: HashMap::from([(board.hash(), 1)]),
+            board,
+            players,
+            bots,
+            time_control,
+            moves: Vec::new(),
+            san: Vec::new(),
+            quiet_plies: 0,
+            clock: [time_control.base; 2],
+            turn_start: Instant::now(),
+            draw_offer: None,
+        };
+
+        let line = format!(
+            "start {} {} {} {}",
+            id, game.players[0], game.players[1], time_control
+        );
+        println!("{}", line);
+        for player in &game.players {
+            self.send_to(player, &line);
+        }
+        self.games.insert(id, game);
+        self.think(id);
+        Ok(())
+    }
+
+    fn play(&mut self, id: usize, seat: usize, m: Move) -> Result<()> {
+        let game = self.games.get_mut(&id).unwrap();
+        if game.side() != seat {
+            bail!("it is not your turn in game {}", id);
+        }
+        if !game.board.get_all_moves().contains(&m) {
+            bail!("illegal move {} in game {}", m, id);
+        }
+
+        let elapsed = game.turn_start.elapsed();
+        if elapsed >= game.clock[seat] {
+            self.finish(id, winner(1 - seat), "time forfeit");
+            return Ok(());
+        }
+        game.clock[seat] = game.clock[seat] - elapsed + game.time_control.increment;
+        game.turn_start = Instant::now();
+
+        let zeroing =
+            game.board.is_capture(m) || game.board.get_square(m.from).split().0 == Piece::Pawn;
+        game.quiet_plies = if zeroing { 0 } else { game.quiet_plies + 1 };
+        game.san.push(pgn::to_san(&mut game.board, m));
+        game.moves.push(m);
+        game.board.make_move(m);
+        game.board.update_status();
+        let repetitions = game.repetitions.entry(game.board.hash()).or_default();
+        *repetitions += 1;
+        let repetitions = *repetitions;
+        if game.draw_offer == Some(1 - seat) {
+            game.draw_offer = None;
+        }
+
+        let line = format!(
+            "move {} {} {} {}",
+            id,
+            m,
+            game.clock[0].as_millis(),
+            game.clock[1].as_millis()
+        );
+        let players = game.players.clone();
+        let status = game.board.get_status();
+        let quiet_plies = game.quiet_plies;
+        for player in &players {
+            self.send_to(player, &line);
+        }
+
+        match status {
+            Status::Checkmate => self.finish(id, winner(seat), "checkmate"),
+            Status::Stalemate => self.finish(id, Outcome::Draw, "stalemate"),
+            Status::Dead => self.finish(id, Outcome::Draw, "insufficient material"),
+            Status::Playing | Status::Ended => {
+                if repetitions >= 3 {
+                    self.finish(id, Outcome::Draw, "threefold repetition");
+                } else if quiet_plies >= FIFTY_MOVE_PLIES {
+                    self.finish(id, Outcome::Draw, "fifty move rule");
+                } else {
+                    self.think(id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, id: usize, seat: usize, reply: &str) -> Result<()> {
+        let game = self.games.get_mut(&id).unwrap();
+        let opponent = game.players[1 - seat].clone();
+        match reply {
+            "offer" if game.draw_offer == Some(1 - seat) => {
+                self.finish(id, Outcome::Draw, "agreement")
+            }
+            "offer" if game.bots[1 - seat].is_some() => {
+                let name = game.players[seat].clone();
+                self.send_to(&name, &format!("draw {} decline", id));
+            }
+            "offer" => {
+                game.draw_offer = Some(seat);
+                self.send_to(&opponent, &format!("draw {} offer", id));
+            }
+            "accept" if game.draw_offer == Some(1 - seat) => {
+                self.finish(id, Outcome::Draw, "agreement")
+            }
+            "decline" if game.draw_offer == Some(1 - seat) => {
+                game.draw_offer = None;
+                self.send_to(&opponent, &format!("draw {} decline", id));
+            }
+            "accept" | "decline" => bail!("no draw offer in game {}", id),
+            _ => bail!("expected draw {} offer|accept|decline", id),
+        }
+        Ok(())
+    }
+
+    fn think(&mut self, id: usize) {
+        let game = self.games.get_mut(&id).unwrap();
+        let side = game.side();
+        let mut player = match game.bots[side].take() {
+            Some(player) => player,
+            None => return,
+        };
+
+        let board = game.board.clone();
+        let movetime = search::allocate_time(
+            game.remaining(side),
+            Some(game.remaining(1 - side)),
+            game.time_control.increment,
+            None,
+        );
+        let events = self.events.clone();
+        thread::spawn(move || {
+            let result = player
+                .think(&board, Limits::movetime(movetime))
+                .map(|(m, _)| m);
+            let _ = events.send(Event::BotMove(id, player, result));
+        });
+    }
+
+    fn bot_move(&mut self, id: usize, player: Player, result: Result<Option<Move>>) {
+        let game = match self.games.get_mut(&id) {
+            Some(game) => game,
+            None => return,
+        };
+        let side = game.side();
+        game.bots[side] = Some(player);
+
+        let failed = match result.map(|m| m.ok_or_else(|| anyhow!("no move returned"))) {
+            Ok(Ok(m)) => match self.play(id, side, m) {
+                Ok(()) => return,
+                Err(err) => err,
+            },
+            Ok(Err(err)) | Err(err) => err,
+        };
+        eprintln!("bot failed in game {}: {:#}", id, failed);
+        self.finish(id, winner(1 - side), "bot failure");
+    }
+
+    fn check_flags(&mut self) {
+        let flagged: Vec<(usize, usize)> = self
+            .games
+            .iter()
+            .filter(|(_, game)| game.remaining(game.side()).is_zero())
+            .map(|(&id, game)| (id, game.side()))
+            .collect();
+        for (id, side) in flagged {
+            self.finish(id, winner(1 - side), "time forfeit");
+        }
+    }
+
+    fn finish(&mut self, id: usize, outcome: Outcome, reason: &str) {
+        let game = match self.games.remove(&id) {
+            Some(game) => game,
+            None => return,
+        };
+
+        let line = format!("end {} {} {}", id, outcome.token(), reason);
+        println!("{}", line);
+        for player in &game.players {
+            self.send_to(player, &line);
+        }
+
+        if let Some(path) = &self.pgn {
+            let record = pgn::Game {
+                tags: vec![
+                    ("Event".to_string(), "chess-ai server".to_string()),
+                    ("Round".to_string(), id.to_string()),
+                    ("White".to_string(), game.players[0].clone()),
+                    ("Black".to_string(), game.players[1].clone()),
+                    ("Result".to_string(), outcome.token().to_string()),
+                    (
+                        "TimeControl".to_string(),
+                        format!(
+                            "{}+{}",
+                            game.time_control.base.as_secs(),
+                            game.time_control.increment.as_secs()
+                        ),
+                    ),
+                    ("Termination".to_string(), reason.to_string()),
+                ],
+                moves: game.san,
+                outcome,
+            };
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", record));
+            if let Err(err) = written {
+                eprintln!("could not write {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    fn cancel_offer(&mut self, id: usize) {
+        let offer = match self.offers.remove(&id) {
+            Some(offer) => offer,
+            None => return,
+        };
+        let line = format!("cancelled {}", id);
+        match &offer.to {
+            Some(to) => {
+                self.send_to(&offer.from, &line);
+                self.send_to(to, &line);
+            }
+            None => self.broadcast(&line),
+        }
+    }
+
+    fn seat(&self, id: usize, name: &str) -> Result<usize> {
+        self.games
+            .get(&id)
+            .and_then(|game| game.seat(name))
+            .ok_or_else(|| anyhow!("you are not playing game {}", id))
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.clients
+            .iter()
+            .find(|(_, client)| client.name.as_deref() == Some(name))
+            .map(|(&id, _)| id)
+    }
+
+    fn next_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn send(&mut self, id: usize, line: &str) {
+        if let Some(stream) = self
+            .clients
+            .get_mut(&id)
+            .and_then(|client| client.stream.as_mut())
+        {
+            let _ = writeln!(stream, "{}", line).and_then(|_| stream.flush());
+        }
+    }
+
+    fn send_lines(&mut self, id: usize, lines: &[String]) {
+        for line in lines {
+            self.send(id, line);
+        }
+    }
+
+    fn send_to(&mut self, name: &str, line: &str) {
+        if let Some(id) = self.find(name) {
+            self.send(id, line);
+        }
+    }
+
+    fn broadcast(&mut self, line: &str) {
+        let ids: Vec<usize> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.name.is_some())
+            .map(|(&id, _)| id)
+            .collect();
+        for id in ids {
+            self.send(id, line);
+        }
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.len() > 32
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        bail!("invalid name {} (letters, digits, -, _ and . only)", name);
+    }
+    Ok(())
+}
+
+fn color_arg(tokens: &[&str]) -> Result<Option<Piece>> {
+    match tokens {
+        [] | ["random"] => Ok(None),
+        ["white"] => Ok(Some(Piece::White)),
+        ["black"] => Ok(Some(Piece::Black)),
+        _ => bail!("expected white, black or random"),
+    }
+}
+
+fn id_arg(token: &str) -> Result<usize> {
+    token
+        .parse()
+        .with_context(|| format!("invalid id {}", token))
+}
+
+fn side(color: Piece) -> usize {
+    usize::from(!color.is_white())
+}
+
+fn winner(side: usize) -> Outcome {
+    if side == 0 {
+        Outcome::WhiteWins
+    } else {
+        Outcome::BlackWins
+    }
+}
+
+fn color_name(color: Piece) -> &'static str {
+    if color.is_white() {
+        "white"
+    } else {
+        "black"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    struct TestClient {
+        reader: BufReader<TcpStream>,
+        stream: TcpStream,
+    }
+
+    impl TestClient {
+        fn login(address: SocketAddr, name: &str) -> Self {
+            let stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            let mut client = Self {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                stream,
+            };
+            client.expect(&format!("hello {}", PROTOCOL_VERSION));
+            client.send(&format!("hello {} {}", PROTOCOL_VERSION, name));
+            client.expect(&format!("welcome {}", name));
+            client
+        }
+
+        fn send(&mut self, line: &str) {
+            writeln!(self.stream, "{}", line).unwrap();
+        }
+
+        fn expect(&mut self, prefix: &str) -> String {
+            loop {
+                let mut line = String::new();
+                self.reader.read_line(&mut line).unwrap();
+                assert!(!line.is_empty(), "connection closed waiting for {}", prefix);
+                if line.starts_with(prefix) {
+                    return line.trim_end().to_string();
+                }
+            }
+        }
+    }
+
+    fn start_game(white: &mut TestClient, black: &mut TestClient, time_control: &str) -> usize {
+        white.send(&format!("seek {} white", time_control));
+        white.expect("seek ");
+        black.send(&format!("seek {}", time_control));
+        let start = white.expect("start ");
+        assert_eq!(black.expect("start "), start);
+
+        let tokens: Vec<&str> = start.split_whitespace().collect();
+        assert_eq!(tokens[2..4], ["alice", "bob"]);
+        tokens[1].parse().unwrap()
+    }
+
+    #[test]
+    fn parses_time_controls() {
+        let parsed: TimeControl = "3+2".parse().unwrap();
+        assert_eq!(parsed.base, Duration::from_secs(180));
+        assert_eq!(parsed.increment, Duration::from_secs(2));
+        assert_eq!(parsed.to_string(), "3+2");
+
+        for invalid in [
+            "nan", "inf", "-inf", "1e300", "0", "-1", "1+nan", "1+inf", "1+-1", "x",
+        ] {
+            assert!(invalid.parse::<TimeControl>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn ignores_lines_queued_after_quit() {
+        let (mut server, events, address) = Server::bind("127.0.0.1:0").unwrap();
+        thread::spawn(move || server.serve(events));
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "hello {} bob\nquit\nplayers\n", PROTOCOL_VERSION).unwrap();
+        let mut closed = String::new();
+        let _ = stream.read_to_string(&mut closed);
+
+        let mut alice = TestClient::login(address, "alice");
+        alice.send("players");
+        assert_eq!(alice.expect("player "), "player alice");
+    }
+
+    #[test]
+    fn plays_games_between_two_clients() {
+        let (mut server, events, address) = Server::bind("127.0.0.1:0").unwrap();
+        thread::spawn(move || server.serve(events));
+
+        let mut alice = TestClient::login(address, "alice");
+        let mut bob = TestClient::login(address, "bob");
+
+        let game = start_game(&mut alice, &mut bob, "1");
+        alice.send(&format!("move {} e2e4", game));
+        bob.expect(&format!("move {} e2e4 ", game));
+        bob.send(&format!("move {} e2e5", game));
+        bob.expect(&format!("error illegal move e2e5 in game {}", game));
+        bob.send(&format!("move {} e7e5", game));
+        alice.expect(&format!("move {} e7e5 ", game));
+        alice.send(&format!("resign {}", game));
+        let end = format!("end {} 0-1 resignation", game);
+        assert_eq!(alice.expect("end "), end);
+        assert_eq!(bob.expect("end "), end);
+
+        let game = start_game(&mut alice, &mut bob, "0.005");
+        let end = format!("end {} 0-1 time forfeit", game);
+        assert_eq!(alice.expect("end "), end);
+        assert_eq!(bob.expect("end "), end);
+    }
+}
@@ -0,0 +1,539 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    board::{Board, Move, Status},
+    engine::search::Line,
+    json, pgn,
+};
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const WRITE_TIMEOUT: Duration = Duration::from_millis(200);
+const EVAL_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_FRAME: usize = 1 << 16;
+const VIEWER_PAGE: &str = include_str!("../assets/spectate.html");
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+#[derive(Default)]
+struct Shared {
+    snapshot: String,
+    viewers: Vec<(usize, Sender<Arc<[u8]>>)>,
+    next_id: usize,
+}
+
+impl Shared {
+    fn broadcast(&mut self, message: &str) {
+        let frame: Arc<[u8]> = frame(OPCODE_TEXT, message.as_bytes()).into();
+        self.viewers
+            .retain(|(_, viewer)| viewer.send(frame.clone()).is_ok());
+    }
+}
+
+// each viewer gets its own writer so a slow socket never stalls the game loop
+fn spawn_writer(mut stream: TcpStream) -> Sender<Arc<[u8]>> {
+    let (sender, frames) = mpsc::channel::<Arc<[u8]>>();
+    thread::spawn(move || {
+        for frame in frames {
+            if stream.write_all(&frame).is_err() {
+                break;
+            }
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+    });
+    sender
+}
+
+pub struct Spectators {
+    shared: Arc<Mutex<Shared>>,
+    start_fen: String,
+    moves: Vec<Move>,
+    san: Vec<String>,
+    position: Board,
+    status: Status,
+    lines: Vec<Line>,
+    eval: Option<String>,
+    eval_sent: Instant,
+    eval_pending: bool,
+    used: [Duration; 2],
+    turn_start: Instant,
+}
+
+impl Spectators {
+    pub fn listen(address: &str, board: &Board) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("could not listen on {}", address))?;
+        println!("spectators can watch on http://{}/", listener.local_addr()?);
+
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let accepting = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().map_while(|stream| stream.ok()) {
+                let shared = accepting.clone();
+                thread::spawn(move || {
+                    let _ = serve(stream, shared);
+                });
+            }
+        });
+
+        let mut spectators = Self {
+            shared,
+            start_fen: String::new(),
+            moves: Vec::new(),
+            san: Vec::new(),
+            position: Board::default(),
+            status: board.get_status(),
+            lines: Vec::new(),
+            eval: None,
+            eval_sent: Instant::now(),
+            eval_pending: false,
+            used: [Duration::ZERO; 2],
+            turn_start: Instant::now(),
+        };
+        spectators.restart(board);
+        spectators.publish(board, None, None);
+        Ok(spectators)
+    }
+
+    pub fn update(&mut self, board: &Board, clocks: Option<[Duration; 2]>) {
+        let lines = board.analysis();
+        if lines != self.lines {
+            self.eval = lines
+                .first()
+                .map(|line| eval_json(line, board.get_current_turn().is_white()));
+            self.lines = lines;
+            self.eval_pending = true;
+        }
+
+        if board.hash() != self.position.hash() {
+            match self.find_move(board) {
+                Some(m) => {
+                    let side = usize::from(!self.position.get_current_turn().is_white());
+                    self.used[side] += self.turn_start.elapsed();
+                    self.turn_start = Instant::now();
+
+                    self.san.push(pgn::to_san(&mut self.position, m));
+                    self.moves.push(m);
+                    self.position.make_move(m);
+                    self.status = board.get_status();
+
+                    let message = format!(
+                        "{{\"type\":\"move\",\"ply\":{},\"uci\":\"{}\",\"san\":{},\"fen\":{},\"status\":\"{}\",\"clocks\":{},\"eval\":{}}}",
+                        self.moves.len(),
+                        m,
+                        json::string(self.san.last().unwrap()),
+                        json::string(&board.to_fen()),
+                        self.status,
+                        self.clocks_json(board, clocks),
+                        self.eval.as_deref().unwrap_or("null")
+                    );
+                    self.publish(board, clocks, Some(&message));
+                }
+                None => {
+                    self.resync(board);
+                    self.publish(board, clocks, None);
+                }
+            }
+            self.eval_pending = false;
+        } else if board.get_status() != self.status {
+            self.status = board.get_status();
+            self.publish(board, clocks, None);
+        } else if self.eval_pending && self.eval_sent.elapsed() >= EVAL_INTERVAL {
+            self.eval_pending = false;
+            self.eval_sent = Instant::now();
+            let message = format!(
+                "{{\"type\":\"eval\",\"eval\":{}}}",
+                self.eval.as_deref().unwrap_or("null")
+            );
+            self.publish(board, clocks, Some(&message));
+        }
+    }
+
+    fn find_move(&self, board: &Board) -> Option<Move> {
+        if board.get_ply() != self.position.get_ply() + 1 {
+            return None;
+        }
+
+        let mut position = self.position.clone();
+        position.get_all_moves().into_iter().find(|&m| {
+            let mut child = self.position.clone();
+            child.make_move(m);
+            child.hash() == board.hash()
+        })
+    }
+
+    fn resync(&mut self, board: &Board) {
+        if let Ok(mut position) = Board::from_fen(&self.start_fen) {
+            let plies = board.get_ply().checked_sub(position.get_ply());
+            if let Some(plies) = plies.filter(|&plies| plies <= self.moves.len()) {
+                for &m in &self.moves[..plies] {
+                    position.make_move(m);
+                }
+                if position.hash() == board.hash() {
+                    self.moves.truncate(plies);
+                    self.san.truncate(plies);
+                    self.position = position;
+                    self.status = board.get_status();
+                    return;
+                }
+            }
+        }
+        self.restart(board);
+    }
+
+    fn restart(&mut self, board: &Board) {
+        self.start_fen = board.to_fen();
+        self.moves.clear();
+        self.san.clear();
+        self.position = Board::from_fen(&self.start_fen).unwrap_or_default();
+        self.status = board.get_status();
+        self.used = [Duration::ZERO; 2];
+        self.turn_start = Instant::now();
+    }
+
+    fn publish(&mut self, board: &Board, clocks: Option<[Duration; 2]>, message: Option<&str>) {
+        let [white, black] = board.get_opponents();
+        let snapshot = format!(
+            "{{\"type\":\"snapshot\",\"white\":{},\"black\":{},\"start_fen\":{},\"fen\":{},\"moves\":{},\"san\":{},\"status\":\"{}\",\"clocks\":{},\"eval\":{}}}",
+            json::string(&white.to_string()),
+            json::string(&black.to_string()),
+            json::string(&self.start_fen),
+            json::string(&board.to_fen()),
+            json::array(&self.moves, |m| format!("\"{}\"", m)),
+            json::array(&self.san, |san| json::string(san)),
+            self.status,
+            self.clocks_json(board, clocks),
+            self.eval.as_deref().unwrap_or("null")
+        );
+
+        let mut shared = self.shared.lock().unwrap();
+        shared.broadcast(message.unwrap_or(&snapshot));
+        shared.snapshot = snapshot;
+    }
+
+    fn clocks_json(&self, board: &Board, clocks: Option<[Duration; 2]>) -> String {
+        let running = if board.get_status() != Status::Playing {
+            "null"
+        } else if board.get_current_turn().is_white() {
+            "\"white\""
+        } else {
+            "\"black\""
+        };
+        let (mode, times) = match clocks {
+            Some(remaining) => ("remaining", remaining),
+            None => ("used", self.used),
+        };
+        format!(
+            "{{\"mode\":\"{}\",\"white\":{},\"black\":{},\"running\":{}}}",
+            mode,
+            times[0].as_millis(),
+            times[1].as_millis(),
+            running
+        )
+    }
+}
+
+fn serve(stream: TcpStream, shared: Arc<Mutex<Shared>>) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut key = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let key = match key {
+        Some(key) => key,
+        None => {
+            let response = if request.starts_with("GET / ") {
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    VIEWER_PAGE.len(),
+                    VIEWER_PAGE
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".into()
+            };
+            stream.write_all(response.as_bytes())?;
+            return Ok(());
+        }
+    };
+
+    let accept = base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept
+    )?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+    let writer = spawn_writer(stream);
+
+    let id = {
+        let mut shared = shared.lock().unwrap();
+        let _ = writer.send(frame(OPCODE_TEXT, shared.snapshot.as_bytes()).into());
+        let id = shared.next_id;
+        shared.next_id += 1;
+        shared.viewers.push((id, writer.clone()));
+        id
+    };
+
+    while let Ok((opcode, payload)) = read_frame(&mut reader) {
+        let reply = match opcode {
+            OPCODE_CLOSE => break,
+            OPCODE_PING => frame(OPCODE_PONG, &payload),
+            _ => continue,
+        };
+        if writer.send(reply.into()).is_err() {
+            break;
+        }
+    }
+
+    shared
+        .lock()
+        .unwrap()
+        .viewers
+        .retain(|(viewer, _)| *viewer != id);
+    let _ = writer.send(frame(OPCODE_CLOSE, &[]).into());
+    Ok(())
+}
+
+fn read_frame(reader: &mut impl Read) -> Result<(u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    let length = match header[1] & 0x7F {
+        126 => {
+            let mut bytes = [0; 2];
+            reader.read_exact(&mut bytes)?;
+            u16::from_be_bytes(bytes) as usize
+        }
+        127 => {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            u64::from_be_bytes(bytes) as usize
+        }
+        length => length as usize,
+    };
+
+    let mut mask = [0; 4];
+    if masked {
+        reader.read_exact(&mut mask)?;
+    }
+    if length > MAX_FRAME {
+        bail!("frame too large: {} bytes", length);
+    }
+    let mut payload = vec![0; length];
+    reader.read_exact(&mut payload)?;
+    if masked {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+    Ok((opcode, payload))
+}
+
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (value, add) in h.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let triple = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn eval_json(line: &Line, white_to_move: bool) -> String {
+    let sign = if white_to_move { 1 } else { -1 };
+    let mate = match line.mate_in() {
+        Some(moves) => (moves * sign).to_string(),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"score\":{},\"mate\":{},\"depth\":{},\"pv\":{}}}",
+        line.score * sign,
+        mate,
+        line.depth,
+        json::array(&line.pv, |m| format!("\"{}\"", m))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn sha1_matches_known_digests() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(&sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn base64_pads_partial_groups() {
+        let cases = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (data, encoded) in cases {
+            assert_eq!(base64(data.as_bytes()), encoded);
+        }
+    }
+
+    #[test]
+    fn accepts_the_rfc_6455_handshake() {
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let accept = base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+        assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        assert_eq!(
+            frame(OPCODE_TEXT, b"Hello"),
+            [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]
+        );
+        let masked = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let (opcode, payload) = read_frame(&mut &masked[..]).unwrap();
+        assert_eq!((opcode, payload.as_slice()), (OPCODE_TEXT, &b"Hello"[..]));
+
+        for length in [125, 126, 1 << 16, (1 << 16) + 1] {
+            let payload = vec![7; length];
+            let encoded = frame(OPCODE_PING, &payload);
+            let header = match length {
+                125 => 2,
+                126 => 4,
+                _ => 10,
+            };
+            assert_eq!(encoded.len(), header + length);
+            match read_frame(&mut encoded.as_slice()) {
+                Ok(decoded) => assert_eq!(decoded, (OPCODE_PING, payload)),
+                Err(_) => assert!(length > MAX_FRAME),
+            }
+        }
+    }
+}
@@ -0,0 +1,587 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    board::Board,
+    engine::tablebase::{
+        self, canonical, kind_from_index, Material, Table, DRAW, INVALID, MAX_PIECES, UNKNOWN,
+    },
+    piece::Piece,
+};
+
+const PROMOTIONS: [usize; 4] = [4, 3, 2, 1];
+const MAX_DTM: usize = INVALID as usize - 2;
+
+pub fn run(args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("generate") => generate(&args[1..]),
+        _ => bail!("usage: tablebase generate [--output dir] [--pieces 3|4] [signature...]"),
+    }
+}
+
+fn generate(args: &[String]) -> Result<()> {
+    let mut output = PathBuf::from("tablebases");
+    let mut max_pieces = MAX_PIECES;
+    let mut signatures = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => match args.next() {
+                Some(dir) => output = PathBuf::from(dir),
+                None => bail!("--output expects a directory"),
+            },
+            "--pieces" => match args.next().map(|pieces| pieces.parse()) {
+                Some(Ok(pieces)) if (3..=MAX_PIECES).contains(&pieces) => max_pieces = pieces,
+                _ => bail!("--pieces expects 3 or {}", MAX_PIECES),
+            },
+            arg if arg.starts_with("--") => bail!("unexpected argument: {}", arg),
+            signature => signatures.push(Material::parse(signature)?),
+        }
+    }
+
+    if signatures.is_empty() {
+        signatures = all_materials(max_pieces);
+    }
+    std::fs::create_dir_all(&output)
+        .with_context(|| format!("could not create {}", output.display()))?;
+
+    let mut solved = HashMap::new();
+    for material in &signatures {
+        solve(material, &output, &mut solved)?;
+    }
+
+    Ok(())
+}
+
+fn all_materials(max_pieces: usize) -> Vec<Material> {
+    let mut materials = Vec::new();
+    for white in 0..5 {
+        materials.push(format!("K{}K", "QRBNP".as_bytes()[white] as char));
+    }
+    if max_pieces >= 4 {
+        for a in 0..5 {
+            for b in a..5 {
+                let (a, b) = ("QRBNP".as_bytes()[a] as char, "QRBNP".as_bytes()[b] as char);
+                materials.push(format!("K{}{}K", a, b));
+                materials.push(format!("K{}K{}", a, b));
+            }
+        }
+    }
+
+    materials
+        .iter()
+        .filter_map(|signature| Material::parse(signature).ok())
+        .collect()
+}
+
+fn solve(
+    material: &Material,
+    output: &Path,
+    solved: &mut HashMap<String, Arc<Vec<u8>>>,
+) -> Result<Arc<Vec<u8>>> {
+    if let Some(full) = solved.get(&material.signature) {
+        return Ok(full.clone());
+    }
+
+    let path = output
+        .join(&material.signature)
+        .with_extension(tablebase::EXTENSION);
+    let full = if path.exists() {
+        Arc::new(expand(material, &Table::load(&path)?))
+    } else {
+        let generator = Generator::new(material, output, solved)?;
+
+        let start = Instant::now();
+        let full = Arc::new(generator.run());
+        Table::new(material.clone(), &full).save(&path)?;
+        println!(
+            "{}: {} positions in {:.1}s",
+            material.signature,
+            material.reduced_size(),
+            start.elapsed().as_secs_f64()
+        );
+        full
+    };
+
+    solved.insert(material.signature.clone(), full.clone());
+    Ok(full)
+}
+
+fn expand(material: &Material, table: &Table) -> Vec<u8> {
+    let mut squares = vec![0; material.pieces.len()];
+    (0..material.full_size())
+        .map(|index| {
+            let white_to_move = material.decode_full_index(index, &mut squares);
+            table.get(&squares, white_to_move)
+        })
+        .collect()
+}
+
+struct Exit {
+    table: Option<Arc<Vec<u8>>>,
+    flip: bool,
+    order: Vec<usize>,
+}
+
+struct Generator {
+    material: Material,
+    kinds: Vec<Piece>,
+    white: Vec<bool>,
+    exits: HashMap<(usize, usize, usize), Exit>,
+}
+
+impl Generator {
+    #[allow(clippy::needless_range_loop)]
+    fn new(
+        material: &Material,
+        output: &Path,
+        solved: &mut HashMap<String, Arc<Vec<u8>>>,
+    ) -> Result<Self> {
+        let kinds: Vec<Piece> = material.pieces.iter().map(|p| p.split().0).collect();
+        let white: Vec<bool> = material.pieces.iter().map(Piece::is_white).collect();
+        let n = kinds.len();
+
+        let mut exits = HashMap::new();
+        for captured in 0..=n {
+            if captured < n && kinds[captured] == Piece::King {
+                continue;
+            }
+
+            for promoted in 0..=n {
+                let promotions: &[usize] = if promoted == n {
+                    &[0]
+                } else if kinds[promoted] == Piece::Pawn && promoted != captured {
+                    &PROMOTIONS
+                } else {
+                    continue;
+                };
+                if captured == n && promoted == n {
+                    continue;
+                }
+
+                for &kind in promotions {
+                    let mut pieces: Vec<(Piece, usize)> = (0..n)
+                        .filter(|&slot| slot != captured)
+                        .map(|slot| {
+                            let piece = if slot == promoted {
+                                kind_from_index(kind) | material.pieces[slot].color()
+                            } else {
+                                material.pieces[slot]
+                            };
+                            (piece, slot)
+                        })
+                        .collect();
+                    let mut flipped = false;
+                    let child = canonical(&mut pieces, &mut flipped);
+                    let order = pieces
+                        .iter()
+                        .map(|(_, slot)| if flipped { slot ^ 56 } else { *slot })
+                        .collect();
+
+                    let table = if child.pieces.len() == 2 {
+                        None
+                    } else {
+                        Some(solve(&child, output, solved)?)
+                    };
+                    exits.insert(
+                        (captured, promoted, kind),
+                        Exit {
+                            table,
+                            flip: flipped,
+                            order,
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(Self {
+            material: material.clone(),
+            kinds,
+            white,
+            exits,
+        })
+    }
+
+    fn run(&self) -> Vec<u8> {
+        let size = self.material.full_size();
+        let n = self.kinds.len();
+        let mut board = Board::default();
+
+        // symmetric positions have the same moves, so only the reduced table is
+        // searched and its nodes are copied over the full index space
+        let nodes: Vec<Node> = (0..self.material.reduced_size())
+            .map(|index| self.node(&mut board, index))
+            .collect();
+
+        let mut values = vec![UNKNOWN; size];
+        let mut counts = vec![0u8; size];
+        let mut floors = vec![0u8; size];
+        let mut buckets: Vec<Vec<u32>> = vec![Vec::new(); MAX_DTM + 1];
+
+        let mut squares = vec![0; n];
+        for index in 0..size {
+            let white_to_move = self.material.decode_full_index(index, &mut squares);
+            let node = nodes[self.material.reduced_index(&squares, white_to_move)];
+            values[index] = node.value;
+            counts[index] = node.count;
+            floors[index] = node.floor;
+            for &dtm in [node.win, node.loss].iter().flatten() {
+                buckets[dtm as usize].push(index as u32);
+            }
+        }
+
+        for dtm in 0..=MAX_DTM {
+            let bucket = std::mem::take(&mut buckets[dtm]);
+            for index in bucket {
+                let index = index as usize;
+                if values[index] != UNKNOWN {
+                    continue;
+                }
+                values[index] = dtm as u8 + 1;
+
+                let white_to_move = self.material.decode_full_index(index, &mut squares);
+                self.for_each_predecessor(&mut board, &squares, white_to_move, |parent| {
+                    if values[parent] != UNKNOWN {
+                        return;
+                    }
+                    if dtm % 2 == 0 {
+                        if dtm < MAX_DTM {
+                            buckets[dtm + 1].push(parent as u32);
+                        }
+                    } else {
+                        counts[parent] -= 1;
+                        if counts[parent] == 0 {
+                            let loss = (dtm + 1).max(floors[parent] as usize);
+                            if loss <= MAX_DTM {
+                                buckets[loss].push(parent as u32);
+                            }
+                        }
+                    }
+                });
+            }
+        }
+
+        for value in values.iter_mut() {
+            if *value == UNKNOWN {
+                *value = DRAW;
+            }
+        }
+        values
+    }
+
+    fn node(&self, board: &mut Board, index: usize) -> Node {
+        let n = self.kinds.len();
+        let mut squares = vec![0; n];
+        let white_to_move = self.material.decode_reduced_index(index, &mut squares);
+        let invalid = Node {
+            value: INVALID,
+            ..Node::default()
+        };
+        for (i, &square) in squares.iter().enumerate() {
+            if squares[..i].contains(&square) {
+                return invalid;
+            }
+            if self.kinds[i] == Piece::Pawn && !(8..56).contains(&square) {
+                return invalid;
+            }
+        }
+
+        let turn = color(white_to_move);
+        self.place(board, &squares, turn);
+        if board.is_check(turn.ennemy()) {
+            return invalid;
+        }
+
+        let moves = board.get_all_moves();
+        if moves.is_empty() {
+            return if board.is_in_check() {
+                Node {
+                    loss: Some(0),
+                    ..Node::default()
+                }
+            } else {
+                Node {
+                    value: DRAW,
+                    ..Node::default()
+                }
+            };
+        }
+
+        let mut count = 0u8;
+        let mut best_win: Option<usize> = None;
+        let mut floor = 0;
+        let mut child = squares.clone();
+        for m in moves {
+            let slot = squares.iter().position(|&square| square == m.from).unwrap();
+            let captured = squares
+                .iter()
+                .position(|&square| square == m.to)
+                .unwrap_or(n);
+            child.copy_from_slice(&squares);
+            child[slot] = m.to;
+
+            let exit = if !m.promotion.is_none() {
+                Some(self.exit_value(&child, white_to_move, captured, slot, m.promotion.index()))
+            } else if captured < n {
+                Some(self.exit_value(&child, white_to_move, captured, n, 0))
+            } else {
+                None
+            };
+            match exit.and_then(tablebase::Probe::decode) {
+                Some(probe) if probe.wdl == tablebase::Wdl::Loss => {
+                    let dtm = probe.dtm as usize + 1;
+                    best_win = Some(best_win.map_or(dtm, |best| best.min(dtm)));
+                    count += 1;
+                }
+                Some(probe) if probe.wdl == tablebase::Wdl::Win => {
+                    floor = floor.max(probe.dtm as usize + 1);
+                }
+                _ => count += 1,
+            }
+        }
+
+        Node {
+            value: UNKNOWN,
+            count,
+            floor: floor as u8,
+            win: best_win.filter(|&dtm| dtm <= MAX_DTM).map(|dtm| dtm as u8),
+            loss: (count == 0 && floor <= MAX_DTM).then_some(floor as u8),
+        }
+    }
+
+    fn place(&self, board: &mut Board, squares: &[usize], turn: Piece) {
+        let pieces: Vec<(Piece, usize)> = self
+            .material
+            .pieces
+            .iter()
+            .copied()
+            .zip(squares.iter().copied())
+            .collect();
+        board.set_pieces(&pieces, turn);
+    }
+
+    fn exit_value(
+        &self,
+        squares: &[usize],
+        white_to_move: bool,
+        captured: usize,
+        promoted: usize,
+        kind: usize,
+    ) -> u8 {
+        let exit = &self.exits[&(captured, promoted, kind)];
+        let table = match &exit.table {
+            Some(table) => table,
+            None => return DRAW,
+        };
+
+        let child: Vec<usize> = exit
+            .order
+            .iter()
+            .map(|&slot| {
+                if exit.flip {
+                    squares[slot] ^ 56
+                } else {
+                    squares[slot]
+                }
+            })
+            .collect();
+        table[Material::full_index(&child, white_to_move == exit.flip)]
+    }
+
+    fn for_each_predecessor(
+        &self,
+        board: &mut Board,
+        squares: &[usize],
+        white_to_move: bool,
+        mut visit: impl FnMut(usize),
+    ) {
+        let mover = !white_to_move;
+        let mut parent = squares.to_vec();
+        self.place(board, squares, color(mover));
+
+        for slot in 0..squares.len() {
+            if self.white[slot] != mover {
+                continue;
+            }
+
+            let square = squares[slot];
+            let origins: Vec<usize> = if self.kinds[slot] == Piece::Pawn {
+                let mut origins = Vec::with_capacity(2);
+                let backward: isize = if mover { 8 } else { -8 };
+                let one = (square as isize + backward) as usize;
+                let first_row = if mover { 7 } else { 0 };
+                if one / 8 != first_row && !squares.contains(&one) {
+                    origins.push(one);
+                    let double_row = if mover { 4 } else { 3 };
+                    let two = (square as isize + 2 * backward) as usize;
+                    if square / 8 == double_row && !squares.contains(&two) {
+                        origins.push(two);
+                    }
+                }
+                origins
+            } else {
+                // without captures the other pieces move the same way backwards
+                self.material.pieces[slot]
+                    .legal_moves([square % 8, square / 8], board)
+                    .iter()
+                    .map(|[x, y]| y * 8 + x)
+                    .filter(|to| !squares.contains(to))
+                    .collect()
+            };
+
+            for from in origins {
+                parent.copy_from_slice(squares);
+                parent[slot] = from;
+                visit(Material::full_index(&parent, mover));
+            }
+        }
+    }
+}
+
+fn color(white: bool) -> Piece {
+    if white {
+        Piece::White
+    } else {
+        Piece::Black
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    value: u8,
+    count: u8,
+    floor: u8,
+    win: Option<u8>,
+    loss: Option<u8>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self {
+            value: UNKNOWN,
+            count: 0,
+            floor: 0,
+            win: None,
+            loss: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::engine::tablebase::{Probe, Tablebase, Wdl};
+
+    fn generate_into(name: &str, signatures: &[&str]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("chess-ai-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut args = vec!["--output".to_string(), dir.display().to_string()];
+        args.extend(signatures.iter().map(|signature| signature.to_string()));
+        generate(&args).unwrap();
+        dir
+    }
+
+    fn longest_win(dir: &Path, signature: &str) -> u8 {
+        let material = Material::parse(signature).unwrap();
+        let table = Table::load(dir.join(signature).with_extension(tablebase::EXTENSION)).unwrap();
+        expand(&material, &table)
+            .into_iter()
+            .filter_map(Probe::decode)
+            .filter(|probe| probe.wdl == Wdl::Win)
+            .map(|probe| probe.dtm)
+            .max()
+            .unwrap()
+    }
+
+    fn probe(tablebase: &Tablebase, fen: &str) -> Option<(Wdl, u8)> {
+        let board = Board::from_fen(fen).unwrap();
+        tablebase.probe(&board).map(|probe| (probe.wdl, probe.dtm))
+    }
+
+    fn play_out(tablebase: &Tablebase, fen: &str) {
+        let mut board = Board::from_fen(fen).unwrap();
+        let dtm = tablebase.probe(&board).unwrap().dtm;
+        let mut plies = 0;
+        while let Some((m, _)) = tablebase.best_move(&board) {
+            board.make_move(m);
+            plies += 1;
+        }
+        assert!(
+            board.is_in_check() && board.get_all_moves().is_empty(),
+            "{}",
+            fen
+        );
+        assert_eq!(plies, dtm, "{}", fen);
+    }
+
+    #[test]
+    fn generates_three_piece_tables() {
+        let dir = generate_into("tablebases", &["KQK", "KRK", "KPK"]);
+        assert_eq!(longest_win(&dir, "KQK"), 19);
+        assert_eq!(longest_win(&dir, "KRK"), 31);
+
+        let tablebase = Tablebase::open(&dir).unwrap();
+        for (fen, expected) in [
+            ("7k/8/6K1/8/8/8/Q7/8 w - - 0 1", (Wdl::Win, 1)),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", (Wdl::Draw, 0)),
+            ("6k1/8/6K1/8/8/8/8/R7 w - - 0 1", (Wdl::Win, 1)),
+            ("k7/8/1K6/8/8/8/8/7R b - - 0 1", (Wdl::Loss, 2)),
+            ("8/8/8/8/8/8/6Rk/K7 b - - 0 1", (Wdl::Draw, 0)),
+            ("k7/8/8/8/8/8/P7/K7 w - - 0 1", (Wdl::Draw, 0)),
+            ("8/8/8/8/8/3k4/4P3/K7 b - - 0 1", (Wdl::Draw, 0)),
+        ] {
+            assert_eq!(probe(&tablebase, fen), Some(expected), "{}", fen);
+        }
+        for fen in [
+            "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1",
+            "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1",
+        ] {
+            let wdl = if fen.contains(" w ") {
+                Wdl::Win
+            } else {
+                Wdl::Loss
+            };
+            assert_eq!(
+                probe(&tablebase, fen).map(|(wdl, _)| wdl),
+                Some(wdl),
+                "{}",
+                fen
+            );
+        }
+
+        play_out(&tablebase, "8/8/8/4k3/8/8/8/R3K3 w - - 0 1");
+        play_out(&tablebase, "8/8/8/3k4/8/8/8/Q3K3 w - - 0 1");
+        play_out(&tablebase, "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[ignore = "generates KRKP and its sub-tables, run with --release -- --ignored"]
+    fn generates_krkp() {
+        let dir = generate_into("tablebases-krkp", &["KRKP"]);
+        let tablebase = Tablebase::open(&dir).unwrap();
+        for (fen, wdl) in [
+            ("4k3/8/8/8/8/8/p7/R3K3 w - - 0 1", Wdl::Win),
+            ("4k3/8/8/8/8/8/p7/R3K3 b - - 0 1", Wdl::Loss),
+        ] {
+            assert_eq!(
+                probe(&tablebase, fen).map(|(wdl, _)| wdl),
+                Some(wdl),
+                "{}",
+                fen
+            );
+        }
+        play_out(&tablebase, "4k3/8/8/8/8/8/p7/R3K3 w - - 0 1");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
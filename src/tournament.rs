@@ -0,0 +1,725 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    match_play::{self, Opening, Player, Settings, Spec},
+    pgn::{Game, Outcome},
+};
+
+const DEFAULT_STATE: &str = "tournament.state";
+const DEFAULT_PGN: &str = "tournament.pgn";
+const STATE_HEADER: &str = "# chess-ai tournament state";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    RoundRobin,
+    Gauntlet,
+    Swiss,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "round-robin" => Ok(Self::RoundRobin),
+            "gauntlet" => Ok(Self::Gauntlet),
+            "swiss" => Ok(Self::Swiss),
+            _ => bail!(
+                "unknown format: {} (expected round-robin, gauntlet or swiss)",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::RoundRobin => write!(f, "round-robin"),
+            Self::Gauntlet => write!(f, "gauntlet"),
+            Self::Swiss => write!(f, "swiss"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pairing {
+    id: usize,
+    round: usize,
+    white: usize,
+    black: usize,
+    opening: usize,
+}
+
+struct State {
+    file: File,
+    games: BTreeMap<usize, (Pairing, Outcome)>,
+    byes: BTreeMap<usize, usize>,
+}
+
+impl State {
+    fn open(path: &Path, header: &[String]) -> Result<Self> {
+        let mut state = Self {
+            file: OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("could not open {}", path.display()))?,
+            games: BTreeMap::new(),
+            byes: BTreeMap::new(),
+        };
+
+        let text = fs::read_to_string(path)?;
+        if text.is_empty() {
+            writeln!(state.file, "{}", STATE_HEADER)?;
+            for line in header {
+                writeln!(state.file, "{}", line)?;
+            }
+            state.file.flush()?;
+            return Ok(state);
+        }
+
+        let mut found = Vec::new();
+        for line in text.lines().filter(|line| !line.starts_with('#')) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let invalid = || format!("invalid line in {}: {}", path.display(), line);
+            let number = |i: usize| -> Result<usize> {
+                fields
+                    .get(i)
+                    .and_then(|field| field.parse().ok())
+                    .with_context(invalid)
+            };
+
+            match fields.first().copied() {
+                Some("game") => {
+                    let pairing = Pairing {
+                        id: number(1)?,
+                        round: number(2)?,
+                        white: number(3)?,
+                        black: number(4)?,
+                        opening: number(5)?,
+                    };
+                    let outcome = match fields.get(6).copied() {
+                        Some("1-0") => Outcome::WhiteWins,
+                        Some("0-1") => Outcome::BlackWins,
+                        Some("1/2-1/2") => Outcome::Draw,
+                        _ => bail!(invalid()),
+                    };
+                    state.games.insert(pairing.id, (pairing, outcome));
+                }
+                Some("bye") => {
+                    state.byes.insert(number(1)?, number(2)?);
+                }
+                Some(_) => found.push(line.to_string()),
+                None => (),
+            }
+        }
+        if found != header {
+            bail!(
+                "{} belongs to a different tournament, remove it or pass another --state",
+                path.display()
+            );
+        }
+
+        Ok(state)
+    }
+
+    fn record_game(&mut self, pairing: Pairing, outcome: Outcome) -> Result<()> {
+        writeln!(
+            self.file,
+            "game {} {} {} {} {} {}",
+            pairing.id,
+            pairing.round,
+            pairing.white,
+            pairing.black,
+            pairing.opening,
+            outcome.token()
+        )?;
+        self.file.flush()?;
+        self.games.insert(pairing.id, (pairing, outcome));
+        Ok(())
+    }
+
+    fn record_bye(&mut self, round: usize, player: usize) -> Result<()> {
+        writeln!(self.file, "bye {} {}", round, player)?;
+        self.file.flush()?;
+        self.byes.insert(round, player);
+        Ok(())
+    }
+
+    fn points(&self, players: usize, before_round: usize) -> Vec<f64> {
+        let mut points = vec![0.; players];
+        for (pairing, outcome) in self.games.values() {
+            if pairing.round < before_round {
+                let white_score = match_play::white_score(*outcome);
+                points[pairing.white] += white_score;
+                points[pairing.black] += 1. - white_score;
+            }
+        }
+        for (&round, &player) in &self.byes {
+            if round < before_round {
+                points[player] += 1.;
+            }
+        }
+        points
+    }
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut engines = Vec::new();
+    let mut format = Format::RoundRobin;
+    let mut rounds = None;
+    let mut state_path = PathBuf::from(DEFAULT_STATE);
+    let mut pgn_path = PathBuf::from(DEFAULT_PGN);
+    let mut settings = Settings::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--engine" => match args.next() {
+                Some(spec) => engines.push(spec.clone()),
+                None => bail!("--engine expects an engine"),
+            },
+            "--format" => match args.next() {
+                Some(name) => format = name.parse()?,
+                None => bail!("--format expects round-robin, gauntlet or swiss"),
+            },
+            "--rounds" => match args.next().map(|rounds| rounds.parse()) {
+                Some(Ok(value)) if value > 0 => rounds = Some(value),
+                _ => bail!("--rounds expects a positive number"),
+            },
+            "--state" => match args.next() {
+                Some(path) => state_path = PathBuf::from(path),
+                None => bail!("--state expects a file"),
+            },
+            "--pgn" => match args.next() {
+                Some(path) => pgn_path = PathBuf::from(path),
+                None => bail!("--pgn expects a file"),
+            },
+            arg => {
+                if !settings.parse_arg(arg, &mut args)? {
+                    bail!("unexpected argument: {}", arg);
+                }
+            }
+        }
+    }
+
+    if engines.len() < 2 {
+        bail!("a tournament needs at least two --engine");
+    }
+    let specs = engines
+        .iter()
+        .map(|spec| Spec::parse(spec))
+        .collect::<Result<Vec<_>>>()?;
+    let openings = settings.openings()?;
+    let rounds = rounds.unwrap_or(match format {
+        Format::Swiss => (specs.len() as f64).log2().ceil() as usize,
+        _ => 1,
+    });
+
+    let mut header = vec![format!("format {}", format), format!("rounds {}", rounds)];
+    header.extend(engines.iter().map(|spec| format!("engine {}", spec)));
+    let mut state = State::open(&state_path, &header)?;
+    let resumed = !state.games.is_empty() || !state.byes.is_empty();
+    if resumed {
+        println!(
+            "resuming from {}: {} games already played",
+            state_path.display(),
+            state.games.len()
+        );
+    }
+
+    let mut output = BufWriter::new(
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&pgn_path)
+            .with_context(|| format!("could not open {}", pgn_path.display()))?,
+    );
+    println!(
+        "{} tournament, {} engines, {} round(s), concurrency {}",
+        format,
+        specs.len(),
+        rounds,
+        settings.concurrency
+    );
+
+    match format {
+        Format::RoundRobin | Format::Gauntlet => {
+            let pairings = schedule(format, specs.len(), rounds, openings.len());
+            play_pairings(
+                &specs,
+                &settings,
+                &openings,
+                &pairings,
+                &mut state,
+                &mut output,
+            )?;
+        }
+        Format::Swiss => {
+            for round in 1..=rounds {
+                let (pairings, bye) = swiss_round(&state, specs.len(), round, openings.len());
+                if let Some(player) = bye.filter(|_| !state.byes.contains_key(&round)) {
+                    println!("round {}: {} has a bye", round, specs[player].name);
+                    state.record_bye(round, player)?;
+                }
+                play_pairings(
+                    &specs,
+                    &settings,
+                    &openings,
+                    &pairings,
+                    &mut state,
+                    &mut output,
+                )?;
+            }
+        }
+    }
+
+    print_crosstable(&specs, &state);
+    println!("games written to {}", pgn_path.display());
+
+    Ok(())
+}
+
+fn schedule(format: Format, players: usize, rounds: usize, openings: usize) -> Vec<Pairing> {
+    let pairs: Vec<(usize, usize)> = match format {
+        Format::Gauntlet => (1..players).map(|opponent| (0, opponent)).collect(),
+        _ => (0..players)
+            .flat_map(|i| (i + 1..players).map(move |j| (i, j)))
+            .collect(),
+    };
+
+    let mut pairings = Vec::new();
+    for round in 1..=rounds {
+        for &(i, j) in &pairs {
+            for (white, black) in [(i, j), (j, i)] {
+                let id = pairings.len();
+                pairings.push(Pairing {
+                    id,
+                    round,
+                    white,
+                    black,
+                    opening: id / 2 % openings,
+                });
+            }
+        }
+    }
+    pairings
+}
+
+fn swiss_round(
+    state: &State,
+    players: usize,
+    round: usize,
+    openings: usize,
+) -> (Vec<Pairing>, Option<usize>) {
+    let points = state.points(players, round);
+    let mut order: Vec<usize> = (0..players).collect();
+    order.sort_by(|&a, &b| points[b].total_cmp(&points[a]).then(a.cmp(&b)));
+
+    let mut played = HashSet::new();
+    let mut colors = vec![0; players];
+    for (pairing, _) in state
+        .games
+        .values()
+        .filter(|(pairing, _)| pairing.round < round)
+    {
+        played.insert((pairing.white, pairing.black));
+        played.insert((pairing.black, pairing.white));
+        colors[pairing.white] += 1;
+        colors[pairing.black] -= 1;
+    }
+
+    let bye = if players % 2 == 1 {
+        let had_bye: HashSet<usize> = state
+            .byes
+            .iter()
+            .filter(|(&bye_round, _)| bye_round < round)
+            .map(|(_, &player)| player)
+            .collect();
+        let bye = order
+            .iter()
+            .rev()
+            .find(|player| !had_bye.contains(player))
+            .or(order.last())
+            .copied();
+        order.retain(|&player| Some(player) != bye);
+        bye
+    } else {
+        None
+    };
+
+    let pairs = pair(&order, &played)
+        .unwrap_or_else(|| order.chunks(2).map(|pair| (pair[0], pair[1])).collect());
+
+    let pairings = pairs
+        .into_iter()
+        .enumerate()
+        .map(|(i, (higher, lower))| {
+            let (white, black) = if colors[lower] < colors[higher] {
+                (lower, higher)
+            } else {
+                (higher, lower)
+            };
+            let id = (round - 1) * (players / 2) + i;
+            Pairing {
+                id,
+                round,
+                white,
+                black,
+                opening: id % openings,
+            }
+        })
+        .collect();
+
+    (pairings, bye)
+}
+
+fn pair(order: &[usize], played: &HashSet<(usize, usize)>) -> Option<Vec<(usize, usize)>> {
+    let (&first, rest) = match order.split_first() {
+        Some(split) => split,
+        None => return Some(Vec::new()),
+    };
+
+    for (i, &opponent) in rest.iter().enumerate() {
+        if played.contains(&(first, opponent)) {
+            continue;
+        }
+
+        let mut remaining = rest.to_vec();
+        remaining.remove(i);
+        if let Some(mut pairs) = pair(&remaining, played) {
+            pairs.insert(0, (first, opponent));
+            return Some(pairs);
+        }
+    }
+
+    None
+}
+
+fn play_pairings(
+    specs: &[Spec],
+    settings: &Settings,
+    openings: &[Opening],
+    pairings: &[Pairing],
+    state: &mut State,
+    output: &mut BufWriter<File>,
+) -> Result<()> {
+    let pending: Vec<Pairing> = pairings
+        .iter()
+        .filter(|pairing| !state.games.contains_key(&pairing.id))
+        .copied()
+        .collect();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let next = AtomicUsize::new(0);
+    let abort = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| -> Result<()> {
+        for _ in 0..settings.concurrency.min(pending.len()) {
+            let sender = sender.clone();
+            let (pending, next, abort) = (&pending, &next, &abort);
+            scope.spawn(move || {
+                let result = worker(specs, settings, openings, pending, next, abort, &sender);
+                if let Err(err) = result {
+                    let _ = sender.send(Err(err));
+                }
+            });
+        }
+        drop(sender);
+
+        for finished in receiver {
+            let (pairing, game, termination) = match finished {
+                Ok(finished) => finished,
+                Err(err) => {
+                    abort.store(true, Ordering::Relaxed);
+                    return Err(err);
+                }
+            };
+
+            state.record_game(pairing, game.outcome)?;
+            writeln!(output, "{}", game)?;
+            output.flush()?;
+            println!(
+                "round {} game {:>4}: {} - {} {} ({})",
+                pairing.round,
+                pairing.id + 1,
+                specs[pairing.white].name,
+                specs[pairing.black].name,
+                game.outcome.token(),
+                termination
+            );
+        }
+
+        Ok(())
+    })
+}
+
+fn worker(
+    specs: &[Spec],
+    settings: &Settings,
+    openings: &[Opening],
+    pending: &[Pairing],
+    next: &AtomicUsize,
+    abort: &AtomicBool,
+    sender: &mpsc::Sender<Result<(Pairing, Game, String)>>,
+) -> Result<()> {
+    let mut players: HashMap<usize, Player> = HashMap::new();
+
+    loop {
+        let pairing = match pending.get(next.fetch_add(1, Ordering::Relaxed)) {
+            Some(&pairing) if !abort.load(Ordering::Relaxed) => pairing,
+            _ => return Ok(()),
+        };
+
+        let mut white = match players.remove(&pairing.white) {
+            Some(player) => player,
+            None => Player::new(&specs[pairing.white])?,
+        };
+        let mut black = match players.remove(&pairing.black) {
+            Some(player) => player,
+            None => Player::new(&specs[pairing.black])?,
+        };
+        let played = match_play::play_game(
+            [&mut white, &mut black],
+            [&specs[pairing.white].name, &specs[pairing.black].name],
+            &openings[pairing.opening],
+            settings,
+            abort,
+        )?;
+        players.insert(pairing.white, white);
+        players.insert(pairing.black, black);
+
+        let (mut game, termination) = match played {
+            Some(played) => played,
+            None => return Ok(()),
+        };
+        game.tags
+            .insert(1, ("Round".to_string(), pairing.round.to_string()));
+        if sender.send(Ok((pairing, game, termination))).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+fn print_crosstable(specs: &[Spec], state: &State) {
+    let players = specs.len();
+    let points = state.points(players, usize::MAX);
+    let mut games = vec![0; players];
+    let mut against = vec![vec![None; players]; players];
+    let mut buchholz = vec![0.; players];
+    let mut sonneborn_berger = vec![0.; players];
+
+    for (pairing, outcome) in state.games.values() {
+        let white_score = match_play::white_score(*outcome);
+        for (player, opponent, score) in [
+            (pairing.white, pairing.black, white_score),
+            (pairing.black, pairing.white, 1. - white_score),
+        ] {
+            games[player] += 1;
+            *against[player][opponent].get_or_insert(0.) += score;
+            buchholz[player] += points[opponent];
+            sonneborn_berger[player] += score * points[opponent];
+        }
+    }
+
+    let mut ranking: Vec<usize> = (0..players).collect();
+    ranking.sort_by(|&a, &b| {
+        points[b]
+            .total_cmp(&points[a])
+            .then(sonneborn_berger[b].total_cmp(&sonneborn_berger[a]))
+            .then(buchholz[b].total_cmp(&buchholz[a]))
+            .then(a.cmp(&b))
+    });
+
+    let width = specs
+        .iter()
+        .map(|spec| spec.name.len())
+        .chain(["engine".len()])
+        .max()
+        .unwrap_or(0);
+    let mut header = format!(
+        "{:>3}  {:<width$}  {:>5}  {:>5}  {:>6}  {:>6} ",
+        "#",
+        "engine",
+        "score",
+        "games",
+        "sb",
+        "buch",
+        width = width
+    );
+    for rank in 1..=players {
+        header.push_str(&format!(" {:>4}", rank));
+    }
+    println!("{}", header);
+
+    for (rank, &player) in ranking.iter().enumerate() {
+        let mut row = format!(
+            "{:>3}  {:<width$}  {:>5.1}  {:>5}  {:>6.2}  {:>6.1} ",
+            rank + 1,
+            specs[player].name,
+            points[player],
+            games[player],
+            sonneborn_berger[player],
+            buchholz[player],
+            width = width
+        );
+        for &opponent in &ranking {
+            row.push_str(&match against[player][opponent] {
+                _ if opponent == player => format!(" {:>4}", "*"),
+                Some(score) => format!(" {:>4.1}", score),
+                None => format!(" {:>4}", "."),
+            });
+        }
+        println!("{}", row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn state_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("chess-ai-{}-{}.state", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn header(players: &[&str]) -> Vec<String> {
+        vec![format!("players {}", players.join(" "))]
+    }
+
+    #[test]
+    fn state_survives_a_restart() {
+        let path = state_path("tournament-resume");
+        let header = header(&["a", "b", "c"]);
+        {
+            let mut state = State::open(&path, &header).unwrap();
+            assert!(state.games.is_empty());
+            for (id, white, black, outcome) in [
+                (0, 0, 1, Outcome::WhiteWins),
+                (1, 1, 0, Outcome::Draw),
+                (2, 2, 0, Outcome::BlackWins),
+            ] {
+                let pairing = Pairing {
+                    id,
+                    round: 1 + id / 2,
+                    white,
+                    black,
+                    opening: id / 2,
+                };
+                state.record_game(pairing, outcome).unwrap();
+            }
+            state.record_bye(1, 2).unwrap();
+        }
+
+        let state = State::open(&path, &header).unwrap();
+        assert_eq!(state.games.len(), 3);
+        let (pairing, outcome) = state.games[&2];
+        assert_eq!(
+            (pairing.round, pairing.white, pairing.black, pairing.opening),
+            (2, 2, 0, 1)
+        );
+        assert_eq!(outcome, Outcome::BlackWins);
+        assert_eq!(state.byes, BTreeMap::from([(1, 2)]));
+        assert_eq!(state.points(3, 2), vec![1.5, 0.5, 1.]);
+        assert_eq!(state.points(3, 3), vec![2.5, 0.5, 1.]);
+        drop(state);
+
+        let err = State::open(&path, &self::header(&["a", "b", "d"]))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("different tournament"), "{}", err);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn swiss_never_repeats_opponents() {
+        let path = state_path("tournament-swiss");
+        let players = 5;
+        let mut state = State::open(&path, &header(&["a", "b", "c", "d", "e"])).unwrap();
+        let mut met = HashSet::new();
+        for round in 1..=players - 1 {
+            let points = state.points(players, round);
+            let (pairings, bye) = swiss_round(&state, players, round, 2);
+            assert_eq!(pairings.len(), players / 2);
+
+            let bye = bye.unwrap();
+            let expected = (0..players)
+                .filter(|player| !state.byes.values().any(|had| had == player))
+                .min_by(|&a, &b| points[a].total_cmp(&points[b]).then(b.cmp(&a)))
+                .unwrap();
+            assert_eq!(bye, expected, "round {}", round);
+            state.record_bye(round, bye).unwrap();
+
+            for pairing in pairings {
+                assert!(pairing.white != bye && pairing.black != bye);
+                let pair = (
+                    pairing.white.min(pairing.black),
+                    pairing.white.max(pairing.black),
+                );
+                assert!(met.insert(pair), "{:?} met twice", pair);
+                let outcome = if pairing.white < pairing.black {
+                    Outcome::WhiteWins
+                } else {
+                    Outcome::BlackWins
+                };
+                state.record_game(pairing, outcome).unwrap();
+            }
+        }
+        let byes: HashSet<usize> = state.byes.values().copied().collect();
+        assert_eq!(byes.len(), players - 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn round_robin_balances_colours() {
+        let players = 4;
+        let pairings = schedule(Format::RoundRobin, players, 2, 3);
+        assert_eq!(pairings.len(), 2 * players * (players - 1));
+
+        let mut colors = vec![0; players];
+        let mut games = HashMap::new();
+        for pairing in &pairings {
+            colors[pairing.white] += 1;
+            colors[pairing.black] -= 1;
+            *games.entry((pairing.white, pairing.black)).or_insert(0) += 1;
+        }
+        assert_eq!(colors, vec![0; players]);
+        assert!(games.values().all(|&count| count == 2));
+        for pair in pairings.chunks(2) {
+            assert_eq!(
+                (pair[0].white, pair[0].black),
+                (pair[1].black, pair[1].white)
+            );
+            assert_eq!(pair[0].opening, pair[1].opening);
+        }
+
+        let gauntlet = schedule(Format::Gauntlet, players, 1, 1);
+        assert_eq!(gauntlet.len(), 2 * (players - 1));
+        assert!(gauntlet
+            .iter()
+            .all(|pairing| pairing.white == 0 || pairing.black == 0));
+    }
+}
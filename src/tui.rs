@@ -0,0 +1,282 @@
+use std::{
+    io::{self, BufRead, Write},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    board::{Board, Opponent, Status},
+    net, pgn,
+    piece::Piece,
+    render::analysis,
+    spectate::Spectators,
+};
+
+const TICK: Duration = Duration::from_millis(50);
+
+const CLEAR: &str = "\x1b[2J\x1b[H";
+const RESET: &str = "\x1b[0m";
+const LIGHT_SQUARE: &str = "\x1b[48;5;180m";
+const DARK_SQUARE: &str = "\x1b[48;5;137m";
+const LIGHT_LAST_MOVE: &str = "\x1b[48;5;186m";
+const DARK_LAST_MOVE: &str = "\x1b[48;5;143m";
+const WHITE_PIECE: &str = "\x1b[1;97m";
+const BLACK_PIECE: &str = "\x1b[1;30m";
+
+const HELP: &str = "moves: e4, Nf3, e7e8q...  u: undo  r: restart  a: analysis  f: flip  q: quit";
+const NETWORK_HELP: &str = "moves: e4, Nf3, e7e8q...  u: takeback  d: draw  g: resign  n: decline  a: analysis  f: flip  q: quit";
+
+pub fn run(
+    mut board: Board,
+    mut game: Option<net::Game>,
+    mut spectators: Option<Spectators>,
+    mut show_analysis: bool,
+) -> Result<()> {
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(|line| line.ok()) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let [white, black] = board.get_opponents();
+    let mut flipped = black == Opponent::Player && white != Opponent::Player;
+    let mut message = String::new();
+    let mut drawn = String::new();
+    let mut last_update = Instant::now();
+    loop {
+        match lines.recv_timeout(TICK) {
+            Ok(line) => {
+                let command = line.trim();
+                message.clear();
+                match (command, game.as_mut()) {
+                    ("", _) => (),
+                    ("q" | "quit" | "exit", _) => break,
+                    ("a" | "analysis", _) => show_analysis = !show_analysis,
+                    ("f" | "flip", _) => flipped = !flipped,
+                    ("u" | "undo", None) => board.undo(),
+                    ("r" | "restart", None) => board.reset(),
+                    ("u" | "undo" | "takeback", Some(game)) => game.takeback(&mut board),
+                    ("d" | "draw", Some(game)) => game.offer_draw(&mut board),
+                    ("g" | "resign", Some(game)) => game.resign(&mut board),
+                    ("n" | "decline", Some(game)) => game.decline(),
+                    (_, _) => {
+                        if let Err(err) = play(&mut board, command) {
+                            message = format!("{:#}", err);
+                        }
+                    }
+                }
+                drawn.clear();
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if let Some(game) = game.as_mut() {
+            game.update(&mut board);
+        }
+        board.update(last_update.elapsed());
+        last_update = Instant::now();
+        if let Some(spectators) = spectators.as_mut() {
+            spectators.update(&board, game.as_ref().and_then(|game| game.clocks()));
+        }
+
+        if board.flying_piece().is_some() {
+            continue;
+        }
+        let mut frame = render(&mut board, flipped);
+        if let Some(game) = &game {
+            frame.push_str(&format!("{}\n", game.title()));
+        }
+        if show_analysis {
+            let lines = board.analysis();
+            if lines.is_empty() {
+                frame.push_str("waiting for the engine...\n");
+            }
+            for line in &lines {
+                frame.push_str(&format!("{}\n", analysis::label(line)));
+            }
+        }
+        if !message.is_empty() {
+            frame.push_str(&format!("{}\n", message));
+        }
+        frame.push_str(if game.is_some() { NETWORK_HELP } else { HELP });
+        frame.push_str("\n> ");
+
+        if frame != drawn {
+            print!("{}{}", CLEAR, frame);
+            io::stdout().flush()?;
+            drawn = frame;
+        }
+    }
+
+    board.stop_thinking();
+    println!();
+    Ok(())
+}
+
+fn play(board: &mut Board, command: &str) -> Result<()> {
+    if board.get_status() != Status::Playing {
+        bail!("the game is over");
+    }
+    if board.current_opponent() != Opponent::Player {
+        bail!("it is not your turn");
+    }
+
+    let m = pgn::parse_move(board, command)?;
+    board.move_piece(m.from, m.to);
+    if !m.promotion.is_none() {
+        board.promote(m.promotion.split().0);
+    }
+    Ok(())
+}
+
+fn render(board: &mut Board, flipped: bool) -> String {
+    let [white, black] = board.get_opponents();
+    let mut frame = format!("white: {}  black: {}\n\n", white, black);
+
+    for row in 0..8 {
+        let y = if flipped { 7 - row } else { row };
+        frame.push_str(&format!(" {} ", 8 - y));
+        for column in 0..8 {
+            let x = if flipped { 7 - column } else { column };
+            let light = (x + y) % 2 == 0;
+            let background = match (board.is_in_last_move(x, y), light) {
+                (true, true) => LIGHT_LAST_MOVE,
+                (true, false) => DARK_LAST_MOVE,
+                (false, true) => LIGHT_SQUARE,
+                (false, false) => DARK_SQUARE,
+            };
+
+            let piece = board.get_piece(x, y);
+            let foreground = if piece.is_white() {
+                WHITE_PIECE
+            } else {
+                BLACK_PIECE
+            };
+            frame.push_str(&format!(
+                "{}{} {} {}",
+                background,
+                foreground,
+                glyph(piece),
+                RESET
+            ));
+        }
+        frame.push('\n');
+    }
+
+    frame.push_str("   ");
+    for column in 0..8 {
+        let x = if flipped { 7 - column } else { column };
+        frame.push_str(&format!(" {} ", (b'a' + x as u8) as char));
+    }
+    frame.push_str(&format!("\n\n{}\n", status(board)));
+    frame
+}
+
+fn status(board: &mut Board) -> String {
+    let turn = board.get_current_turn();
+    let name = |color: Piece| if color.is_white() { "white" } else { "black" };
+    match board.get_status() {
+        Status::Playing if board.is_in_check() => format!("{} to move, check", name(turn)),
+        Status::Playing => format!("{} to move", name(turn)),
+        Status::Checkmate => format!("checkmate, {} wins", name(turn.ennemy())),
+        Status::Stalemate => "stalemate, draw".to_string(),
+        Status::Dead => "insufficient material, draw".to_string(),
+        Status::Ended => "game over".to_string(),
+    }
+}
+
+fn glyph(piece: Piece) -> char {
+    match piece.split().0 {
+        Piece::Pawn => '♟',
+        Piece::LeftKnight | Piece::RightKnight => '♞',
+        Piece::Bishop => '♝',
+        Piece::Rook => '♜',
+        Piece::Queen => '♛',
+        Piece::King => '♚',
+        _ => ' ',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn players(fen: Option<&str>) -> Board {
+        let mut board = fen.map_or_else(Board::default, |fen| Board::from_fen(fen).unwrap());
+        board.set_opponents(Opponent::Player, Opponent::Player);
+        board
+    }
+
+    #[test]
+    fn plays_san_and_coordinate_moves() {
+        let mut board = players(None);
+        for command in ["e4", "e7e5", "Nf3"] {
+            play(&mut board, command).unwrap();
+        }
+        let expected =
+            Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        assert_eq!(board.hash(), expected.unwrap().hash());
+        for command in ["e4", "e7e4", "Ke6", "castle"] {
+            assert!(play(&mut board, command).is_err(), "{}", command);
+        }
+
+        let mut board = players(Some("4k3/P7/8/8/8/8/8/4K3 w - - 0 1"));
+        play(&mut board, "a7a8n").unwrap();
+        assert_eq!(board.to_fen(), "N3k3/8/8/8/8/8/8/4K3 b - - 0 1");
+
+        board.set_opponents(Opponent::Player, Opponent::Computer);
+        let err = play(&mut board, "Ke7").unwrap_err();
+        assert_eq!(err.to_string(), "it is not your turn");
+    }
+
+    #[test]
+    fn highlights_the_last_move() {
+        let mut board = players(None);
+        play(&mut board, "e4").unwrap();
+
+        for flipped in [false, true] {
+            let frame = render(&mut board, flipped);
+            let rank = |rank: usize| {
+                frame
+                    .lines()
+                    .find(|line| line.starts_with(&format!(" {} ", rank)))
+                    .unwrap()
+            };
+            assert_eq!(frame.matches(LIGHT_LAST_MOVE).count(), 2);
+            assert!(rank(4).contains(&format!("{}{} ♟ ", LIGHT_LAST_MOVE, WHITE_PIECE)));
+            assert!(rank(2).contains(LIGHT_LAST_MOVE));
+            assert!(frame.ends_with("black to move\n"));
+        }
+        assert!(render(&mut board, true)
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with(" 1 "));
+    }
+
+    #[test]
+    fn reports_the_end_of_the_game() {
+        let mut board = players(None);
+        for command in ["f3", "e5", "g4", "Qh4"] {
+            play(&mut board, command).unwrap();
+        }
+        assert!(render(&mut board, false).ends_with("checkmate, black wins\n"));
+        let err = play(&mut board, "a3").unwrap_err();
+        assert_eq!(err.to_string(), "the game is over");
+
+        let mut board = players(Some("7k/8/4Q1K1/8/8/8/8/8 w - - 0 1"));
+        play(&mut board, "Qf7").unwrap();
+        assert_eq!(status(&mut board), "stalemate, draw");
+
+        let mut board = players(Some("7k/8/6K1/8/8/8/8/6N1 b - - 0 1"));
+        board.update_status();
+        assert_eq!(status(&mut board), "insufficient material, draw");
+    }
+}
@@ -0,0 +1,365 @@
+use std::{fs, path::PathBuf, thread, time::Instant};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    board::Board,
+    engine::{
+        eval::{self, phase_weight, Weights, PARAMETERS, TOTAL_PHASE},
+        ordering::Heuristics,
+        picker::MovePicker,
+    },
+};
+
+const DEFAULT_EPOCHS: usize = 1000;
+const DEFAULT_LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+const QUIESCENCE_DEPTH: usize = 16;
+
+struct Position {
+    features: Vec<(u16, i8)>,
+    phase: f64,
+    result: f64,
+}
+
+impl Position {
+    fn evaluate(&self, params: &[f64]) -> f64 {
+        let mut midgame = 0.;
+        let mut endgame = 0.;
+        for &(index, count) in &self.features {
+            let value = params[index as usize] * count as f64;
+            if (index as usize) < PARAMETERS / 2 {
+                midgame += value;
+            } else {
+                endgame += value;
+            }
+        }
+        midgame * self.phase + endgame * (1. - self.phase)
+    }
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut corpus = None;
+    let mut output = eval::weights_file().unwrap_or_else(|| PathBuf::from("weights.txt"));
+    let mut epochs = DEFAULT_EPOCHS;
+    let mut learning_rate = DEFAULT_LEARNING_RATE;
+    let mut threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => match args.next() {
+                Some(path) => output = PathBuf::from(path),
+                None => bail!("--output expects a file"),
+            },
+            "--epochs" => match args.next().map(|epochs| epochs.parse()) {
+                Some(Ok(value)) => epochs = value,
+                _ => bail!("--epochs expects a number"),
+            },
+            "--learning-rate" => match args.next().map(|rate| rate.parse()) {
+                Some(Ok(value)) => learning_rate = value,
+                _ => bail!("--learning-rate expects a number"),
+            },
+            "--threads" => match args.next().map(|threads| threads.parse()) {
+                Some(Ok(value)) if value > 0 => threads = value,
+                _ => bail!("--threads expects a positive number"),
+            },
+            arg if arg.starts_with("--") => bail!("unexpected argument: {}", arg),
+            path if corpus.is_none() => corpus = Some(path.to_string()),
+            arg => bail!("unexpected argument: {}", arg),
+        }
+    }
+
+    let corpus = match corpus {
+        Some(corpus) => corpus,
+        None => bail!(
+            "usage: tune <corpus> [--output file] [--epochs N] [--learning-rate R] [--threads N]"
+        ),
+    };
+
+    let start = Instant::now();
+    let positions = load_corpus(&corpus)?;
+    if positions.is_empty() {
+        bail!("no usable positions in {}", corpus);
+    }
+    println!(
+        "loaded {} positions in {:.1}s",
+        positions.len(),
+        start.elapsed().as_secs_f64()
+    );
+
+    let mut params: Vec<f64> = eval::weights()
+        .params()
+        .into_iter()
+        .map(f64::from)
+        .collect();
+    let k = fit_scaling(&positions, &params, threads);
+    println!(
+        "K = {:.4}, error = {:.6}",
+        k,
+        error(&positions, &params, k, threads)
+    );
+
+    let mut momentum = vec![0.; PARAMETERS];
+    let mut velocity = vec![0.; PARAMETERS];
+    for epoch in 1..=epochs {
+        let gradient = gradient(&positions, &params, k, threads);
+        for i in 0..PARAMETERS {
+            momentum[i] = BETA1 * momentum[i] + (1. - BETA1) * gradient[i];
+            velocity[i] = BETA2 * velocity[i] + (1. - BETA2) * gradient[i] * gradient[i];
+            let momentum = momentum[i] / (1. - BETA1.powi(epoch as i32));
+            let velocity = velocity[i] / (1. - BETA2.powi(epoch as i32));
+            params[i] -= learning_rate * momentum / (velocity.sqrt() + EPSILON);
+        }
+
+        if epoch % 50 == 0 || epoch == epochs {
+            println!(
+                "epoch {:5}  error {:.6}  {:.1}s",
+                epoch,
+                error(&positions, &params, k, threads),
+                start.elapsed().as_secs_f64()
+            );
+        }
+    }
+
+    let params: Vec<i32> = params.iter().map(|param| param.round() as i32).collect();
+    Weights::from_params(&params).save(&output)?;
+    println!("weights written to {}", output.display());
+
+    Ok(())
+}
+
+fn load_corpus(path: &str) -> Result<Vec<Position>> {
+    let text = fs::read_to_string(path).with_context(|| format!("could not read {}", path))?;
+    let heuristics = Heuristics::default();
+    let mut positions = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (fen, result) = parse_line(line)
+            .with_context(|| format!("{}:{}: no result found", path, line_number + 1))?;
+        let mut board = Board::from_fen(&fen)
+            .with_context(|| format!("{}:{}: invalid position", path, line_number + 1))?;
+        if board.is_in_check() {
+            continue;
+        }
+
+        let leaf = quiet_leaf(&mut board, &heuristics, -i32::MAX, i32::MAX, 0).1;
+        positions.push(features(&leaf, result));
+    }
+
+    Ok(positions)
+}
+
+fn parse_line(line: &str) -> Option<(String, f64)> {
+    let fields: Vec<&str> = line
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .filter(|field| !field.is_empty())
+        .collect();
+    if fields.len() < 5 {
+        return None;
+    }
+
+    let fen = fields[..4].join(" ");
+    let result = fields[4..]
+        .iter()
+        .rev()
+        .find_map(|field| match field.trim_matches('"') {
+            "1-0" | "[1.0]" => Some(1.),
+            "0-1" | "[0.0]" => Some(0.),
+            "1/2-1/2" | "[0.5]" => Some(0.5),
+            _ => None,
+        })?;
+
+    Some((fen, result))
+}
+
+fn quiet_leaf(
+    board: &mut Board,
+    heuristics: &Heuristics,
+    mut alpha: i32,
+    beta: i32,
+    ply: usize,
+) -> (i32, Board) {
+    let stand_pat = eval::evaluate(board);
+    let mut best = (stand_pat, board.clone());
+    if stand_pat >= beta || ply >= QUIESCENCE_DEPTH {
+        return best;
+    }
+    alpha = alpha.max(stand_pat);
+
+    let color = board.get_current_turn();
+    let mut picker = MovePicker::captures(None);
+    while let Some(m) = picker.next(board, heuristics) {
+        let mut child = board.clone();
+        child.make_move(m);
+        if child.is_check(color) {
+            continue;
+        }
+
+        let (score, leaf) = quiet_leaf(&mut child, heuristics, -beta, -alpha, ply + 1);
+        let score = -score;
+        if score > alpha {
+            alpha = score;
+            best = (score, leaf);
+            if score >= beta {
+                break;
+            }
+        }
+    }
+
+    best
+}
+
+fn features(board: &Board, result: f64) -> Position {
+    let mut counts = vec![0i8; PARAMETERS];
+    let mut phase = 0;
+
+    for square_index in 0..64 {
+        let piece = board.get_square(square_index);
+        if piece.is_none() {
+            continue;
+        }
+
+        let index = piece.index();
+        let (sign, table_index) = if piece.is_white() {
+            (1, square_index)
+        } else {
+            (-1, square_index ^ 56)
+        };
+
+        for game_phase in 0..2 {
+            counts[Weights::value_index(game_phase, index)] += sign;
+            counts[Weights::table_index(game_phase, index, table_index)] += sign;
+        }
+        phase += phase_weight(index);
+    }
+
+    let features = counts
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count != 0)
+        .map(|(index, &count)| (index as u16, count))
+        .collect();
+
+    Position {
+        features,
+        phase: phase.min(TOTAL_PHASE) as f64 / TOTAL_PHASE as f64,
+        result,
+    }
+}
+
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1. / (1. + (-k * score / 400.).exp())
+}
+
+fn error(positions: &[Position], params: &[f64], k: f64, threads: usize) -> f64 {
+    let chunk_size = positions.len().div_ceil(threads);
+    let total: f64 = thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|position| {
+                            (position.result - sigmoid(position.evaluate(params), k)).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum()
+    });
+
+    total / positions.len() as f64
+}
+
+fn gradient(positions: &[Position], params: &[f64], k: f64, threads: usize) -> Vec<f64> {
+    let chunk_size = positions.len().div_ceil(threads);
+    let mut gradient = vec![0.; PARAMETERS];
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut gradient = vec![0.; PARAMETERS];
+                    for position in chunk {
+                        let prediction = sigmoid(position.evaluate(params), k);
+                        let slope =
+                            (prediction - position.result) * prediction * (1. - prediction) * k
+                                / 400.;
+                        for &(index, count) in &position.features {
+                            let weight = if (index as usize) < PARAMETERS / 2 {
+                                position.phase
+                            } else {
+                                1. - position.phase
+                            };
+                            gradient[index as usize] += slope * count as f64 * weight;
+                        }
+                    }
+                    gradient
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            for (total, value) in gradient.iter_mut().zip(handle.join().unwrap()) {
+                *total += value;
+            }
+        }
+    });
+
+    let scale = 2. / positions.len() as f64;
+    gradient.iter_mut().for_each(|value| *value *= scale);
+    gradient
+}
+
+fn fit_scaling(positions: &[Position], params: &[f64], threads: usize) -> f64 {
+    let mut low = 0.1;
+    let mut high = 4.;
+    for _ in 0..40 {
+        let a = low + (high - low) / 3.;
+        let b = high - (high - low) / 3.;
+        if error(positions, params, a, threads) < error(positions, params, b, threads) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    (low + high) / 2.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line_needs_an_explicit_result() {
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K -";
+        assert_eq!(
+            parse_line(&format!("{} [1.0]", fen)),
+            Some((fen.to_string(), 1.))
+        );
+        assert_eq!(
+            parse_line(&format!("{} c9 \"1/2-1/2\";", fen)),
+            Some((fen.to_string(), 0.5))
+        );
+        assert_eq!(
+            parse_line(&format!("{} 0 1 0-1", fen)),
+            Some((fen.to_string(), 0.))
+        );
+        assert_eq!(parse_line(&format!("{} 0 1", fen)), None);
+        assert_eq!(parse_line(&format!("{} 1.0", fen)), None);
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    io::{self, BufRead},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    board::{Board, Move},
+    engine::{
+        computer::Computer,
+        search::{self, Limits, Line, Search, DEFAULT_HASH_MB},
+        strength::{Strength, MAX_LEVEL, MIN_LEVEL},
+    },
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+const MAX_HASH_MB: usize = 4096;
+const MAX_THREADS: usize = 256;
+const MAX_MULTI_PV: usize = 64;
+
+struct Uci {
+    board: Board,
+    computer: Computer,
+    searching: bool,
+    infinite: bool,
+    held: Option<Option<Move>>,
+    ponderhit_limits: Option<Limits>,
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    if let Some(arg) = args.first() {
+        bail!("unexpected argument: {}", arg);
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(|line| line.ok()) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut uci = Uci::new();
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => match uci.handle(&line) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(err) => println!("info string {:#}", err),
+            },
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => {
+                if !uci.searching {
+                    break;
+                }
+                uci.stop();
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+
+        uci.poll();
+    }
+
+    uci.computer.cancel();
+
+    Ok(())
+}
+
+impl Uci {
+    fn new() -> Self {
+        let mut computer = Computer::new(Search::default());
+        computer.set_reporter(Arc::new(|lines: &[Line]| {
+            for line in lines {
+                println!("info {}", line);
+            }
+        }));
+
+        Self {
+            board: Board::default(),
+            computer,
+            searching: false,
+            infinite: false,
+            held: None,
+            ponderhit_limits: None,
+        }
+    }
+
+    fn handle(&mut self, line: &str) -> Result<bool> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let args = tokens.get(1..).unwrap_or_default();
+
+        match tokens.first().copied() {
+            Some("uci") => {
+                println!("id name chess-ai {}", env!("CARGO_PKG_VERSION"));
+                println!("id author {}", env!("CARGO_PKG_AUTHORS"));
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_HASH_MB, MAX_HASH_MB
+                );
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTI_PV
+                );
+                println!(
+                    "option name Skill Level type spin default {} min {} max {}",
+                    MAX_LEVEL, MIN_LEVEL, MAX_LEVEL
+                );
+                println!("option name Ponder type check default false");
+                println!("uciok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                self.computer.clear();
+                self.board = Board::default();
+            }
+            Some("position") => self.position(args)?,
+            Some("go") => self.go(args)?,
+            Some("stop") => self.stop(),
+            Some("ponderhit") => self.ponderhit(),
+            Some("setoption") => self.set_option(args)?,
+            Some("quit") => return Ok(false),
+            Some(command) => bail!("unknown command: {}", command),
+            None => (),
+        }
+
+        Ok(true)
+    }
+
+    fn position(&mut self, args: &[&str]) -> Result<()> {
+        let (setup, moves) = match args.iter().position(|&arg| arg == "moves") {
+            Some(i) => (&args[..i], &args[i + 1..]),
+            None => (args, &[][..]),
+        };
+
+        let mut board = match setup {
+            ["startpos"] => Board::default(),
+            ["fen", fen @ ..] => Board::from_fen(&fen.join(" "))?,
+            _ => bail!("position expects startpos or fen"),
+        };
+        for name in moves {
+            let m: Move = name.parse()?;
+            if !board.get_all_moves().contains(&m) {
+                bail!("illegal move: {}", name);
+            }
+            board.make_move(m);
+        }
+
+        self.board = board;
+        Ok(())
+    }
+
+    fn go(&mut self, args: &[&str]) -> Result<()> {
+        let mut limits = Limits::default();
+        let mut times = [None; 2];
+        let mut increments = [Duration::ZERO; 2];
+        let mut moves_to_go = None;
+        let mut infinite = false;
+        let mut ponder = false;
+
+        let mut args = args.iter();
+        while let Some(&arg) = args.next() {
+            let mut value = || -> Result<u64> {
+                args.next()
+                    .with_context(|| format!("go {} expects a value", arg))?
+                    .parse()
+                    .with_context(|| format!("invalid value for go {}", arg))
+            };
+            match arg {
+                "depth" => limits.depth = Some(value()? as i32),
+                "nodes" => limits.nodes = Some(value()?),
+                "movetime" => limits.movetime = Some(Duration::from_millis(value()?)),
+                "wtime" => times[0] = Some(Duration::from_millis(value()?)),
+                "btime" => times[1] = Some(Duration::from_millis(value()?)),
+                "winc" => increments[0] = Duration::from_millis(value()?),
+                "binc" => increments[1] = Duration::from_millis(value()?),
+                "movestogo" => moves_to_go = Some(value()? as u32),
+                "infinite" => infinite = true,
+                "ponder" => ponder = true,
+                _ => (),
+            }
+        }
+
+        let side = usize::from(!self.board.get_current_turn().is_white());
+        if let (Some(time), None) = (times[side], limits.movetime) {
+            limits.movetime = Some(search::allocate_time(
+                time,
+                times[1 - side],
+                increments[side],
+                moves_to_go,
+            ));
+        }
+
+        self.searching = true;
+        self.infinite = infinite;
+        self.held = None;
+        if ponder {
+            self.ponderhit_limits = Some(limits);
+            self.computer.ponder_on(self.board.clone());
+        } else {
+            self.ponderhit_limits = None;
+            self.computer.think(&self.board, limits);
+        }
+
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if !self.searching {
+            return;
+        }
+
+        self.infinite = false;
+        self.ponderhit_limits = None;
+        match self.held.take() {
+            Some(best_move) => self.finish(best_move),
+            None => self.computer.stop(),
+        }
+    }
+
+    fn ponderhit(&mut self) {
+        if let Some(limits) = self.ponderhit_limits.take() {
+            self.computer.think(&self.board, limits);
+        }
+    }
+
+    fn set_option(&mut self, args: &[&str]) -> Result<()> {
+        let value_at = args.iter().position(|&arg| arg == "value");
+        let name = match args.split_first() {
+            Some((&"name", rest)) => rest[..value_at.map_or(rest.len(), |i| i - 1)].join(" "),
+            _ => bail!("setoption expects a name"),
+        };
+        let value = value_at
+            .map(|i| args[i + 1..].join(" "))
+            .unwrap_or_default();
+        let number = || -> Result<usize> {
+            value
+                .parse()
+                .with_context(|| format!("invalid value for {}: {}", name, value))
+        };
+
+        match name.to_ascii_lowercase().as_str() {
+            "hash" => self.computer.set_hash(number()?.min(MAX_HASH_MB)),
+            "threads" => self.computer.set_threads(number()?.min(MAX_THREADS)),
+            "multipv" => self.computer.set_multi_pv(number()?.min(MAX_MULTI_PV)),
+            "skill level" => self.computer.set_strength(Strength::new(number()? as u8)?),
+            "ponder" => (),
+            _ => bail!("unknown option: {}", name),
+        }
+
+        Ok(())
+    }
+
+    fn poll(&mut self) {
+        if !self.searching {
+            return;
+        }
+
+        if let Some(best_move) = self.computer.poll() {
+            if self.infinite {
+                self.held = Some(best_move);
+            } else {
+                self.finish(best_move);
+            }
+        }
+    }
+
+    fn finish(&mut self, best_move: Option<Move>) {
+        self.searching = false;
+
+        match (best_move, self.computer.ponder_move()) {
+            (Some(best_move), Some(ponder_move)) => {
+                println!("bestmove {} ponder {}", best_move, ponder_move)
+            }
+            (Some(best_move), None) => println!("bestmove {}", best_move),
+            (None, _) => println!("bestmove 0000"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn uci(commands: &[&str]) -> Uci {
+        let mut uci = Uci::new();
+        for command in commands {
+            assert!(uci.handle(command).unwrap(), "{}", command);
+        }
+        uci
+    }
+
+    fn wait(uci: &mut Uci, done: impl Fn(&Uci) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while !done(uci) {
+            assert!(Instant::now() < deadline, "the search never finished");
+            uci.poll();
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    #[test]
+    fn sets_up_positions() {
+        let mut uci = uci(&["position startpos moves e2e4 e7e5 g1f3"]);
+        let expected =
+            Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        assert_eq!(uci.board.hash(), expected.unwrap().hash());
+
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        uci.handle(&format!("position fen {} moves e2e4", fen))
+            .unwrap();
+        assert_eq!(uci.board.to_fen(), "4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1");
+
+        for command in [
+            "position startpos moves e2e5",
+            "position fen 8/8/8 w - - 0 1",
+            "position",
+        ] {
+            assert!(uci.handle(command).is_err(), "{}", command);
+        }
+        assert_eq!(uci.board.to_fen(), "4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1");
+    }
+
+    #[test]
+    fn searches_until_the_limit_or_stop() {
+        let mut uci = uci(&["position startpos", "go depth 2"]);
+        assert!(uci.searching);
+        wait(&mut uci, |uci| !uci.searching);
+
+        // an infinite search holds its move until told to stop
+        uci.handle("go infinite depth 1").unwrap();
+        wait(&mut uci, |uci| uci.held.is_some());
+        let best_move = uci.held.unwrap().unwrap();
+        assert!(uci.board.get_all_moves().contains(&best_move));
+        assert!(uci.searching);
+        uci.handle("stop").unwrap();
+        assert!(!uci.searching);
+
+        uci.handle("go infinite").unwrap();
+        uci.handle("stop").unwrap();
+        wait(&mut uci, |uci| !uci.searching);
+    }
+
+    #[test]
+    fn sets_options() {
+        let mut uci = uci(&[
+            "setoption name Hash value 8",
+            "setoption name Threads value 2",
+            "setoption name MultiPV value 3",
+            "setoption name Skill Level value 20",
+            "setoption name Ponder value true",
+            "position startpos",
+            "go depth 2",
+        ]);
+        wait(&mut uci, |uci| !uci.searching);
+        assert_eq!(uci.computer.lines().len(), 3);
+
+        for command in [
+            "setoption name Hash value lots",
+            "setoption name Skill Level value 99",
+            "setoption name Contempt value 10",
+            "setoption value 3",
+        ] {
+            assert!(uci.handle(command).is_err(), "{}", command);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use piston_window::OpenGL;
 use piston_window::{PistonWindow, WindowSettings};
 
@@ -7,6 +7,11 @@ pub const SIZE: [f64; 2] = [800.; 2];
 pub fn window() -> Result<PistonWindow> {
     let opengl = OpenGL::V3_2;
 
+    // winit panics instead of returning an error when there is no display
+    if !has_display() {
+        bail!("could not open a window, no display available");
+    }
+
     let window = WindowSettings::new("chess-ai", SIZE)
         .graphics_api(opengl)
         .exit_on_esc(true)
@@ -17,3 +22,15 @@ pub fn window() -> Result<PistonWindow> {
 
     Ok(window)
 }
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn has_display() -> bool {
+    ["DISPLAY", "WAYLAND_DISPLAY"]
+        .iter()
+        .any(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty()))
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn has_display() -> bool {
+    true
+}
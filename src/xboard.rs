@@ -0,0 +1,433 @@
+use std::{
+    io::{self, BufRead},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    board::{Board, Move, Status},
+    engine::{
+        computer::Computer,
+        search::{self, Limits, Line, Search},
+    },
+    pgn,
+    piece::Piece,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+const MATE_SCORE: i32 = 100000;
+
+struct Xboard {
+    board: Board,
+    history: Vec<Board>,
+    computer: Computer,
+    engine_color: Option<Piece>,
+    thinking: bool,
+    ponder: bool,
+    clock: Option<Duration>,
+    opponent_clock: Option<Duration>,
+    increment: Duration,
+    moves_per_control: u32,
+    move_time: Option<Duration>,
+    depth: Option<i32>,
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    if let Some(arg) = args.first() {
+        bail!("unexpected argument: {}", arg);
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(|line| line.ok()) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let post = Arc::new(AtomicBool::new(false));
+    let mut xboard = Xboard::new(post.clone());
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => match xboard.handle(&line, &post) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(err) => println!("{:#}", err),
+            },
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) if xboard.thinking => thread::sleep(POLL_INTERVAL),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        xboard.poll();
+    }
+
+    xboard.computer.cancel();
+
+    Ok(())
+}
+
+impl Xboard {
+    fn new(post: Arc<AtomicBool>) -> Self {
+        let mut computer = Computer::new(Search::default());
+        computer.set_reporter(Arc::new(move |lines: &[Line]| {
+            if post.load(Ordering::Relaxed) {
+                for line in lines {
+                    print_thinking(line);
+                }
+            }
+        }));
+        computer.set_ponder(false);
+
+        Self {
+            board: Board::default(),
+            history: Vec::new(),
+            computer,
+            engine_color: Some(Piece::Black),
+            thinking: false,
+            ponder: false,
+            clock: None,
+            opponent_clock: None,
+            increment: Duration::ZERO,
+            moves_per_control: 0,
+            move_time: None,
+            depth: None,
+        }
+    }
+
+    fn handle(&mut self, line: &str, post: &AtomicBool) -> Result<bool> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let args = tokens.get(1..).unwrap_or_default();
+
+        match tokens.first().copied() {
+            Some("protover") => {
+                println!(
+                    "feature myname=\"chess-ai {}\" usermove=1 setboard=1 ping=1 playother=1 \
+                     colors=0 analyze=0 sigint=0 sigterm=0 done=1",
+                    env!("CARGO_PKG_VERSION")
+                );
+            }
+            Some("new") => {
+                self.computer.clear();
+                self.board = Board::default();
+                self.history.clear();
+                self.engine_color = Some(Piece::Black);
+                self.thinking = false;
+                self.move_time = None;
+                self.depth = None;
+            }
+            Some("setboard") => {
+                self.stop_thinking();
+                self.board = Board::from_fen(&args.join(" "))
+                    .map_err(|_| anyhow!("tellusererror Illegal position"))?;
+                self.history.clear();
+            }
+            Some("usermove") => {
+                let name = args.first().context("Error (missing move): usermove")?;
+                self.user_move(name)?;
+            }
+            Some("go") => {
+                self.stop_thinking();
+                self.engine_color = Some(self.board.get_current_turn());
+            }
+            Some("playother") => {
+                self.stop_thinking();
+                self.engine_color = Some(self.board.get_current_turn().ennemy());
+            }
+            Some("force") | Some("result") => {
+                self.stop_thinking();
+                self.engine_color = None;
+            }
+            Some("undo") => self.take_back(1)?,
+            Some("remove") => self.take_back(2)?,
+            Some("?") => self.computer.stop(),
+            Some("time") => self.clock = Some(centiseconds(args)?),
+            Some("otim") => self.opponent_clock = Some(centiseconds(args)?),
+            Some("level") => self.level(args)?,
+            Some("st") => {
+                let seconds: u64 = parse(args.first().copied(), "st")?;
+                self.move_time = Some(Duration::from_secs(seconds));
+            }
+            Some("sd") => self.depth = Some(parse(args.first().copied(), "sd")?),
+            Some("ping") => println!("pong {}", args.join(" ")),
+            Some("post") => post.store(true, Ordering::Relaxed),
+            Some("nopost") => post.store(false, Ordering::Relaxed),
+            Some("hard") => self.set_ponder(true),
+            Some("easy") => self.set_ponder(false),
+            Some("quit") => return Ok(false),
+            Some(
+                "xboard" | "accepted" | "rejected" | "random" | "computer" | "name" | "rating"
+                | "ics" | "white" | "black" | "draw",
+            )
+            | None => (),
+            Some(command) => bail!("Error (unknown command): {}", command),
+        }
+
+        self.start_thinking();
+        Ok(true)
+    }
+
+    fn user_move(&mut self, name: &str) -> Result<()> {
+        let m = name
+            .parse::<Move>()
+            .ok()
+            .filter(|m| self.board.get_all_moves().contains(m))
+            .or_else(|| pgn::parse_san(&mut self.board, name).ok())
+            .ok_or_else(|| anyhow!("Illegal move: {}", name))?;
+
+        self.play(m);
+        if !self.is_engine_turn() {
+            self.computer.cancel();
+        }
+        Ok(())
+    }
+
+    fn play(&mut self, m: Move) {
+        self.history.push(self.board.clone());
+        self.board.make_move(m);
+        self.board.update_status();
+
+        if let Some(result) = self.result() {
+            println!("{}", result);
+        }
+    }
+
+    fn result(&self) -> Option<String> {
+        let (winner, reason) = match self.board.get_status() {
+            Status::Playing | Status::Ended => return None,
+            Status::Checkmate if self.board.get_current_turn().is_white() => ("0-1", "Black mates"),
+            Status::Checkmate => ("1-0", "White mates"),
+            Status::Stalemate => ("1/2-1/2", "Stalemate"),
+            Status::Dead => ("1/2-1/2", "Insufficient material"),
+        };
+        Some(format!("{} {{{}}}", winner, reason))
+    }
+
+    fn take_back(&mut self, plies: usize) -> Result<()> {
+        if self.history.len() < plies {
+            bail!("Error (no move to undo): {}", plies);
+        }
+
+        self.stop_thinking();
+        self.history.truncate(self.history.len() - plies + 1);
+        self.board = self.history.pop().unwrap();
+        Ok(())
+    }
+
+    fn level(&mut self, args: &[&str]) -> Result<()> {
+        let (moves, base, increment) = match *args {
+            [moves, base, increment] => (moves, base, increment),
+            _ => bail!("Error (level expects three values): {}", args.join(" ")),
+        };
+
+        let (minutes, seconds) = base.split_once(':').unwrap_or((base, "0"));
+        let minutes: u64 = parse(Some(minutes), "level")?;
+        let seconds: u64 = parse(Some(seconds), "level")?;
+        let increment: f64 = parse(Some(increment), "level")?;
+        let increment = Duration::try_from_secs_f64(increment)
+            .map_err(|_| anyhow!("Error (invalid value): level"))?;
+
+        self.moves_per_control = parse(Some(moves), "level")?;
+        self.clock = Some(Duration::from_secs(minutes * 60 + seconds));
+        self.opponent_clock = self.clock;
+        self.increment = increment;
+        self.move_time = None;
+        Ok(())
+    }
+
+    fn set_ponder(&mut self, ponder: bool) {
+        self.ponder = ponder;
+        self.computer.set_ponder(ponder);
+        if !ponder && !self.thinking {
+            self.computer.cancel();
+        }
+    }
+
+    fn is_engine_turn(&self) -> bool {
+        self.engine_color == Some(self.board.get_current_turn())
+            && self.board.get_status() == Status::Playing
+    }
+
+    fn start_thinking(&mut self) {
+        if self.thinking || !self.is_engine_turn() {
+            return;
+        }
+
+        let moves_to_go = (self.moves_per_control > 0).then(|| {
+            let played = (self.board.get_ply() / 2) as u32;
+            self.moves_per_control - played % self.moves_per_control
+        });
+        let limits = Limits {
+            depth: self.depth,
+            nodes: None,
+            movetime: self
+                .move_time
+                .or_else(|| {
+                    self.clock.map(|clock| {
+                        search::allocate_time(
+                            clock,
+                            self.opponent_clock,
+                            self.increment,
+                            moves_to_go,
+                        )
+                    })
+                })
+                .or_else(|| self.depth.is_none().then_some(DEFAULT_MOVE_TIME)),
+        };
+
+        self.thinking = true;
+        self.computer.think(&self.board, limits);
+    }
+
+    fn stop_thinking(&mut self) {
+        self.thinking = false;
+        self.computer.cancel();
+    }
+
+    fn poll(&mut self) {
+        if !self.thinking {
+            return;
+        }
+
+        if let Some(best_move) = self.computer.poll() {
+            self.thinking = false;
+            if let Some(best_move) = best_move.filter(|_| self.is_engine_turn()) {
+                println!("move {}", best_move);
+                self.play(best_move);
+                if self.ponder && self.board.get_status() == Status::Playing {
+                    self.computer.ponder(&self.board);
+                }
+            }
+        }
+    }
+}
+
+fn print_thinking(line: &Line) {
+    let score = match line.mate_in() {
+        Some(moves) if moves > 0 => MATE_SCORE + moves,
+        Some(moves) => -MATE_SCORE + moves,
+        None => line.score,
+    };
+    let pv: Vec<String> = line.pv.iter().map(|m| m.to_string()).collect();
+
+    println!(
+        "{} {} {} {} {}",
+        line.depth,
+        score,
+        line.time.as_millis() / 10,
+        line.nodes,
+        pv.join(" ")
+    );
+}
+
+fn centiseconds(args: &[&str]) -> Result<Duration> {
+    let centiseconds: u64 = parse(args.first().copied(), "time")?;
+    Ok(Duration::from_millis(centiseconds * 10))
+}
+
+fn parse<T: FromStr>(value: Option<&str>, command: &str) -> Result<T> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| anyhow!("Error (invalid value): {}", command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(commands: &[&str]) -> Xboard {
+        let post = Arc::new(AtomicBool::new(false));
+        let mut xboard = Xboard::new(post.clone());
+        for command in ["force"].iter().chain(commands) {
+            xboard.handle(command, &post).unwrap();
+        }
+        xboard
+    }
+
+    fn handle(xboard: &mut Xboard, command: &str) -> Result<bool> {
+        xboard.handle(command, &AtomicBool::new(false))
+    }
+
+    #[test]
+    fn plays_user_moves() {
+        let mut xboard = setup(&["usermove e2e4", "usermove Nf6"]);
+        let expected =
+            Board::from_fen("rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2");
+        assert_eq!(xboard.board.hash(), expected.unwrap().hash());
+        assert!(handle(&mut xboard, "usermove e4e6").is_err());
+        assert!(handle(&mut xboard, "usermove").is_err());
+        assert!(!xboard.thinking);
+    }
+
+    #[test]
+    fn takes_moves_back() {
+        let mut xboard = setup(&["usermove e2e4", "usermove e7e5", "usermove g1f3"]);
+        handle(&mut xboard, "undo").unwrap();
+        assert_eq!(xboard.board.get_ply(), 2);
+        handle(&mut xboard, "remove").unwrap();
+        assert_eq!(xboard.board.hash(), Board::default().hash());
+        assert!(handle(&mut xboard, "undo").is_err());
+    }
+
+    #[test]
+    fn sets_up_positions() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1";
+        let mut xboard = setup(&[&format!("setboard {}", fen)]);
+        assert_eq!(xboard.board.to_fen(), fen);
+        assert!(handle(&mut xboard, "setboard 8/8/8 w").is_err());
+        assert_eq!(xboard.board.to_fen(), fen);
+    }
+
+    #[test]
+    fn reads_time_controls() {
+        let mut xboard = setup(&["level 40 5 3"]);
+        assert_eq!(xboard.moves_per_control, 40);
+        assert_eq!(xboard.clock, Some(Duration::from_secs(300)));
+        assert_eq!(xboard.opponent_clock, Some(Duration::from_secs(300)));
+        assert_eq!(xboard.increment, Duration::from_secs(3));
+
+        handle(&mut xboard, "level 0 2:30 0.5").unwrap();
+        assert_eq!(xboard.clock, Some(Duration::from_secs(150)));
+        assert_eq!(xboard.increment, Duration::from_millis(500));
+        for level in [
+            "level 40 5 -1",
+            "level 40 5 nan",
+            "level 40 5 inf",
+            "level 40 5",
+        ] {
+            assert!(handle(&mut xboard, level).is_err(), "{}", level);
+        }
+        assert_eq!(xboard.increment, Duration::from_millis(500));
+
+        handle(&mut xboard, "st 2").unwrap();
+        assert_eq!(xboard.move_time, Some(Duration::from_secs(2)));
+        handle(&mut xboard, "sd 6").unwrap();
+        assert_eq!(xboard.depth, Some(6));
+        assert!(handle(&mut xboard, "sd deep").is_err());
+    }
+
+    #[test]
+    fn reports_results() {
+        let mut xboard = setup(&["setboard 6k1/8/6K1/8/8/8/8/R7 w - - 0 1"]);
+        assert_eq!(xboard.result(), None);
+        handle(&mut xboard, "usermove a1a8").unwrap();
+        assert_eq!(xboard.result().as_deref(), Some("1-0 {White mates}"));
+
+        let mut xboard = setup(&["setboard 7k/8/4Q1K1/8/8/8/8/8 w - - 0 1", "usermove e6f7"]);
+        assert_eq!(xboard.result().as_deref(), Some("1/2-1/2 {Stalemate}"));
+
+        handle(&mut xboard, "new").unwrap();
+        handle(&mut xboard, "result 1/2-1/2 {Stalemate}").unwrap();
+        assert_eq!(xboard.engine_color, None);
+    }
+}